@@ -0,0 +1,63 @@
+//! Per-run setup time of a drrun-style command line.
+//!
+//! Compares building the Process from scratch for every candidate, the
+//! way solvers did before ProcessTemplate (reading the target's ELF
+//! header and stat'ing the client each time), with instantiating a
+//! template built once. Nothing is spawned, so this is only the setup.
+//! Run with `cargo bench --bench instantiate`
+use b7::binary::Binary;
+use b7::generators::Input;
+use b7::process::{Process, ProcessTemplate};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 100_000;
+const TARGET: &str = "/bin/true";
+const DRRUN: &str = "/opt/dynamorio/bin64/drrun";
+const CLIENT: &str = "/opt/dynamorio/samples/bin64/libinscount.so";
+// drrun options as a user might pass them through "wrap" or drclient
+const OPTIONS: &[&str] = &[
+    "-root",
+    "/opt/dynamorio",
+    "-disable_traces",
+    "-no_follow_children",
+    "-stderr_mask",
+    "0xc",
+    "-loglevel",
+    "0",
+];
+
+fn per_run<F: FnMut(&Input) -> Process>(inp: &Input, mut build: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        drop(build(inp));
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let inp = Input::new(vec![b"candidate".to_vec()], b"AAAAAAAA".to_vec());
+
+    let rebuilt = per_run(&inp, |inp| {
+        let _ = Binary::new(TARGET).bits();
+        let _ = Path::new(CLIENT).exists();
+        let mut process = Process::new(DRRUN);
+        process.args(OPTIONS);
+        process.args(&["-c", CLIENT, "--", TARGET]);
+        for arg in &inp.argv {
+            process.arg(OsStr::from_bytes(arg));
+        }
+        process.input(inp.stdin.clone());
+        process
+    });
+
+    let mut template = ProcessTemplate::new(DRRUN);
+    template.args(OPTIONS);
+    template.args(&["-c", CLIENT, "--", TARGET]);
+    let instantiated = per_run(&inp, |inp| template.instantiate(inp));
+
+    println!("built every run:      {:?}", rebuilt);
+    println!("from a template:      {:?}", instantiated);
+}
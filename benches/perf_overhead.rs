@@ -0,0 +1,64 @@
+//! Time spent per perf run on top of running the target.
+//!
+//! Runs /bin/true without instrumentation, through a PerfSolver that
+//! was never set up (so it rebuilds its template and attr every run),
+//! and through one that was. Both reuse their worker thread's counter.
+//! Fails if the overhead after setup isn't down to microseconds.
+//! Run with `cargo bench --bench perf_overhead`
+use b7::brute::{run_target, InstCountData, InstCounter};
+use b7::perf::{self, PerfSolver};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 2000;
+// what perf may add to a run once set up
+const TARGET: Duration = Duration::from_millis(1);
+
+fn per_run<F: FnMut()>(mut run: F) -> Duration {
+    // warm up the page cache and the waiter thread
+    for _ in 0..RUNS / 10 {
+        run();
+    }
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        run();
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    // see tests/run_wyvern.rs, main runs before any thread is started
+    b7::process::block_signal();
+
+    let probe = perf::probe();
+    if probe.hardware.is_some() {
+        println!("skipped: {}", probe.advice());
+        return;
+    }
+
+    let data = InstCountData::builder("/bin/true").build();
+    let plain = per_run(|| {
+        run_target(&data).unwrap();
+    });
+
+    let unprepared = PerfSolver::new();
+    let rebuilt = per_run(|| {
+        unprepared.get_inst_count(&data).unwrap();
+    });
+
+    let mut prepared = PerfSolver::new();
+    prepared.setup("/bin/true", &HashMap::new()).unwrap();
+    let cached = per_run(|| {
+        prepared.get_inst_count(&data).unwrap();
+    });
+
+    println!("plain run:           {:?}", plain);
+    println!("perf, no setup:      {:?}", rebuilt);
+    println!("perf, after setup:   {:?}", cached);
+    let overhead = cached.checked_sub(plain).unwrap_or_default();
+    println!("overhead after setup: {:?} per run", overhead);
+    if overhead >= TARGET {
+        println!("over the target of {:?}", TARGET);
+        std::process::exit(1);
+    }
+}
@@ -51,6 +51,7 @@ fn main() {
         .whitelist_type("perf_event_attr")
         .whitelist_type("perf_type_id")
         .whitelist_type("perf_hw_id")
+        .whitelist_type("perf_sw_ids")
         .generate()
         .expect("Unable to generate bindings");
 
@@ -0,0 +1,4 @@
+//! The C API of src/ffi.rs as a shared library, see include/b7.h. It is
+//! a crate of its own so that only `cargo build` in this directory links
+//! libb7.so, and B7 itself stays a plain Rust library
+pub use libb7::ffi::*;
@@ -0,0 +1,48 @@
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+// Compile tests/ffi_smoke.c against the cdylib and run it
+#[test]
+fn c_smoke_test() {
+    let ffi = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let include = ffi.parent().unwrap().join("include");
+    // integration tests live in target/<profile>/deps,
+    // the cdylib is one directory up
+    let lib_dir = env::current_exe()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .parent()
+        .unwrap()
+        .to_path_buf();
+    let exe = lib_dir.join("ffi_smoke");
+
+    let compiler = cc::Build::new()
+        .cargo_metadata(false)
+        .opt_level(0)
+        .host("x86_64-unknown-linux-gnu")
+        .target("x86_64-unknown-linux-gnu")
+        .get_compiler();
+    let status = compiler
+        .to_command()
+        .arg(ffi.join("tests").join("ffi_smoke.c"))
+        .arg("-I")
+        .arg(include)
+        .arg("-L")
+        .arg(&lib_dir)
+        .arg("-lb7")
+        .arg("-o")
+        .arg(&exe)
+        .status()
+        .expect("failed to run the C compiler");
+    assert!(status.success());
+
+    let status = Command::new(&exe)
+        .env("LD_LIBRARY_PATH", &lib_dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
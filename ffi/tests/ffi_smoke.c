@@ -0,0 +1,64 @@
+/* Solves a flag through the C API with the mock solver.
+ * Built and run by tests/ffi.rs */
+#include <stdio.h>
+#include <string.h>
+
+#include "b7.h"
+
+static int check(B7Status status, const char *what) {
+    if (status != B7_OK) {
+        const char *msg = b7_last_error_message();
+        fprintf(stderr, "%s failed (%d): %s\n", what, status, msg ? msg : "?");
+        return 0;
+    }
+    return 1;
+}
+
+int main(void) {
+    const char flag[] = "b7\xfeok";
+    B7FfiOpts *opts = NULL;
+    B7FfiResult *result = NULL;
+    size_t len = 0;
+    const uint8_t *stdin_bytes;
+
+    if (!check(b7_opts_new("mock", &opts), "b7_opts_new") ||
+        !check(b7_opts_set_mock_solver(opts, (const uint8_t *)flag, strlen(flag)),
+               "b7_opts_set_mock_solver") ||
+        !check(b7_opts_set_len(opts, 0, 8), "b7_opts_set_len") ||
+        !check(b7_opts_set_charset(opts, 0x00, 0xff), "b7_opts_set_charset") ||
+        !check(b7_run(opts, &result), "b7_run")) {
+        return 1;
+    }
+
+    stdin_bytes = b7_result_get_stdin(result, &len);
+    if (len != strlen(flag) || memcmp(stdin_bytes, flag, len) != 0) {
+        fprintf(stderr, "solved the wrong stdin (%zu bytes)\n", len);
+        return 1;
+    }
+    b7_result_free(result);
+
+    /* reused options still run with the mock solver */
+    if (!check(b7_run(opts, &result), "second b7_run")) {
+        return 1;
+    }
+    stdin_bytes = b7_result_get_stdin(result, &len);
+    if (len != strlen(flag) || memcmp(stdin_bytes, flag, len) != 0) {
+        fprintf(stderr, "second run solved the wrong stdin (%zu bytes)\n", len);
+        return 1;
+    }
+    b7_result_free(result);
+
+    /* errors come back as codes with a message, not crashes */
+    if (b7_opts_set_solver(opts, "nope") != B7_INVALID_ARGUMENT ||
+        b7_last_error_message() == NULL) {
+        fprintf(stderr, "unknown solver was accepted\n");
+        return 1;
+    }
+    if (b7_run(NULL, &result) != B7_INVALID_ARGUMENT) {
+        fprintf(stderr, "NULL opts was accepted\n");
+        return 1;
+    }
+
+    b7_opts_free(opts);
+    return 0;
+}
@@ -0,0 +1,91 @@
+#ifndef B7_H
+#define B7_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum {
+  B7_OK = 0,
+  B7_INVALID_ARGUMENT = 1,
+  B7_SOLVER_ERROR = 2,
+  B7_TIMEOUT = 3,
+  B7_PANIC = 4,
+} B7Status;
+
+/**
+ * Options for a single run, built up with the b7_opts_* functions
+ */
+typedef struct B7FfiOpts B7FfiOpts;
+
+/**
+ * Solved input of a run
+ */
+typedef struct B7FfiResult B7FfiResult;
+
+/**
+ * Message of the last error on this thread, or NULL if there was none.
+ * Valid until the next failing call on the same thread
+ */
+const char *b7_last_error_message(void);
+
+void b7_opts_free(B7FfiOpts *opts);
+
+/**
+ * Create options for brute forcing the binary at `path`, with the perf
+ * solver, stdin solving only and a 5 second timeout
+ */
+B7Status b7_opts_new(const char *path, B7FfiOpts **opts_out);
+
+/**
+ * Inclusive range of byte values tried at every position
+ */
+B7Status b7_opts_set_charset(B7FfiOpts *opts, uint8_t min, uint8_t max);
+
+B7Status b7_opts_set_len(B7FfiOpts *opts, uint32_t min_len, uint32_t max_len);
+
+/**
+ * Use the mock solver, which never runs the target and solves to `flag`
+ */
+B7Status b7_opts_set_mock_solver(B7FfiOpts *opts, const uint8_t *flag, uintptr_t len);
+
+B7Status b7_opts_set_phases(B7FfiOpts *opts, int argv, int stdin);
+
+/**
+ * Use one of the built in solvers, e.g. "perf" or "dynamorio"
+ */
+B7Status b7_opts_set_solver(B7FfiOpts *opts, const char *name);
+
+B7Status b7_opts_set_timeout_ms(B7FfiOpts *opts, uint64_t timeout_ms);
+
+/**
+ * Set a solver variable, e.g. "dynpath" for the dynamorio solver
+ */
+B7Status b7_opts_set_var(B7FfiOpts *opts, const char *key, const char *value);
+
+void b7_result_free(B7FfiResult *result);
+
+/**
+ * Solved argument `idx`, or NULL if there is no such argument
+ */
+const uint8_t *b7_result_get_arg(const B7FfiResult *result, uintptr_t idx, uintptr_t *len_out);
+
+uintptr_t b7_result_get_argc(const B7FfiResult *result);
+
+/**
+ * Solved stdin. The bytes are not NUL terminated and stay valid
+ * until the result is freed
+ */
+const uint8_t *b7_result_get_stdin(const B7FfiResult *result, uintptr_t *len_out);
+
+/**
+ * Run the brute force. The options can be reused for another run,
+ * which gets a fresh solver of the same kind. On success `*result_out` must
+ * be freed with b7_result_free
+ */
+B7Status b7_run(B7FfiOpts *opts, B7FfiResult **result_out);
+
+#endif /* B7_H */
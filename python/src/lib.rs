@@ -0,0 +1,252 @@
+//! Python bindings for B7, built with maturin:
+//!
+//! ```python
+//! import b7
+//! brute = b7.B7("./target", solver="perf", timeout=5.0)
+//! brute.on_progress(lambda counts, lowest: print(max(counts, key=lambda c: c[1])))
+//! print(brute.run()["stdin"])
+//! ```
+use libb7::b7tui::Ui;
+use libb7::brute::InstCounter;
+use libb7::errors::Runner;
+use libb7::{mock, solver_by_name, B7Opts, B7Results};
+use pyo3::exceptions;
+use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Solver that never runs the target, see b7::mock::MockSolver
+#[pyclass]
+#[derive(Clone)]
+struct MockSolver {
+    inner: mock::MockSolver,
+}
+
+#[pymethods]
+impl MockSolver {
+    #[new]
+    fn new(flag: &PyBytes) -> MockSolver {
+        MockSolver {
+            inner: mock::MockSolver::new(flag.as_bytes()),
+        }
+    }
+}
+
+// Forwards progress to the Python callback. The brute runs without
+// the GIL, so it is only taken back for the callback and to check for
+// Ctrl-C. Any Python error stops the run and is re-raised by run()
+struct PyUi {
+    on_progress: Option<PyObject>,
+    error: Option<PyErr>,
+}
+
+impl Ui for PyUi {
+    fn update<
+        I: 'static
+            + std::fmt::Display
+            + Copy
+            + Into<u64>
+            + std::fmt::Debug
+            + std::marker::Send
+            + std::cmp::Ord,
+    >(
+        &mut self,
+        results: &[(I, i64)],
+        min: u64,
+    ) -> bool {
+        if let Some(callback) = &self.on_progress {
+            let gil = Python::acquire_gil();
+            let counts: Vec<(String, i64)> =
+                results.iter().map(|r| (r.0.to_string(), r.1)).collect();
+            if let Err(e) = callback.call1(gil.python(), (counts, min)) {
+                self.error = Some(e);
+                return false;
+            }
+        }
+        true
+    }
+
+    fn wait(&mut self) -> bool {
+        let gil = Python::acquire_gil();
+        // raises KeyboardInterrupt if Ctrl-C was pressed since the last round
+        if let Err(e) = gil.python().check_signals() {
+            self.error = Some(e);
+            return false;
+        }
+        true
+    }
+
+    fn done(&mut self) -> bool {
+        true
+    }
+}
+
+#[derive(Clone)]
+enum SolverChoice {
+    Named(String),
+    Mock(MockSolver),
+}
+
+#[pyclass]
+struct B7 {
+    path: String,
+    solver: SolverChoice,
+    charset: Option<(u16, u16)>,
+    timeout: f64,
+    args: bool,
+    stdin: bool,
+    min_len: u32,
+    max_len: Option<u32>,
+    dynpath: Option<String>,
+    on_progress: Option<PyObject>,
+}
+
+#[pymethods]
+impl B7 {
+    #[new]
+    #[args(
+        solver = "None",
+        charset = "None",
+        timeout = "5.0",
+        args = "false",
+        stdin = "true",
+        min_len = "0",
+        max_len = "None",
+        dynpath = "None"
+    )]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        path: String,
+        solver: Option<&PyAny>,
+        charset: Option<(u16, u16)>,
+        timeout: f64,
+        args: bool,
+        stdin: bool,
+        min_len: u32,
+        max_len: Option<u32>,
+        dynpath: Option<String>,
+    ) -> PyResult<B7> {
+        if timeout.is_nan() || timeout < 0.0 || !timeout.is_finite() {
+            return Err(PyErr::new::<exceptions::ValueError, _>(format!(
+                "bad timeout {}, expected a number of seconds",
+                timeout
+            )));
+        }
+        let solver = match solver {
+            None => SolverChoice::Named(String::from("perf")),
+            Some(solver) => match solver.extract::<MockSolver>() {
+                Ok(mock) => SolverChoice::Mock(mock),
+                Err(_) => SolverChoice::Named(solver.extract()?),
+            },
+        };
+        Ok(B7 {
+            path,
+            solver,
+            charset,
+            timeout,
+            args,
+            stdin,
+            min_len,
+            max_len,
+            dynpath,
+            on_progress: None,
+        })
+    }
+
+    /// Call `callback(counts, lowest)` after every round, with the
+    /// (candidate, count) pairs of the round and the lowest count
+    fn on_progress(&mut self, callback: PyObject) {
+        self.on_progress = Some(callback);
+    }
+
+    /// Run the brute force and return its results as a dict. Solved
+    /// inputs are bytes, exactly as they were fed to the target
+    fn run(&self, py: Python) -> PyResult<PyObject> {
+        let solver: Box<InstCounter> = match &self.solver {
+            SolverChoice::Named(name) => match solver_by_name(name) {
+                Some(solver) => solver,
+                None => {
+                    return Err(PyErr::new::<exceptions::ValueError, _>(format!(
+                        "unknown solver {}",
+                        name
+                    )))
+                }
+            },
+            SolverChoice::Mock(mock) => Box::new(mock.inner.clone()),
+        };
+
+        let mut vars = HashMap::new();
+        if let Some(dynpath) = &self.dynpath {
+            vars.insert(String::from("dynpath"), dynpath.clone());
+        }
+        let mut ui = PyUi {
+            on_progress: self.on_progress.as_ref().map(|cb| cb.clone_ref(py)),
+            error: None,
+        };
+        let timeout = Duration::from_millis((self.timeout * 1000.0) as u64);
+
+        let results = py.allow_threads(|| {
+            let mut opts = B7Opts::new(
+                self.path.clone(),
+                self.args,
+                self.stdin,
+                solver,
+                &mut ui,
+                vars,
+                timeout,
+            );
+            opts.set_min_len(self.min_len).set_max_len(self.max_len);
+            if let Some((min, max)) = self.charset {
+                opts.set_charset(min, max);
+            }
+            opts.run()
+        });
+
+        if let Some(e) = ui.error.take() {
+            return Err(e);
+        }
+        match results {
+            Ok(results) => to_dict(py, &results),
+            Err(e) => match e.runner() {
+                Runner::Timeout => Err(PyErr::new::<exceptions::TimeoutError, _>(e.to_string())),
+                _ => Err(PyErr::new::<exceptions::RuntimeError, _>(e.to_string())),
+            },
+        }
+    }
+}
+
+// B7Results as a dict, one entry per field
+fn to_dict(py: Python, results: &B7Results) -> PyResult<PyObject> {
+    let dict = PyDict::new(py);
+    let argv: Vec<&PyBytes> = results.argv.iter().map(|a| PyBytes::new(py, a)).collect();
+    dict.set_item("argv", argv)?;
+    dict.set_item("stdin", PyBytes::new(py, &results.stdin))?;
+    dict.set_item("arg_brute", &results.arg_brute)?;
+    dict.set_item("stdin_brute", &results.stdin_brute)?;
+    dict.set_item("min_len", results.min_len)?;
+    dict.set_item("max_len", results.max_len)?;
+    dict.set_item("cancelled", results.cancelled)?;
+    dict.set_item("fallback", &results.fallback)?;
+
+    let mut phases = Vec::new();
+    for phase in &results.stats.phases {
+        let stats = PyDict::new(py);
+        stats.set_item("name", &phase.name)?;
+        stats.set_item("runs", phase.runs)?;
+        stats.set_item("memo_hits", phase.memo_hits)?;
+        stats.set_item("errors", phase.errors)?;
+        stats.set_item("timeouts", phase.timeouts)?;
+        stats.set_item("elapsed", phase.elapsed.as_secs_f64())?;
+        phases.push(stats);
+    }
+    dict.set_item("stats", phases)?;
+    Ok(dict.to_object(py))
+}
+
+#[pymodule]
+fn b7(_py: Python, m: &PyModule) -> PyResult<()> {
+    m.add_class::<B7>()?;
+    m.add_class::<MockSolver>()?;
+    Ok(())
+}
@@ -0,0 +1,49 @@
+import b7
+
+
+def test_mock_solves_stdin():
+    brute = b7.B7("mock", solver=b7.MockSolver(b"b7ok"), max_len=8)
+    best = []
+    brute.on_progress(lambda counts, lowest: best.append(max(counts, key=lambda c: c[1])))
+
+    res = brute.run()
+    assert res["stdin"] == b"b7ok"
+    assert res["stdin_brute"] == "b7ok"
+    assert [b[0] for b in best[-4:]] == [str(c) for c in b"b7ok"]
+    assert sum(p["runs"] for p in res["stats"]) > 0
+
+
+def test_bytes_round_trip():
+    flag = b"\xfe\x00\xff"
+    brute = b7.B7("mock", solver=b7.MockSolver(flag), charset=(0x00, 0xFF), max_len=8)
+    assert brute.run()["stdin"] == flag
+
+
+def test_progress_error_stops_run():
+    def boom(counts, lowest):
+        raise ValueError("stop")
+
+    brute = b7.B7("mock", solver=b7.MockSolver(b"b7ok"), max_len=8)
+    brute.on_progress(boom)
+    try:
+        brute.run()
+    except ValueError:
+        pass
+    else:
+        assert False, "run() should raise the callback's error"
+
+
+def test_bad_arguments():
+    try:
+        b7.B7("mock", timeout=-1.0)
+    except ValueError:
+        pass
+    else:
+        assert False, "a negative timeout should be rejected"
+
+    try:
+        b7.B7("mock", solver="nonexistent").run()
+    except ValueError:
+        pass
+    else:
+        assert False, "an unknown solver should be rejected"
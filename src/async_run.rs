@@ -0,0 +1,133 @@
+//! Running B7 from async code, behind the async feature.
+//!
+//! The brute itself stays synchronous: it runs on a thread of its own
+//! and reports back over channels, so no executor thread is blocked.
+//! Single children can be awaited with `finish`, which the waiter
+//! wakes instead of a thread blocking on the exit.
+//! As with `B7Opts`, SIGCHLD has to be blocked on every thread of the
+//! process, so call `process::block_signal` before starting the runtime.
+use crate::b7tui::Progress;
+pub use crate::b7tui::ProgressEvent;
+use crate::brute::InstCounter;
+use crate::cancel::CancelToken;
+use crate::config::OptsConfig;
+use crate::errors::*;
+use crate::process::{ExitStatus, ProcessHandle};
+use crate::{solver_by_name, B7Results};
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::{mpsc, oneshot};
+
+/// A brute running on its own thread. Resolves to the results of
+/// `B7Opts::run`; dropping it cancels the brute
+pub struct AsyncRun {
+    result: oneshot::Receiver<Result<B7Results, SolverError>>,
+    progress: Option<mpsc::UnboundedReceiver<ProgressEvent>>,
+    cancel: CancelToken,
+}
+
+impl AsyncRun {
+    /// Events for every round, ending when the brute does. Can only be
+    /// taken once
+    pub fn progress(&mut self) -> Option<impl Stream<Item = ProgressEvent>> {
+        self.progress.take()
+    }
+
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+}
+
+impl Future for AsyncRun {
+    type Output = Result<B7Results, SolverError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.result).poll(cx).map(|result| {
+            result.unwrap_or_else(|_| {
+                Err(SolverError::new(
+                    Runner::RunnerError,
+                    "brute thread panicked",
+                ))
+            })
+        })
+    }
+}
+
+impl Drop for AsyncRun {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// Start a brute with one of the built in solvers
+pub fn run_async(config: OptsConfig) -> AsyncRun {
+    match solver_by_name(&config.solver) {
+        Some(solver) => run_async_with(config, solver),
+        None => {
+            let (tx, result) = oneshot::channel();
+            let _ = tx.send(Err(SolverError::new(
+                Runner::MissingArgs,
+                &format!("unknown solver {}", config.solver),
+            )));
+            AsyncRun {
+                result,
+                progress: None,
+                cancel: CancelToken::new(),
+            }
+        }
+    }
+}
+
+/// Start a brute with any solver, ignoring the solver name of `config`
+pub fn run_async_with(config: OptsConfig, solver: Box<InstCounter>) -> AsyncRun {
+    let (result_tx, result) = oneshot::channel();
+    let (progress_tx, progress) = mpsc::unbounded_channel();
+    let cancel = CancelToken::new();
+    let token = cancel.clone();
+
+    thread::spawn(move || {
+        // nobody listening is fine, the result is still wanted
+        let mut ui = Progress::new(|event| {
+            let _ = progress_tx.send(event);
+            true
+        });
+        let mut opts = config.into_opts_with(solver, &mut ui);
+        opts.set_cancel_token(token);
+        let _ = result_tx.send(opts.run());
+    });
+
+    AsyncRun {
+        result,
+        progress: Some(progress),
+        cancel,
+    }
+}
+
+/// Waits for the child of `handle` to exit without blocking a thread.
+/// Like `ProcessHandle::try_finish` there is no timeout, drop the
+/// future (and the handle, which kills the child) to give up
+pub fn finish(handle: &mut ProcessHandle) -> Finish {
+    Finish { handle }
+}
+
+/// Future returned by `finish`
+pub struct Finish<'a> {
+    handle: &'a mut ProcessHandle,
+}
+
+impl<'a> Future for Finish<'a> {
+    type Output = Result<ExitStatus, SolverError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        // before checking, so a message sent in between still wakes us
+        self.handle.set_waker(cx.waker());
+        match self.handle.try_finish() {
+            Ok(Some(status)) => Poll::Ready(Ok(status)),
+            Ok(None) => Poll::Pending,
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
@@ -1,7 +1,12 @@
+use crate::logging;
+use crate::statistics;
 use log::LevelFilter;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::time::{Duration, Instant};
 use termion::event::Key;
 use termion::input::MouseTerminal;
 use termion::input::TermRead;
@@ -20,48 +25,102 @@ enum Format {
     Decimal,
 }
 
+// A candidate as the brute reported it, a byte or a length
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Candidate(u64);
+
+impl Candidate {
+    fn new<I: Copy + Into<u64>>(id: &I) -> Candidate {
+        Candidate((*id).into())
+    }
+
+    fn format(self, format: &Format) -> String {
+        match format {
+            Format::Decimal => self.0.to_string(),
+            Format::Hex => format!("{:x}", self.0),
+            // all bytes of the number, without leading zeros
+            Format::String => {
+                let bytes = self.0.to_be_bytes();
+                let first = bytes.iter().position(|&b| b != 0).unwrap_or(7);
+                String::from_utf8_lossy(&bytes[first..]).into_owned()
+            }
+        }
+    }
+}
+
 // Trait that all Uis will implement to ensure genericness
 pub trait Ui {
     // handle a new ui check
     fn update<
-        I: 'static + std::fmt::Display + Clone + std::fmt::Debug + std::marker::Send + std::cmp::Ord,
+        I: 'static
+            + std::fmt::Display
+            + Copy
+            + Into<u64>
+            + std::fmt::Debug
+            + std::marker::Send
+            + std::cmp::Ord,
     >(
         &mut self,
         results: &[(I, i64)],
         min: u64,
     ) -> bool;
-    // allow gui to pause if user doesn't want to continue
+    // how fast the phase runs so far, and the per run wall clock time of
+    // each candidate of the round. Called right before update
+    fn timing<I: std::fmt::Display>(&mut self, _runs_per_sec: f64, _latencies: &[(I, Duration)]) {}
+    // how long the rest of the phase should take, see
+    // statistics::estimate_duration. Called right before timing
+    fn eta(&mut self, _left: Option<Duration>) {}
+    // the statistics of the whole run, see RunStats::report. Called
+    // once all phases are done, right before done
+    fn stats(&mut self, _report: &[String]) {}
+    // allow gui to pause if user doesn't want to continue.
+    // false means the user quit, and the brute stops like on a cancel
     fn wait(&mut self) -> bool;
-    // separate wait to signify all results are calculated
+    // separate wait to signify all results are calculated,
+    // false if the user quit rather than just closing
     fn done(&mut self) -> bool;
 }
 
 // struct for Tui-rs implementation
 pub struct Tui {
     // TODO probably can be shortened with generics
-    terminal: tui::Terminal<
-        tui::backend::TermionBackend<
-            termion::screen::AlternateScreen<
-                termion::input::MouseTerminal<termion::raw::RawTerminal<std::io::Stdout>>,
+    // None once the user quit, dropping it restores the terminal
+    terminal: Option<
+        tui::Terminal<
+            tui::backend::TermionBackend<
+                termion::screen::AlternateScreen<
+                    termion::input::MouseTerminal<termion::raw::RawTerminal<std::io::Stdout>>,
+                >,
             >,
         >,
     >,
     size: tui::layout::Rect,
-    cache: Vec<(Vec<(u64, u64)>, u64)>,
+    cache: Vec<(Vec<(Candidate, u64)>, u64)>,
     numrun: u64,
+    // of the phase so far, shown in the title
+    runs_per_sec: f64,
+    eta: Option<Duration>,
     currun: u64,
     gap: u16,
     format: Format,
     cont: bool,
     path: Option<String>,
     history: Vec<String>,
+    // RunStats::report of the finished run, empty until then
+    stats: Vec<String>,
     selected: Option<usize>,
+    // first bar on screen, None to keep the round's outlier in view
+    scroll: Option<usize>,
+    // bars on screen at the last redraw
+    shown: Range<usize>,
 }
 
 // constructor
 impl Tui {
     pub fn new(path: Option<String>) -> Tui {
-        init_logger(LevelFilter::Trace).unwrap();
+        // --log-format json may have installed its own logger already,
+        // the log pane stays empty then
+        let _ = init_logger(LevelFilter::Trace);
 
         // Set default level for unknown targets to Trace
         set_default_level(LevelFilter::Info);
@@ -76,17 +135,22 @@ impl Tui {
         let cache = Vec::new();
         let history = Vec::new();
         Tui {
-            terminal,
+            terminal: Some(terminal),
             size,
             cache,
             numrun: 0,
+            runs_per_sec: 0.0,
+            eta: None,
             currun: 0,
             gap: 0,
             format: Format::Hex,
             cont: false,
             path,
             history,
+            stats: Vec::new(),
             selected: None,
+            scroll: None,
+            shown: 0..0,
         }
     }
     pub fn set_path(&mut self, path: String) {
@@ -112,52 +176,122 @@ impl Tui {
             None => return,
         }
     }
+
+    /// Write the run on screen to `<binary>.run<N>.csv`, one row per
+    /// candidate with its count and the count above the round minimum
+    pub fn export_csv(&self) -> io::Result<String> {
+        let run = match self.currun.checked_sub(1) {
+            Some(idx) if (idx as usize) < self.cache.len() => &self.cache[idx as usize],
+            _ => return Err(io::Error::new(io::ErrorKind::NotFound, "no run to export")),
+        };
+        let name = format!(
+            "{}.run{}.csv",
+            self.path.as_ref().map(|p| &**p).unwrap_or("b7"),
+            self.currun
+        );
+        write_csv(&mut File::create(&name)?, run)?;
+        Ok(name)
+    }
+
+    // export_csv, reporting the outcome in the log pane
+    fn export_key(&self) {
+        match self.export_csv() {
+            Ok(name) => info!("Exported run {} to {}", self.currun, name),
+            Err(e) => warn!("Failed to export run {}: {}", self.currun, e),
+        }
+    }
+
+    // leave the alternate screen and raw mode, nothing is drawn after
+    fn quit(&mut self) {
+        self.terminal = None;
+    }
+
     pub fn redraw(&mut self) -> bool {
+        self.load_cache();
+        let terminal = match self.terminal.as_mut() {
+            Some(terminal) => terminal,
+            None => return false,
+        };
         // resize terminal if needed
-        let size = self.terminal.size().unwrap();
+        let size = terminal.size().unwrap();
         if self.size != size {
-            self.terminal.resize(size).unwrap();
+            terminal.resize(size).unwrap();
             self.size = size;
         }
-        self.load_cache();
         if !self.cache.is_empty() {
             let history = &self.history;
+            let stats = &self.stats;
             let graph = &self.cache[(self.currun - 1) as usize];
             let graph3: Vec<(String, u64)> = graph
                 .0
                 .iter()
-                .map(|s| match self.format {
-                    Format::Decimal => (format!("{}", s.0), s.1 as u64),
-                    Format::Hex => (format!("{:x}", s.0), s.1 as u64),
-                    Format::String => (
-                        format!("{}", String::from_utf8_lossy(&[s.0 as u8])),
-                        s.1 as u64,
-                    ),
-                })
+                .map(|s| (s.0.format(&self.format), s.1))
                 .collect();
 
             let mut graph2: Vec<(&str, u64)> = Vec::new();
             let gap = self.gap;
-            self.terminal
+            let counts: Vec<(usize, i64)> = graph
+                .0
+                .iter()
+                .enumerate()
+                .map(|(i, s)| (i, s.1 as i64))
+                .collect();
+            let outlier = if counts.is_empty() {
+                0
+            } else {
+                statistics::find_outlier(&counts).0
+            };
+            let scroll = self.scroll;
+            let mut shown = 0..0;
+            let runs_per_sec = self.runs_per_sec;
+            let eta = self.eta;
+            let mut title = String::new();
+            terminal
                 .draw(|mut f| {
+                    // the statistics panel takes from the bars once the run is done
+                    let constraints = if stats.is_empty() {
+                        [
+                            Constraint::Percentage(60),
+                            Constraint::Percentage(25),
+                            Constraint::Percentage(15),
+                            Constraint::Percentage(0),
+                        ]
+                    } else {
+                        [
+                            Constraint::Percentage(40),
+                            Constraint::Percentage(20),
+                            Constraint::Percentage(15),
+                            Constraint::Percentage(25),
+                        ]
+                    };
                     let chunks = Layout::default()
                         .direction(Direction::Vertical)
                         .margin(1)
-                        .constraints(
-                            [
-                                Constraint::Percentage(60),
-                                Constraint::Percentage(25),
-                                Constraint::Percentage(15),
-                            ]
-                            .as_ref(),
-                        )
+                        .constraints(constraints.as_ref())
                         .split(size);
 
+                    // inside the borders
+                    let width = chunks[0].width.saturating_sub(2);
+                    shown = bar_window(graph3.len(), width, gap, scroll, outlier);
+                    title = format!("B7 ({:.1} runs/sec", runs_per_sec);
+                    if let Some(eta) = eta {
+                        title += &format!(", about {}s left", eta.as_secs());
+                    }
+                    title += ")";
+                    if shown.len() < graph3.len() {
+                        title += &format!(
+                            " bars {}-{} of {}",
+                            shown.start + 1,
+                            shown.end,
+                            graph3.len()
+                        );
+                    }
+
                     BarChart::default()
-                        .block(Block::default().title("B7").borders(Borders::ALL))
+                        .block(Block::default().title(&title).borders(Borders::ALL))
                         .data({
                             // convert String to &str and chop off uneccesary instructions
-                            graph2 = graph3
+                            graph2 = graph3[shown.clone()]
                                 .iter()
                                 .map(|s| {
                                     let adjusted = s.1 - graph.1;
@@ -166,7 +300,7 @@ impl Tui {
                                 .collect::<Vec<(&str, u64)>>();
                             &graph2
                         })
-                        .bar_width(2)
+                        .bar_width(BAR_WIDTH)
                         .style(Style::default().fg(Color::Yellow))
                         .value_style(Style::default().fg(Color::Black).bg(Color::Yellow))
                         .bar_gap(gap)
@@ -201,11 +335,72 @@ impl Tui {
                         )
                         .highlight_symbol(">")
                         .render(&mut f, chunks[2]);
+
+                    if !stats.is_empty() {
+                        SelectableList::default()
+                            .block(
+                                Block::default()
+                                    .borders(Borders::ALL)
+                                    .title("Run Statistics"),
+                            )
+                            .items(stats)
+                            .style(Style::default().fg(Color::White))
+                            .render(&mut f, chunks[3]);
+                    }
                 })
                 .unwrap();
+            if scroll.is_some() {
+                self.scroll = Some(shown.start);
+            }
+            self.shown = shown;
         }
         true
     }
+
+    // pan the bar chart by a screenful, redraw clamps it at the end.
+    // Switching runs follows the outlier again
+    fn scroll_bars(&mut self, forward: bool) {
+        let page = self.shown.len().max(1);
+        self.scroll = Some(if forward {
+            self.shown.start + page
+        } else {
+            self.shown.start.saturating_sub(page)
+        });
+    }
+}
+
+const BAR_WIDTH: u16 = 2;
+
+// the bars that fit in `width` columns. Without a scroll position the
+// window starts at the left unless that would hide the outlier, which
+// is centered then
+fn bar_window(
+    bars: usize,
+    width: u16,
+    gap: u16,
+    scroll: Option<usize>,
+    outlier: usize,
+) -> Range<usize> {
+    let fit = ((width + gap) / (BAR_WIDTH + gap)).max(1) as usize;
+    if bars <= fit {
+        return 0..bars;
+    }
+    let start = match scroll {
+        Some(start) => start,
+        None if outlier < fit => 0,
+        None => outlier - fit / 2,
+    };
+    let start = start.min(bars - fit);
+    start..start + fit
+}
+
+// (value, count) pairs of a round and its minimum count as CSV
+fn write_csv<W: Write>(out: &mut W, run: &(Vec<(Candidate, u64)>, u64)) -> io::Result<()> {
+    writeln!(out, "value,count,above_min")?;
+    for (value, count) in &run.0 {
+        writeln!(out, "{},{},{}", value.0, count, count.saturating_sub(run.1))?;
+    }
+    Ok(())
 }
 
 // default constructor for syntax sugar
@@ -219,45 +414,60 @@ impl Default for Tui {
 impl Ui for Tui {
     // draw bargraph for new input
     fn update<
-        I: 'static + std::fmt::Display + Clone + std::fmt::Debug + std::marker::Send + std::cmp::Ord,
+        I: 'static
+            + std::fmt::Display
+            + Copy
+            + Into<u64>
+            + std::fmt::Debug
+            + std::marker::Send
+            + std::cmp::Ord,
     >(
         &mut self,
         results: &[(I, i64)],
         min: u64,
     ) -> bool {
-        // convertcachefor barchart
-
-        // TODO implement multiple formats
-        let graph: Vec<(String, u64)>;
-        graph = results
+        // keep the candidates themselves, redraw formats them
+        let graph = results
             .iter()
-            .map(|s| (format!("{}", s.0), s.1 as u64))
+            .map(|s| (Candidate::new(&s.0), s.1 as u64))
             .collect();
-        self.cache.push((
-            graph
-                .iter()
-                .map(|s| ((s.0.parse::<u64>().unwrap()), s.1))
-                .collect::<Vec<(u64, u64)>>(),
-            min,
-        ));
+        self.cache.push((graph, min));
         if self.currun == self.numrun {
             self.currun += 1;
+            self.scroll = None;
         }
         self.numrun += 1;
         let _ = self.redraw();
 
         true
     }
+    fn timing<I: std::fmt::Display>(&mut self, runs_per_sec: f64, _latencies: &[(I, Duration)]) {
+        self.runs_per_sec = runs_per_sec;
+    }
+    fn eta(&mut self, left: Option<Duration>) {
+        self.eta = left;
+    }
+    fn stats(&mut self, report: &[String]) {
+        self.stats = report.to_vec();
+        let _ = self.redraw();
+    }
     // pause for user input before continuing
     fn wait(&mut self) -> bool {
+        if self.terminal.is_none() {
+            return false;
+        }
         let stdin = io::stdin();
         if !self.cont {
             for evt in stdin.keys() {
                 match evt {
-                    Ok(Key::Char('q')) => panic! {"Quitting"},
+                    Ok(Key::Char('q')) => {
+                        self.quit();
+                        return false;
+                    }
                     Ok(Key::Char('h')) => self.format = Format::Hex,
                     Ok(Key::Char('d')) => self.format = Format::Decimal,
                     Ok(Key::Char('s')) => self.format = Format::String,
+                    Ok(Key::Char('e')) => self.export_key(),
                     Ok(Key::Char('c')) => {
                         self.cont ^= true;
                         if self.cont {
@@ -265,6 +475,7 @@ impl Ui for Tui {
                         }
                     }
                     Ok(Key::Right) => {
+                        self.scroll = None;
                         if self.currun < self.numrun {
                             self.currun += 1;
                         } else {
@@ -272,10 +483,13 @@ impl Ui for Tui {
                         }
                     }
                     Ok(Key::Left) => {
+                        self.scroll = None;
                         if self.currun > 1 {
                             self.currun -= 1;
                         }
                     }
+                    Ok(Key::PageDown) => self.scroll_bars(true),
+                    Ok(Key::PageUp) => self.scroll_bars(false),
                     Ok(Key::Up) => {
                         match self.selected {
                             Some(x) => {
@@ -320,24 +534,36 @@ impl Ui for Tui {
     }
     // wait at the end of the program to show results
     fn done(&mut self) -> bool {
+        if self.terminal.is_none() {
+            return false;
+        }
         let stdin = io::stdin();
         for evt in stdin.keys() {
             match evt {
-                Ok(Key::Char('q')) => panic! {"Quitting"},
-                Ok(Key::Char('p')) => panic!("Force Closing"),
+                // 'p' used to panic to force close, it quits like 'q' now
+                // so the terminal is restored and results are still written
+                Ok(Key::Char('q')) | Ok(Key::Char('p')) => {
+                    self.quit();
+                    return false;
+                }
                 Ok(Key::Char('h')) => self.format = Format::Hex,
                 Ok(Key::Char('d')) => self.format = Format::Decimal,
                 Ok(Key::Char('s')) => self.format = Format::String,
+                Ok(Key::Char('e')) => self.export_key(),
                 Ok(Key::Right) => {
+                    self.scroll = None;
                     if self.currun < self.numrun {
                         self.currun += 1;
                     }
                 }
                 Ok(Key::Left) => {
+                    self.scroll = None;
                     if self.currun > 1 {
                         self.currun -= 1;
                     }
                 }
+                Ok(Key::PageDown) => self.scroll_bars(true),
+                Ok(Key::PageUp) => self.scroll_bars(false),
                 Ok(Key::Up) => {
                     match self.selected {
                         Some(x) => {
@@ -374,30 +600,78 @@ impl Ui for Tui {
 }
 
 #[derive(Default)]
-pub struct Env;
+pub struct Env {
+    // for the elapsed time in the progress lines
+    started: Option<Instant>,
+    // of the phase, from the eta call of the round
+    eta: Option<Duration>,
+}
 
 impl Env {
     // initialize the logging
     pub fn new() -> Env {
         let env = env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info");
-        env_logger::Builder::from_env(env)
+        // tests create several Envs in one process, so the logger may
+        // already be installed
+        let _ = env_logger::Builder::from_env(env)
             .default_format_timestamp(false)
-            .init();
-        Env {}
+            .try_init();
+        Env {
+            started: Some(Instant::now()),
+            eta: None,
+        }
     }
 }
 
-// default do nothing just let the prints handle it
+// a progress line per round, the rest is left to the log
 impl Ui for Env {
     fn update<
-        I: 'static + std::fmt::Display + Clone + std::fmt::Debug + std::marker::Send + std::cmp::Ord,
+        I: 'static
+            + std::fmt::Display
+            + Copy
+            + Into<u64>
+            + std::fmt::Debug
+            + std::marker::Send
+            + std::cmp::Ord,
     >(
         &mut self,
-        _results: &[(I, i64)],
+        results: &[(I, i64)],
         _min: u64,
     ) -> bool {
+        let started = *self.started.get_or_insert_with(Instant::now);
+        if results.is_empty() {
+            return true;
+        }
+        let ctx = logging::context();
+        let best = statistics::find_outlier(results);
+        let candidate = Candidate::new(&best.0);
+        let eta = match self.eta {
+            Some(eta) => format!(", about {}s left", eta.as_secs()),
+            None => String::new(),
+        };
+        info!(
+            "{} position {}: best so far {} ({:?}) with {}, {:.1}s elapsed{}",
+            ctx.phase.as_ref().map_or("brute", |p| p.as_str()),
+            ctx.round.unwrap_or(0),
+            candidate.format(&Format::Hex),
+            candidate.format(&Format::String),
+            best.1,
+            started.elapsed().as_secs_f64(),
+            eta
+        );
         true
     }
+    fn eta(&mut self, left: Option<Duration>) {
+        self.eta = left;
+    }
+    fn timing<I: std::fmt::Display>(&mut self, runs_per_sec: f64, latencies: &[(I, Duration)]) {
+        if let Some(slowest) = latencies.iter().max_by_key(|l| l.1) {
+            debug!(
+                "{:.1} runs/sec, slowest candidate {} took {:?}",
+                runs_per_sec, slowest.0, slowest.1
+            );
+        }
+    }
     fn wait(&mut self) -> bool {
         true
     }
@@ -405,3 +679,121 @@ impl Ui for Env {
         true
     }
 }
+
+/// One round of a brute, as reported by `Progress`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ProgressEvent {
+    pub phase: Option<String>,
+    pub round: Option<u32>,
+    /// (candidate, count) pairs of the round
+    pub counts: Vec<(String, i64)>,
+    pub min: u64,
+    /// runs per second of the phase so far
+    #[serde(default)]
+    pub runs_per_sec: f64,
+    /// (candidate, wall clock time of one run) pairs of the candidates
+    /// that ran, memoized ones are left out
+    #[serde(default)]
+    pub latencies: Vec<(String, Duration)>,
+}
+
+/// Ui that hands every round to a callback, for frontends that aren't
+/// a terminal. The brute stops when the callback returns false
+pub struct Progress<F: FnMut(ProgressEvent) -> bool> {
+    callback: F,
+    // from the timing call of the round, sent along with its update
+    runs_per_sec: f64,
+    latencies: Vec<(String, Duration)>,
+}
+
+impl<F: FnMut(ProgressEvent) -> bool> Progress<F> {
+    pub fn new(callback: F) -> Progress<F> {
+        Progress {
+            callback,
+            runs_per_sec: 0.0,
+            latencies: Vec::new(),
+        }
+    }
+}
+
+impl<F: FnMut(ProgressEvent) -> bool> Ui for Progress<F> {
+    fn update<
+        I: 'static
+            + std::fmt::Display
+            + Copy
+            + Into<u64>
+            + std::fmt::Debug
+            + std::marker::Send
+            + std::cmp::Ord,
+    >(
+        &mut self,
+        results: &[(I, i64)],
+        min: u64,
+    ) -> bool {
+        let ctx = logging::context();
+        (self.callback)(ProgressEvent {
+            phase: ctx.phase,
+            round: ctx.round,
+            counts: results.iter().map(|r| (r.0.to_string(), r.1)).collect(),
+            min,
+            runs_per_sec: self.runs_per_sec,
+            latencies: std::mem::replace(&mut self.latencies, Vec::new()),
+        })
+    }
+
+    fn timing<I: std::fmt::Display>(&mut self, runs_per_sec: f64, latencies: &[(I, Duration)]) {
+        self.runs_per_sec = runs_per_sec;
+        self.latencies = latencies.iter().map(|l| (l.0.to_string(), l.1)).collect();
+    }
+
+    fn wait(&mut self) -> bool {
+        true
+    }
+
+    fn done(&mut self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{bar_window, write_csv, Candidate, Format};
+
+    #[test]
+    fn bars_on_screen() {
+        // 20 columns hold 10 bars without a gap, 7 with one
+        assert_eq!(bar_window(256, 20, 0, None, 3), 0..10);
+        assert_eq!(bar_window(256, 20, 1, None, 3), 0..7);
+        // the outlier is centered once it would be off screen
+        assert_eq!(bar_window(256, 20, 0, None, 0x41), 60..70);
+        assert_eq!(bar_window(256, 20, 0, None, 255), 246..256);
+        // panning is clamped at the end
+        assert_eq!(bar_window(256, 20, 0, Some(100), 3), 100..110);
+        assert_eq!(bar_window(256, 20, 0, Some(300), 3), 246..256);
+        // everything fits, or nothing but a bar does
+        assert_eq!(bar_window(5, 200, 0, Some(3), 3), 0..5);
+        assert_eq!(bar_window(256, 0, 0, None, 0), 0..1);
+    }
+
+    #[test]
+    fn csv_rows() {
+        let run = vec![(Candidate(0x41), 120), (Candidate(0x42), 100)];
+        let mut out = Vec::new();
+        write_csv(&mut out, &(run, 100)).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "value,count,above_min\n65,120,20\n66,100,0\n"
+        );
+    }
+
+    #[test]
+    fn candidate_formats() {
+        assert_eq!(Candidate::new(&0x41u8), Candidate(0x41));
+        assert_eq!(Candidate::new(&300u32), Candidate(300));
+        assert_eq!(Candidate(0x41).format(&Format::String), "A");
+        assert_eq!(Candidate(0x4142).format(&Format::String), "AB");
+        assert_eq!(Candidate(0).format(&Format::String), "\0");
+        assert_eq!(Candidate(255).format(&Format::Hex), "ff");
+        assert_eq!(Candidate(255).format(&Format::Decimal), "255");
+    }
+}
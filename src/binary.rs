@@ -1,6 +1,63 @@
+use crate::errors::*;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::Mutex;
+
+// e_ident[EI_CLASS] of an ELF header
+const EI_CLASS: usize = 4;
+const ELFCLASS32: u8 = 1;
+const ELFCLASS64: u8 = 2;
+// e_ident[EI_DATA], the byte order of the rest of the header
+const EI_DATA: usize = 5;
+const ELFDATA2MSB: u8 = 2;
+// offset of e_type and e_machine, the same for both classes
+const E_TYPE: usize = 16;
+const E_MACHINE: usize = 18;
+// e_type of position independent executables (and shared libraries)
+const ET_DYN: u64 = 3;
+// p_type of the program header naming the dynamic loader
+const PT_INTERP: u64 = 3;
+// the size of an ELF64 header, the longer of the two
+const EHDR_LEN: usize = 64;
+
 #[derive(Debug)]
 pub struct Binary {
     path: String,
+    // parsed on first use, see elf()
+    elf: Mutex<Option<Elf>>,
+}
+
+/// Word size of a binary, from the class in its ELF header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bits {
+    Bits32,
+    Bits64,
+}
+
+/// Byte order of a binary, from the data encoding in its ELF header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Endian {
+    Little,
+    Big,
+}
+
+// what the accessors of Binary need from its headers
+#[derive(Clone, Copy, Debug)]
+struct Elf {
+    bits: Bits,
+    endian: Endian,
+    machine: u16,
+    pie: bool,
+}
+
+// an unsigned field of len bytes at off
+fn field(bytes: &[u8], off: usize, len: usize, endian: Endian) -> u64 {
+    let bytes = &bytes[off..off + len];
+    let fold = |n, b: &u8| n << 8 | u64::from(*b);
+    match endian {
+        Endian::Big => bytes.iter().fold(0, fold),
+        Endian::Little => bytes.iter().rev().fold(0, fold),
+    }
 }
 
 // holds the path to the binary possibly expanded later
@@ -8,6 +65,193 @@ impl Binary {
     pub fn new(path: &str) -> Binary {
         Binary {
             path: path.to_string(),
+            elf: Mutex::new(None),
         }
     }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    // The headers, read once and then kept. Errors aren't, so a binary
+    // that is still being written can be looked at again
+    fn elf(&self) -> Result<Elf, SolverError> {
+        let mut elf = self.elf.lock().unwrap();
+        if let Some(elf) = *elf {
+            return Ok(elf);
+        }
+        let parsed = self.parse()?;
+        *elf = Some(parsed);
+        Ok(parsed)
+    }
+
+    fn parse(&self) -> Result<Elf, SolverError> {
+        let mut file = File::open(&self.path)?;
+        let mut header = [0; EHDR_LEN];
+        let len = file.read(&mut header)?;
+        if len < E_MACHINE + 2 || &header[..4] != b"\x7fELF" {
+            return Err(SolverError::new(
+                Runner::IoError,
+                &format!("{} is not an ELF binary", self.path),
+            ));
+        }
+        let bits = match header[EI_CLASS] {
+            ELFCLASS32 => Bits::Bits32,
+            ELFCLASS64 => Bits::Bits64,
+            class => {
+                return Err(SolverError::new(
+                    Runner::IoError,
+                    &format!("{} has unknown ELF class {}", self.path, class),
+                ))
+            }
+        };
+        let endian = if header[EI_DATA] == ELFDATA2MSB {
+            Endian::Big
+        } else {
+            Endian::Little
+        };
+        let machine = field(&header, E_MACHINE, 2, endian) as u16;
+
+        // e_phoff, e_phentsize and e_phnum, and the size of e_phoff
+        let (phoff, phentsize, phnum, word) = match bits {
+            Bits::Bits32 => (28, 42, 44, 4),
+            Bits::Bits64 => (32, 54, 56, 8),
+        };
+        if len < phnum + 2 {
+            return Err(SolverError::new(
+                Runner::IoError,
+                &format!("{} has a truncated ELF header", self.path),
+            ));
+        }
+        // An ET_DYN that asks for a loader is an executable, a shared
+        // library doesn't. Static PIEs have no loader and count as not PIE
+        let pie = field(&header, E_TYPE, 2, endian) == ET_DYN && {
+            let phoff = field(&header, phoff, word, endian);
+            let phentsize = field(&header, phentsize, 2, endian);
+            let phnum = field(&header, phnum, 2, endian);
+            let mut phdrs = Vec::new();
+            file.seek(SeekFrom::Start(phoff))?;
+            file.take(phentsize * phnum).read_to_end(&mut phdrs)?;
+            phentsize >= 4
+                && phdrs
+                    .chunks(phentsize as usize)
+                    .filter(|phdr| phdr.len() == phentsize as usize)
+                    .any(|phdr| field(phdr, 0, 4, endian) == PT_INTERP)
+        };
+
+        Ok(Elf {
+            bits,
+            endian,
+            machine,
+            pie,
+        })
+    }
+
+    /// Reads the ELF header to tell 32 from 64 bit binaries
+    pub fn bits(&self) -> Result<Bits, SolverError> {
+        Ok(self.elf()?.bits)
+    }
+
+    /// Byte order of the binary, e.g. to read addresses out of its memory
+    pub fn endian(&self) -> Result<Endian, SolverError> {
+        Ok(self.elf()?.endian)
+    }
+
+    /// Whether the binary is a position independent executable, loaded
+    /// at a random address unless ASLR is off. Static PIEs count as not
+    pub fn is_pie(&self) -> Result<bool, SolverError> {
+        Ok(self.elf()?.pie)
+    }
+
+    /// Architecture of the binary, named the way qemu-user names it,
+    /// e.g. "arm" or "mipsel"
+    pub fn arch(&self) -> Result<&'static str, SolverError> {
+        let elf = self.elf()?;
+        let machine = elf.machine;
+        let bits64 = elf.bits == Bits::Bits64;
+        let big_endian = elf.endian == Endian::Big;
+        let arch = match (machine, bits64, big_endian) {
+            (3, _, _) => "i386",
+            (62, _, _) => "x86_64",
+            (40, _, false) => "arm",
+            (40, _, true) => "armeb",
+            (183, _, _) => "aarch64",
+            (8, false, true) => "mips",
+            (8, false, false) => "mipsel",
+            (8, true, true) => "mips64",
+            (8, true, false) => "mips64el",
+            (20, _, _) => "ppc",
+            (21, _, _) => "ppc64",
+            (243, false, _) => "riscv32",
+            (243, true, _) => "riscv64",
+            _ => {
+                return Err(SolverError::new(
+                    Runner::IoError,
+                    &format!("{} has unknown ELF machine {}", self.path, machine),
+                ))
+            }
+        };
+        Ok(arch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Binary, Bits, Endian};
+
+    fn sample(name: &str) -> Binary {
+        Binary::new(&format!("{}/tests/{}", env!("CARGO_MANIFEST_DIR"), name))
+    }
+
+    #[test]
+    fn elf_class() {
+        assert_eq!(sample("strlen_test_x86").bits().unwrap(), Bits::Bits32);
+        assert_eq!(sample("strlen_test_x64").bits().unwrap(), Bits::Bits64);
+        assert!(sample("fixtures/cat_flag.sh").bits().is_err());
+    }
+
+    #[test]
+    fn elf_arch() {
+        assert_eq!(sample("strlen_test_x86").arch().unwrap(), "i386");
+        assert_eq!(sample("strlen_test_x64").arch().unwrap(), "x86_64");
+    }
+
+    #[test]
+    fn elf_endian() {
+        assert_eq!(sample("strlen_test_x86").endian().unwrap(), Endian::Little);
+        assert_eq!(sample("strlen_test_x64").endian().unwrap(), Endian::Little);
+    }
+
+    // a 64 bit ET_DYN with one program header of type p_type
+    fn dyn_with_phdr(name: &str, p_type: u8) -> Binary {
+        let mut elf = vec![0; 64 + 56];
+        elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+        elf[16] = 3;
+        elf[18] = 62;
+        elf[32] = 64;
+        elf[54] = 56;
+        elf[56] = 1;
+        elf[64] = p_type;
+        let path = std::env::temp_dir().join(format!("b7-{}-{}", name, std::process::id()));
+        std::fs::write(&path, &elf).unwrap();
+        Binary::new(path.to_str().unwrap())
+    }
+
+    #[test]
+    fn pie() {
+        assert!(!sample("strlen_test_x86").is_pie().unwrap());
+        assert!(!sample("strlen_test_x64").is_pie().unwrap());
+
+        // PT_INTERP, so an executable rather than a library
+        let pie = dyn_with_phdr("pie", 3);
+        assert!(pie.is_pie().unwrap());
+        assert_eq!(pie.arch().unwrap(), "x86_64");
+        std::fs::remove_file(pie.path()).unwrap();
+        // read once, so the answer outlives the file
+        assert!(pie.is_pie().unwrap());
+
+        let library = dyn_with_phdr("library", 1);
+        assert!(!library.is_pie().unwrap());
+        std::fs::remove_file(library.path()).unwrap();
+    }
 }
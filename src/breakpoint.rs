@@ -0,0 +1,134 @@
+use crate::brute::*;
+use crate::errors::*;
+use crate::process::ProcessTemplate;
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::mem;
+
+// DR7 bits enabling debug register 0 as a 1 byte execution breakpoint.
+// Only L0 is set; RW0 and LEN0 are both zero for instruction breakpoints
+const DR7_L0: u64 = 1;
+
+// offset of u_debugreg[n] in the `struct user` used by PTRACE_POKEUSER
+fn debugreg_offset(n: usize) -> usize {
+    let user: libc::user = unsafe { mem::zeroed() };
+    let base = &user as *const libc::user as usize;
+    let reg = &user.u_debugreg[n] as *const _ as usize;
+    reg - base
+}
+
+fn set_debugreg(pid: Pid, n: usize, value: u64) -> Result<(), SolverError> {
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_POKEUSER,
+            pid.as_raw(),
+            debugreg_offset(n) as *mut libc::c_void,
+            value as *mut libc::c_void,
+        )
+    };
+    if res == -1 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+// parse an address like 0x401136 or 401136
+fn parse_addr(addr: &str) -> Result<u64, SolverError> {
+    let hex = addr.trim_start_matches("0x");
+    u64::from_str_radix(hex, 16).map_err(|_| {
+        SolverError::new(
+            Runner::MissingArgs,
+            &format!("could not parse cmp_addr {}", addr),
+        )
+    })
+}
+
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let mut template = ProcessTemplate::new(path);
+    template.apply_vars(vars)?;
+    template.with_ptrace(true);
+    Ok(template)
+}
+
+/// Counts how often the instruction at `cmp_addr` (from vars) executes,
+/// using a hardware breakpoint. The address must be the runtime address,
+/// so PIE targets need ASLR disabled.
+#[derive(Clone, Default)]
+pub struct BreakpointSolver {
+    template: Option<ProcessTemplate>,
+}
+
+impl BreakpointSolver {
+    pub fn new() -> BreakpointSolver {
+        BreakpointSolver { template: None }
+    }
+}
+
+impl InstCounter for BreakpointSolver {
+    fn name(&self) -> &str {
+        "breakpoint"
+    }
+
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.template.as_ref()
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.template = Some(make_template(path, vars)?);
+        Ok(())
+    }
+
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let addr = match data.vars.get("cmp_addr") {
+            Some(addr) => parse_addr(addr)?,
+            None => {
+                return Err(SolverError::new(
+                    Runner::MissingArgs,
+                    "breakpoint solver needs cmp_addr",
+                ))
+            }
+        };
+
+        let template = template_for(self.template.as_ref(), data, make_template)?;
+
+        let handle = template.instantiate(&data.inp).spawn()?;
+        let mut armed = false;
+        let mut skip = handle.wrapper_execs();
+        let mut hits: i64 = 0;
+        handle.finish_with(data.timeout, |status| {
+            if let WaitStatus::Stopped(pid, Signal::SIGTRAP) = *status {
+                if armed {
+                    hits += 1;
+                } else if skip > 0 {
+                    // a wrapper's exec, the target isn't mapped yet
+                    skip -= 1;
+                } else {
+                    // first stop is the exec, the target is mapped now
+                    set_debugreg(pid, 0, addr)?;
+                    set_debugreg(pid, 7, DR7_L0)?;
+                    armed = true;
+                }
+            }
+            Ok(())
+        })?;
+
+        Ok(hits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_addr;
+
+    #[test]
+    fn addr() {
+        assert_eq!(parse_addr("0x401136").unwrap(), 0x401136);
+        assert_eq!(parse_addr("401136").unwrap(), 0x401136);
+        assert!(parse_addr("main").is_err());
+    }
+}
@@ -1,45 +1,527 @@
 // use std::cmp::Ord;
 use scoped_pool::Pool;
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fmt::{Debug, Display};
 use std::marker::Send;
 use std::sync::mpsc::channel;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::b7tui;
+use crate::cancel::{self, CancelToken};
 use crate::errors::*;
 use crate::generators::{Generate, Input};
+use crate::logging;
+use crate::memo::Memo;
+use crate::process::{OutputMode, Process, ProcessTemplate};
 use crate::statistics;
+use crate::statistics::{PhaseStats, Tie};
+#[cfg(target_arch = "x86_64")]
+use crate::syscalls;
+use nix::sys::signal::Signal;
 
+/// Everything a solver needs to measure one input
 #[derive(Clone, Debug)]
 pub struct InstCountData {
+    /// Path of the target as given to B7, relative to B7's working
+    /// directory. Solvers run it in the directory of the "cwd" var if
+    /// there is one, otherwise in that same working directory
     pub path: String,
     pub inp: Input,
+    /// Solver variables of the session, e.g. "dynpath" for dynamorio,
+    /// plus flags like "pin_cpu" which are present with any value
     pub vars: HashMap<String, String>,
+    /// How long a single run may take before it counts as a timeout
     pub timeout: Duration,
 }
 
+impl InstCountData {
+    /// True for a dry run (the "dry_run" var), which shows the command
+    /// lines that would run instead of running them
+    pub fn dry_run(&self) -> bool {
+        self.vars.contains_key("dry_run")
+    }
+
+    /// Data for running `path` with an empty input, no vars and a 5
+    /// second timeout
+    pub fn builder(path: &str) -> InstCountDataBuilder {
+        InstCountDataBuilder {
+            data: InstCountData {
+                path: path.to_string(),
+                inp: Input::new(vec![], vec![]),
+                vars: HashMap::new(),
+                timeout: Duration::new(5, 0),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct InstCountDataBuilder {
+    data: InstCountData,
+}
+
+impl InstCountDataBuilder {
+    pub fn input(&mut self, inp: Input) -> &mut Self {
+        self.data.inp = inp;
+        self
+    }
+
+    pub fn var(&mut self, key: &str, value: &str) -> &mut Self {
+        self.data.vars.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.data.timeout = timeout;
+        self
+    }
+
+    pub fn build(&self) -> InstCountData {
+        self.data.clone()
+    }
+}
+
+/// How a run started by `run_target` ended
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChildOutcome {
+    Exited(i32),
+    /// Killed by a signal, e.g. SIGSEGV for a crash
+    Signaled {
+        signal: Signal,
+        core_dumped: bool,
+    },
+    /// Still running at the timeout, so it was killed
+    TimedOut,
+}
+
+/// What a run started by `run_target` wrote, and how long it took
+#[derive(Clone, Debug)]
+pub struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+    /// stdout went past the "max_output" var and was cut off
+    pub stdout_truncated: bool,
+    pub runtime: Option<Duration>,
+}
+
+/// The template a solver's `setup` made, or if `setup` was never
+/// called one that `make` builds from `data` for just this run
+pub fn template_for<'a, F>(
+    template: Option<&'a ProcessTemplate>,
+    data: &InstCountData,
+    make: F,
+) -> Result<Cow<'a, ProcessTemplate>, SolverError>
+where
+    F: FnOnce(&str, &HashMap<String, String>) -> Result<ProcessTemplate, SolverError>,
+{
+    match template {
+        Some(template) => Ok(Cow::Borrowed(template)),
+        None => make(&data.path, &data.vars).map(Cow::Owned),
+    }
+}
+
+// What `counter` would run for `inp`, with the input's stdin
+fn command_line(
+    counter: &InstCounter,
+    session: &Session,
+    inp: &Input,
+) -> Result<String, SolverError> {
+    let process = match counter.template() {
+        Some(template) => template.instantiate(inp),
+        None => {
+            let mut template = ProcessTemplate::new(session.path);
+            template.apply_vars(&session.vars)?;
+            template.instantiate(inp)
+        }
+    };
+    Ok(format!(
+        "{} < {:?}",
+        process.command_line(),
+        String::from_utf8_lossy(&inp.stdin)
+    ))
+}
+
+/// Run the target of `data` on its input, without instrumentation, and
+/// wait for it to exit or time out. The target is pinned to a single
+/// CPU when the "pin_cpu" var is set, and in the directory of
+/// the "cwd" var
+pub fn run_target(data: &InstCountData) -> Result<(ChildOutcome, CapturedOutput), SolverError> {
+    let mut template = ProcessTemplate::new(&data.path);
+    template.apply_vars(&data.vars)?;
+    run_template(&template, data)
+}
+
+/// Like `run_target`, but runs `template`, e.g. the target wrapped in
+/// an instrumentation tool, with the input of `data`
+pub fn run_template(
+    template: &ProcessTemplate,
+    data: &InstCountData,
+) -> Result<(ChildOutcome, CapturedOutput), SolverError> {
+    run(template, &data.inp, data.timeout)
+}
+
+fn run(
+    template: &ProcessTemplate,
+    inp: &Input,
+    timeout: Duration,
+) -> Result<(ChildOutcome, CapturedOutput), SolverError> {
+    run_process(template.instantiate(inp), timeout)
+}
+
+/// Like `run_template`, for a process the solver had to change after
+/// `ProcessTemplate::instantiate`, e.g. to give every run its own log file
+pub fn run_process(
+    process: Process,
+    timeout: Duration,
+) -> Result<(ChildOutcome, CapturedOutput), SolverError> {
+    let mut handle = process.spawn()?;
+    let outcome = match handle.finish(timeout) {
+        Ok(status) => match (status.code, status.signal) {
+            (Some(code), _) => ChildOutcome::Exited(code),
+            (None, Some(signal)) => ChildOutcome::Signaled {
+                signal,
+                core_dumped: status.core_dumped,
+            },
+            (None, None) => unreachable!("child reaped without exiting"),
+        },
+        // finish already killed it
+        Err(ref e) if *e.runner() == Runner::Timeout => ChildOutcome::TimedOut,
+        Err(e) => return Err(e),
+    };
+
+    // left empty for output that goes elsewhere, see "stdout_mode"
+    let mut stdout = Vec::new();
+    if handle.captures_stdout() {
+        handle.read_stdout(&mut stdout)?;
+    }
+    let mut stderr = Vec::new();
+    if handle.captures_stderr() {
+        handle.read_stderr(&mut stderr)?;
+    }
+    Ok((
+        outcome,
+        CapturedOutput {
+            stdout,
+            stderr,
+            stdout_truncated: handle.stdout_truncated(),
+            runtime: handle.runtime(),
+        },
+    ))
+}
+
+/// How many candidates of a round run in parallel: one per CPU, unless
+/// the "max_parallel" var asks for fewer, e.g. to leave the machine usable
+pub fn worker_count(vars: &HashMap<String, String>) -> Result<usize, SolverError> {
+    match vars.get("max_parallel") {
+        Some(n) => n.parse::<usize>().map(|n| n.max(1)).map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad number of parallel runs {}", n),
+            )
+        }),
+        None => Ok(num_cpus::get()),
+    }
+}
+
+// count of an input that carries no signal (e.g. all filler), from the
+// "reference" var
+pub(crate) fn reference(vars: &HashMap<String, String>) -> Result<Option<i64>, SolverError> {
+    match vars.get("reference") {
+        Some(reference) => reference.parse().map(Some).map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad reference count {}", reference),
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+// how far a winner has to stand out from the "reference" count, 0 if
+// the "reference_margin" var isn't set
+pub(crate) fn reference_margin(vars: &HashMap<String, String>) -> Result<i64, SolverError> {
+    match vars.get("reference_margin") {
+        Some(margin) => margin.parse().map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad reference margin {}", margin),
+            )
+        }),
+        None => Ok(0),
+    }
+}
+
+// candidates this close to the winner are reported as tied with it, 0
+// if the "tie_threshold" var isn't set
+pub(crate) fn tie_threshold(vars: &HashMap<String, String>) -> Result<i64, SolverError> {
+    match vars.get("tie_threshold") {
+        Some(threshold) => threshold.parse::<u32>().map(i64::from).map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad tie threshold {}", threshold),
+            )
+        }),
+        None => Ok(0),
+    }
+}
+
+// how often a phase may backtrack, 0 if the "max_backtrack" var isn't set
+pub(crate) fn max_backtrack(vars: &HashMap<String, String>) -> Result<u32, SolverError> {
+    match vars.get("max_backtrack") {
+        Some(max) => max.parse().map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad number of backtracks {}", max),
+            )
+        }),
+        None => Ok(0),
+    }
+}
+
+/// Measures a single input. Implementations can live outside this
+/// crate and only need the public API.
+///
+/// The candidates of a round are measured in parallel by a pool of
+/// workers (see `B7Opts::set_max_parallel`), all calling
+/// `get_inst_count` on the same solver through `&self`. That is why a
+/// solver has to be `Send + Sync`: state changed while measuring needs
+/// a Mutex or atomics, and each call should spawn its own child.
+///
+/// ```
+/// use b7::brute::{run_target, ChildOutcome, InstCountData, InstCounter};
+/// use b7::errors::{Runner, SolverError};
+/// use b7::generators::Input;
+///
+/// // scores an input by how much the target prints
+/// struct OutputLen;
+///
+/// impl InstCounter for OutputLen {
+///     fn name(&self) -> &str {
+///         "output-len"
+///     }
+///
+///     fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+///         match run_target(data)? {
+///             (ChildOutcome::TimedOut, _) => Err(SolverError::new(Runner::Timeout, "timed out")),
+///             (_, output) => Ok(output.stdout.len() as i64),
+///         }
+///     }
+/// }
+///
+/// let data = InstCountData::builder("./target")
+///     .input(Input::builder().arg("-v").stdin("hunter2\n").build())
+///     .var("pin_cpu", "")
+///     .build();
+/// assert_eq!(&*data.inp.stdin, b"hunter2\n");
+/// assert_eq!(OutputLen.name(), "output-len");
+/// ```
 pub trait InstCounter: Send + Sync + 'static {
     fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError>;
+
+    // called once before any runs with the target and vars of the
+    // session, to do work shared by every run
+    fn setup(&mut self, _path: &str, _vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        Ok(())
+    }
+
+    // name shown to the user, e.g. by --dry-run
+    fn name(&self) -> &str {
+        "custom"
+    }
+
+    /// The template `get_inst_count` instantiates for every input, once
+    /// `setup` made it. Dry runs show its command lines instead of the
+    /// plain target's
+    fn template(&self) -> Option<&ProcessTemplate> {
+        None
+    }
+
+    /// What `setup` fell back to measuring, if the solver couldn't
+    /// measure the usual way (e.g. "task-clock" for perf)
+    fn fallback(&self) -> Option<String> {
+        None
+    }
+
+    // called when a brute phase (argc, stdin length, ...) starts
+    fn start_phase(&self) {}
+}
+
+/// What a plain (uninstrumented) run of the target produced
+#[derive(Clone, Debug)]
+pub struct Output {
+    pub exit_code: Option<i32>,
+    /// Signal the target was killed by, e.g. SIGSEGV when it crashed
+    pub signal: Option<Signal>,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+/// Decision of a success detector about an input
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Verdict {
+    Success,
+    Failure,
+}
+
+/// Decides from a run's output whether an input solved the target.
+/// Returning None means the output doesn't say either way
+pub type Detector = Box<dyn Fn(&Output) -> Option<Verdict> + Send + Sync>;
+
+/// Everything about a B7 run that stays the same from phase to phase
+pub struct Session<'a> {
+    pub path: &'a str,
+    pub counter: &'a InstCounter,
+    pub timeout: Duration,
+    pub vars: HashMap<String, String>,
+    pub detector: Option<&'a Detector>,
+    // counts measured so far, shared by every phase
+    pub memo: &'a Memo,
+    pub cancel: CancelToken,
+}
+
+// run the input without instrumentation and ask the detector about it
+fn detect(
+    session: &Session,
+    detector: &Detector,
+    inp: &Input,
+) -> Result<Option<Verdict>, SolverError> {
+    let mut template = ProcessTemplate::new(session.path);
+    template.apply_vars(&session.vars)?;
+    // the detector looks at the output even if the counted runs don't
+    template.stdout_mode(OutputMode::Piped);
+    template.stderr_mode(OutputMode::Piped);
+    let (outcome, captured) = run(&template, inp, session.timeout)?;
+    let (exit_code, signal) = match outcome {
+        ChildOutcome::Exited(code) => (Some(code), None),
+        ChildOutcome::Signaled { signal, .. } => (None, Some(signal)),
+        ChildOutcome::TimedOut => {
+            return Err(SolverError::new(Runner::Timeout, "child timed out"));
+        }
+    };
+
+    let output = Output {
+        exit_code,
+        signal,
+        stdout: captured.stdout,
+        stderr: captured.stderr,
+    };
+    Ok(detector(&output))
+}
+
+// Trace the winner and the candidate whose count came closest to it,
+// and report where their syscall sequences diverge
+#[cfg(target_arch = "x86_64")]
+fn diff_syscalls<I: Debug + PartialEq>(
+    session: &Session,
+    round: u32,
+    results: &[(I, i64)],
+    inputs: &[(I, Input)],
+    winner: &(I, i64),
+) -> Result<(), SolverError> {
+    let runner_up = results
+        .iter()
+        .filter(|r| r.0 != winner.0)
+        .min_by_key(|r| (r.1 - winner.1).abs());
+    let runner_up = match runner_up {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    let find = |id: &I| inputs.iter().find(|i| i.0 == *id).map(|i| &i.1);
+    let (win_inp, run_inp) = match (find(&winner.0), find(&runner_up.0)) {
+        (Some(w), Some(r)) => (w, r),
+        _ => return Ok(()),
+    };
+
+    let win_trace = syscalls::record(session.path, &session.vars, win_inp, session.timeout)?;
+    let run_trace = syscalls::record(session.path, &session.vars, run_inp, session.timeout)?;
+    match syscalls::diverge(&win_trace, &run_trace) {
+        Some(div) => info!(
+            "round {}: {:?} vs {:?}: {}",
+            round, winner.0, runner_up.0, div
+        ),
+        None => info!(
+            "round {}: {:?} and {:?} made the same syscalls",
+            round, winner.0, runner_up.0
+        ),
+    }
+    Ok(())
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn diff_syscalls<I: Debug + PartialEq>(
+    _session: &Session,
+    _round: u32,
+    _results: &[(I, i64)],
+    _inputs: &[(I, Input)],
+    _winner: &(I, i64),
+) -> Result<(), SolverError> {
+    warn!("Syscall tracing is only supported on x86_64");
+    Ok(())
+}
+
+// The candidate of a decided round whose count came closest to the
+// winning `count`, leaving out those already picked
+fn runner_up<I: Clone + PartialEq>(results: &[(I, i64)], count: i64, tried: &[I]) -> Option<I> {
+    results
+        .iter()
+        .filter(|r| !tried.contains(&r.0))
+        .min_by_key(|r| (r.1 - count).abs())
+        .map(|r| r.0.clone())
+}
+
+// How long the `rounds_left` rounds of `candidates` each should take,
+// at the pace of the phase so far. None if nothing ran yet to go by
+fn eta(
+    stats: &PhaseStats,
+    rounds_left: Option<u32>,
+    candidates: usize,
+    repeat: u32,
+    workers: usize,
+) -> Option<Duration> {
+    if stats.runs == 0 {
+        return None;
+    }
+    let runs = u64::from(rounds_left?) * candidates as u64 * u64::from(repeat.max(1));
+    Some(statistics::estimate_duration(
+        runs,
+        stats.mean_latency(),
+        workers,
+    ))
 }
 
 // can take out Debug trait later
 // Combines the generators with the instruction counters to deduce the next step
 pub fn brute<
     G: Generate<I> + Display,
-    I: 'static + std::fmt::Display + Clone + Debug + Send + Ord,
+    I: 'static + std::fmt::Display + Copy + Into<u64> + Debug + Send + Ord,
     B: b7tui::Ui,
 >(
-    path: &str,
+    session: &Session,
     repeat: u32,
     gen: &mut G,
-    counter: &InstCounter,
     terminal: &mut B,
-    timeout: Duration,
-    vars: HashMap<String, String>,
-) -> Result<(), SolverError> {
-    let n_workers = num_cpus::get();
+) -> Result<PhaseStats, SolverError> {
+    let n_workers = worker_count(&session.vars)?;
+    let start = Instant::now();
+    let mut stats = PhaseStats::default();
+    session.counter.start_phase();
+
+    // Optional count of an input that carries no signal (e.g. all filler).
+    // Winners that don't stand out from it by the margin are flagged
+    let reference = reference(&session.vars)?;
+    let margin = reference_margin(&session.vars)?;
+    let tie_threshold = tie_threshold(&session.vars)?;
+    // Opt in, a round where nothing stands out makes the round before
+    // it pick its next best candidate, at most this often per phase
+    let mut backtracks_left = max_backtrack(&session.vars)?;
+    // results, winning count and the candidates picked so far of every
+    // decided round, kept for backtracking
+    let mut decided: Vec<(Vec<(I, i64)>, i64, Vec<I>)> = Vec::new();
+    let mut round: u32 = 0;
+    // opt in, every round runs two more candidates under PTRACE_SYSCALL
+    let syscall_diff = session.vars.contains_key("syscall_diff");
 
     let pool = Pool::new(n_workers);
 
@@ -49,6 +531,8 @@ pub fn brute<
 
         let mut num_jobs: i64 = 0;
         let mut results: Vec<(I, i64)> = Vec::new();
+        // per run wall clock time of the candidates that ran this round
+        let mut latencies: Vec<(I, Duration)> = Vec::new();
 
         let (tx, rx) = channel();
 
@@ -59,18 +543,75 @@ pub fn brute<
             data.push(inp_pair);
         }
 
-        let counter = Arc::new(counter);
+        // the detector and syscall diffing need the inputs after the round
+        let inputs: Vec<(I, Input)> = if session.detector.is_some() || syscall_diff {
+            data.clone()
+        } else {
+            Vec::new()
+        };
+
+        let counter = Arc::new(session.counter);
+        let timeout = session.timeout;
+        // Counts taken after a fallback measure something else, so the
+        // fallback is part of the solver name in the memo key.
+        let solver = match session.counter.fallback() {
+            Some(fallback) => format!("{} ({})", session.counter.name(), fallback),
+            None => session.counter.name().to_string(),
+        };
+        let dry_run = session.vars.contains_key("dry_run");
+        let mut num_cached: u64 = 0;
+        let mut num_dry: u64 = 0;
+        logging::update(|c| c.round = Some(round));
+        let ctx = logging::context();
 
         pool.scoped(|scope| {
             for inp_pair in data {
+                if session.cancel.is_cancelled() {
+                    break;
+                }
                 num_jobs += 1;
                 let tx = tx.clone();
-                let test = String::from(path);
+                // every candidate scores the same, nothing is run
+                if dry_run {
+                    let count =
+                        command_line(session.counter, session, &inp_pair.1).map(|command| {
+                            info!("Would run {}", command);
+                            stats.commands.push(command);
+                            0
+                        });
+                    num_dry += 1;
+                    let _ = tx.send((inp_pair.0, count, None));
+                    continue;
+                }
+                // The vars, like no_aslr or cmp_addr, change the counts, so
+                // they are part of the memo key too.
+                let key = session.memo.key(&solver, &session.vars, &inp_pair.1);
+                if let Some(count) = session.memo.get(key) {
+                    num_cached += 1;
+                    let _ = tx.send((inp_pair.0, Ok(count), None));
+                    continue;
+                }
+                let test = String::from(session.path);
                 // give it to a thread to handle
-                let vars = vars.clone();
+                let vars = session.vars.clone();
                 let counter = counter.clone();
+                let cancel = session.cancel.clone();
+                let ctx = logging::LogContext {
+                    candidate: Some(inp_pair.0.to_string()),
+                    ..ctx.clone()
+                };
 
                 scope.execute(move || {
+                    logging::set_context(ctx);
+                    if cancel.is_cancelled() {
+                        let _ = tx.send((
+                            inp_pair.0,
+                            Err(SolverError::new(Runner::Aborted, "cancelled")),
+                            None,
+                        ));
+                        return;
+                    }
+                    cancel::set_current(Some(cancel));
                     let inp = inp_pair.1;
                     let data = InstCountData {
                         path: test,
@@ -78,13 +619,18 @@ pub fn brute<
                         vars,
                         timeout,
                     };
+                    // wall clock time of all the repeats of this candidate
+                    let ran = Instant::now();
                     let mut inst_count = counter.get_inst_count(&data);
                     trace!("inst_count: {:?}", inst_count);
                     for _ in 1..repeat {
                         inst_count = counter.get_inst_count(&data);
                         trace!("inst_count: {:?}", inst_count);
                     }
-                    let _ = tx.send((inp_pair.0, inst_count));
+                    if let Ok(count) = inst_count {
+                        session.memo.insert(key, count);
+                    }
+                    let _ = tx.send((inp_pair.0, inst_count, Some(ran.elapsed())));
                 });
             }
         });
@@ -92,8 +638,14 @@ pub fn brute<
         let mut min: u64 = std::i64::MAX as u64;
         // Get results from the threads
 
+        stats.memo_hits += num_cached;
+        let mut num_aborted: u64 = 0;
         for _ in 0..num_jobs {
             let tmp = rx.recv().unwrap();
+            if let Some(time) = tmp.2 {
+                stats.add_run_time(&tmp.0.to_string(), time, repeat);
+                latencies.push((tmp.0, time / repeat.max(1)));
+            }
             match tmp.1 {
                 Ok(x) => {
                     if (x as u64) < min {
@@ -101,24 +653,149 @@ pub fn brute<
                     }
                     results.push((tmp.0, x));
                 }
+                // runs cut short by cancellation say nothing about the input
+                Err(ref x) if *x.runner() == Runner::Aborted => {
+                    num_aborted += 1;
+                    continue;
+                }
                 Err(x) => {
+                    stats.errors += 1;
+                    match x.runner() {
+                        Runner::Timeout => stats.timeouts += 1,
+                        Runner::Crashed => stats.crashes += 1,
+                        _ => {}
+                    }
                     warn!("{:?} \n returned: {:?}", tmp.0, x);
                     continue;
                 }
             }
         }
+        // cancelled jobs don't count as runs, whether or not they started
+        let num_run = num_jobs as u64 - num_cached - num_dry - num_aborted;
+        stats.runs += num_run * u64::from(repeat.max(1));
+        // a cancelled round is incomplete, so its results are dropped
+        // and the generator keeps what the earlier rounds solved
+        if session.cancel.is_cancelled() {
+            info!("Cancelled in round {}", round);
+            stats.cancelled = true;
+            stats.elapsed = start.elapsed();
+            break Ok(stats);
+        }
         results.sort();
-        terminal.update(&results, min);
-
-        terminal.wait();
+        stats
+            .rounds
+            .push(results.iter().map(|r| (r.0.into(), r.1)).collect());
+        // the interface can ask to stop, e.g. when the user quits. That
+        // works like a cancel, so the run ends with what was solved
+        latencies.sort();
+        stats.elapsed = start.elapsed();
+        terminal.eta(eta(
+            &stats,
+            gen.rounds_left(),
+            results.len(),
+            repeat,
+            n_workers,
+        ));
+        terminal.timing(stats.runs_per_sec(), &latencies);
+        if !terminal.update(&results, min) || !terminal.wait() {
+            info!("Stopped by the interface in round {}", round);
+            session.cancel.cancel();
+            stats.cancelled = true;
+            stats.elapsed = start.elapsed();
+            break Ok(stats);
+        }
 
-        // inform generator of the result
+        // inform generator of the result, if there is one to pick
         if results.is_empty() {
-            warn!("Results empty {:?}", results);
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                &format!(
+                    "every candidate in round {} failed, see the warnings above",
+                    round
+                ),
+            ));
         }
         let good_idx = statistics::find_outlier(results.as_slice());
+        if backtracks_left > 0 && !dry_run && statistics::is_flat(&results, tie_threshold) {
+            if let Some(prev) = decided.last_mut() {
+                if let Some(alt) = runner_up(&prev.0, prev.1, &prev.2) {
+                    if gen.retract() {
+                        let prev_round = round - 1;
+                        warn!(
+                            "Nothing stands out in round {}, trying {} for round {} instead",
+                            round, alt, prev_round
+                        );
+                        backtracks_left -= 1;
+                        stats.backtracks.push((prev_round, alt.into()));
+                        // this round runs again with the new pick
+                        stats.rounds.pop();
+                        prev.2.push(alt);
+                        if let Some(pick) = stats.picks.last_mut() {
+                            *pick = alt.into();
+                        }
+                        gen.update(&alt);
+                        continue;
+                    }
+                }
+            }
+        }
+        stats.picks.push(good_idx.0.into());
+        if let Some(reference) = reference {
+            if statistics::is_uncertain(good_idx.1, reference, margin) {
+                warn!(
+                    "{:?} is within {} of the reference count, round {} is uncertain",
+                    good_idx.0, margin, round
+                );
+                stats.uncertain.push(round);
+            }
+        }
+        let tied = statistics::tied_with(&results, good_idx, tie_threshold);
+        if !tied.is_empty() {
+            warn!(
+                "{:?} is tied with {:?} in round {}, the pick may be wrong",
+                good_idx, tied, round
+            );
+            stats.ties.push(Tie {
+                round,
+                candidates: std::iter::once(good_idx)
+                    .chain(tied)
+                    .map(|r| (r.0.into(), r.1))
+                    .collect(),
+            });
+        }
+        // a dry run runs nothing, not even these
+        if syscall_diff && !dry_run {
+            diff_syscalls(session, round, &results, &inputs, good_idx)?;
+        }
+        round += 1;
+
+        if let Some(detector) = session.detector.filter(|_| !dry_run) {
+            if let Some(inp) = inputs.iter().find(|i| i.0 == good_idx.0) {
+                match detect(session, detector, &inp.1)? {
+                    Some(Verdict::Success) => {
+                        info!("Success detected for {:?}, stopping", good_idx.0);
+                        stats.success = Some(inp.1.clone());
+                        stats.elapsed = start.elapsed();
+                        break Ok(stats);
+                    }
+                    Some(Verdict::Failure) => debug!("{:?} was rejected", good_idx.0),
+                    None => {}
+                }
+            }
+        }
+
+        // the first round shows the command lines, the later ones only
+        // differ in the picks, which a dry run can't make
+        if dry_run {
+            stats.elapsed = start.elapsed();
+            break Ok(stats);
+        }
+        if backtracks_left > 0 {
+            decided.push((results.clone(), good_idx.1, vec![good_idx.0]));
+        }
         if !gen.update(&good_idx.0) {
-            break Ok(());
+            stats.elapsed = start.elapsed();
+            break Ok(stats);
         }
     }
 }
@@ -0,0 +1,39 @@
+use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Stops a running brute from another thread, see `B7Opts::cancel_token`.
+/// Clones share the same flag
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+thread_local! {
+    static CURRENT: RefCell<Option<CancelToken>> = RefCell::new(None);
+}
+
+/// Make `token` the one checked by `ProcessHandle::finish` on this
+/// thread. The brute loop sets it on every worker before running a job
+pub fn set_current(token: Option<CancelToken>) {
+    CURRENT.with(|c| *c.borrow_mut() = token);
+}
+
+// whether the token of this thread, if any, was cancelled
+pub fn cancelled() -> bool {
+    CURRENT.with(|c| c.borrow().as_ref().map_or(false, |t| t.is_cancelled()))
+}
@@ -0,0 +1,161 @@
+use crate::b7tui;
+use crate::brute::InstCounter;
+use crate::errors::*;
+use crate::{solver_by_name, B7Opts};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Version stamped into everything B7 writes to disk. Bump it when a
+/// field changes meaning, so old files are rejected instead of misread
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Wraps saved data with the format version it was written with
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Versioned<T> {
+    pub version: u32,
+    pub data: T,
+}
+
+impl<T: Serialize + DeserializeOwned> Versioned<T> {
+    pub fn to_json(data: T) -> Result<String, SolverError> {
+        let versioned = Versioned {
+            version: FORMAT_VERSION,
+            data,
+        };
+        Ok(serde_json::to_string_pretty(&versioned)?)
+    }
+
+    pub fn from_json(json: &str) -> Result<T, SolverError> {
+        Versioned::check(serde_json::from_str(json)?)
+    }
+
+    /// Like `from_json`, for configs written by hand
+    pub fn from_toml(toml: &str) -> Result<T, SolverError> {
+        Versioned::check(toml::from_str(toml)?)
+    }
+
+    fn check(versioned: Versioned<T>) -> Result<T, SolverError> {
+        if versioned.version > FORMAT_VERSION {
+            return Err(SolverError::new(
+                Runner::ParseError,
+                &format!(
+                    "written by a newer B7 (format {}, this one reads up to {})",
+                    versioned.version, FORMAT_VERSION
+                ),
+            ));
+        }
+        Ok(versioned.data)
+    }
+}
+
+/// Everything B7Opts is configured with, minus the live parts: the
+/// interface isn't saved at all, and the solver is saved by name and
+/// rebuilt from it. Missing fields take their defaults
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields, default)]
+pub struct OptsConfig {
+    pub path: String,
+    pub solver: String,
+    pub argstate: bool,
+    pub stdinstate: bool,
+    pub filestate: bool,
+    pub vars: HashMap<String, String>,
+    pub timeout: Duration,
+    pub min_len: u32,
+    pub max_len: Option<u32>,
+    pub charset: (u16, u16),
+    pub heatmap: Option<PathBuf>,
+    pub memo_file: Option<PathBuf>,
+    pub strip_trailing_newline: bool,
+    // see B7Opts::set_output_path
+    pub output_path: Option<PathBuf>,
+}
+
+impl Default for OptsConfig {
+    fn default() -> Self {
+        OptsConfig {
+            path: String::new(),
+            solver: String::from("perf"),
+            argstate: true,
+            stdinstate: true,
+            filestate: false,
+            vars: HashMap::new(),
+            timeout: Duration::new(5, 0),
+            min_len: 0,
+            max_len: None,
+            charset: (crate::CHAR_MIN, crate::CHAR_MAX),
+            heatmap: None,
+            memo_file: None,
+            strip_trailing_newline: false,
+            output_path: None,
+        }
+    }
+}
+
+impl OptsConfig {
+    // the success detector can't be saved, set it again if needed
+    pub fn into_opts<B: b7tui::Ui>(self, terminal: &mut B) -> Result<B7Opts<B>, SolverError> {
+        let solver = match solver_by_name(&self.solver) {
+            Some(solver) => solver,
+            None => {
+                return Err(SolverError::new(
+                    Runner::MissingArgs,
+                    &format!("unknown solver {}", self.solver),
+                ))
+            }
+        };
+        Ok(self.into_opts_with(solver, terminal))
+    }
+
+    /// Like `into_opts`, but with a solver that isn't built in. The
+    /// solver name of the config is ignored
+    pub fn into_opts_with<B: b7tui::Ui>(
+        self,
+        solver: Box<InstCounter>,
+        terminal: &mut B,
+    ) -> B7Opts<B> {
+        let mut opts = B7Opts::new(
+            self.path,
+            self.argstate,
+            self.stdinstate,
+            solver,
+            terminal,
+            self.vars,
+            self.timeout,
+        );
+        opts.set_min_len(self.min_len)
+            .set_max_len(self.max_len)
+            .set_charset(self.charset.0, self.charset.1)
+            .set_heatmap(self.heatmap)
+            .set_memo_file(self.memo_file)
+            .set_strip_trailing_newline(self.strip_trailing_newline)
+            .set_output_path(self.output_path)
+            .set_file_brute(self.filestate);
+        opts
+    }
+}
+
+impl<'a, B: b7tui::Ui> B7Opts<'a, B> {
+    pub fn config(&self) -> OptsConfig {
+        OptsConfig {
+            path: self.path.clone(),
+            solver: self.solver.name().to_string(),
+            argstate: self.argstate,
+            stdinstate: self.stdinstate,
+            filestate: self.filestate,
+            vars: self.vars.clone(),
+            timeout: self.timeout,
+            min_len: self.min_len,
+            max_len: self.max_len,
+            charset: self.charset,
+            heatmap: self.heatmap.clone(),
+            memo_file: self.memo_file.clone(),
+            strip_trailing_newline: self.strip_trailing_newline,
+            output_path: self.output_path.clone(),
+        }
+    }
+}
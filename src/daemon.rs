@@ -0,0 +1,378 @@
+//! `b7 daemon`: runs brute jobs submitted over a unix socket.
+//!
+//! Every message is a little endian u32 length followed by that many
+//! bytes of JSON. A client sends one job, a versioned OptsConfig (see
+//! `config::Versioned`), and if it has a memo file the raw contents of
+//! that file, empty if there is none yet. It then receives `Response`s:
+//! a Progress for every round, a File for every output file the job
+//! wrote, then either Done with the results or Error. Closing the
+//! connection early cancels the job.
+//!
+//! The memo, heatmap and output paths of a job are the client's. The
+//! daemon writes them to the job's directory and sends them back under
+//! those paths, and `submit` writes them there.
+use crate::b7tui::{Progress, ProgressEvent};
+use crate::brute::InstCounter;
+use crate::cancel::CancelToken;
+use crate::config::{OptsConfig, Versioned};
+use crate::errors::*;
+use crate::process;
+use crate::{solver_by_name, B7Results};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+// refuse messages larger than this instead of allocating for them
+const MAX_MESSAGE: u32 = 64 * 1024 * 1024;
+
+/// Sent by the daemon while a job runs
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Progress(ProgressEvent),
+    // an output file of the job, at the path the client asked for
+    File { path: PathBuf, contents: Vec<u8> },
+    Done(B7Results),
+    Error { message: String },
+}
+
+fn write_frame<W: Write>(out: &mut W, bytes: &[u8]) -> Result<(), SolverError> {
+    out.write_all(&(bytes.len() as u32).to_le_bytes())?;
+    out.write_all(bytes)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn read_frame<R: Read>(input: &mut R) -> Result<Vec<u8>, SolverError> {
+    let mut len = [0; 4];
+    input.read_exact(&mut len)?;
+    let len = u32::from_le_bytes(len);
+    if len > MAX_MESSAGE {
+        return Err(SolverError::new(
+            Runner::ParseError,
+            &format!("message of {} bytes is too large", len),
+        ));
+    }
+    let mut bytes = vec![0; len as usize];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+pub fn write_message<W: Write, T: Serialize>(out: &mut W, msg: &T) -> Result<(), SolverError> {
+    write_frame(out, &serde_json::to_vec(msg)?)
+}
+
+pub fn read_message<R: Read, T: DeserializeOwned>(input: &mut R) -> Result<T, SolverError> {
+    Ok(serde_json::from_slice(&read_frame(input)?)?)
+}
+
+type SolverFactory = Box<dyn Fn(&OptsConfig) -> Option<Box<InstCounter>> + Send + Sync>;
+
+/// Limits how many jobs run at once
+struct Slots {
+    free: Mutex<usize>,
+    freed: Condvar,
+}
+
+impl Slots {
+    // the slot is released when the guard is dropped, also if the job
+    // panics
+    fn acquire(&self) -> SlotGuard {
+        let mut free = self.free.lock().unwrap();
+        while *free == 0 {
+            free = self.freed.wait(free).unwrap();
+        }
+        *free -= 1;
+        SlotGuard(self)
+    }
+
+    fn release(&self) {
+        *self.free.lock().unwrap() += 1;
+        self.freed.notify_one();
+    }
+}
+
+struct SlotGuard<'a>(&'a Slots);
+
+impl<'a> Drop for SlotGuard<'a> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+// Where the job in `dir` writes the client's output `file`, under a
+// fixed name since the client's path means nothing on this side
+struct JobFile {
+    client: PathBuf,
+    job: PathBuf,
+}
+
+impl JobFile {
+    fn new(dir: &Path, name: &str, client: PathBuf) -> JobFile {
+        JobFile {
+            client,
+            job: dir.join(name),
+        }
+    }
+
+    // every file the job wrote there, as (client path, contents). A
+    // directory, like the output path, is sent file by file
+    fn collect(&self) -> Result<Vec<(PathBuf, Vec<u8>)>, SolverError> {
+        if self.job.is_dir() {
+            let mut files = Vec::new();
+            for entry in fs::read_dir(&self.job)? {
+                let entry = entry?;
+                let contents = fs::read(entry.path())?;
+                files.push((self.client.join(entry.file_name()), contents));
+            }
+            Ok(files)
+        } else if self.job.exists() {
+            Ok(vec![(self.client.clone(), fs::read(&self.job)?)])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+pub struct Daemon {
+    workdir: PathBuf,
+    slots: Slots,
+    solvers: SolverFactory,
+    jobs: AtomicUsize,
+    max_children: Option<usize>,
+}
+
+impl Daemon {
+    /// Daemon running one job at a time, with a directory per job
+    /// created under `workdir`
+    pub fn new<P: Into<PathBuf>>(workdir: P) -> Daemon {
+        Daemon {
+            workdir: workdir.into(),
+            slots: Slots {
+                free: Mutex::new(1),
+                freed: Condvar::new(),
+            },
+            solvers: Box::new(|config| solver_by_name(&config.solver)),
+            jobs: AtomicUsize::new(0),
+            max_children: None,
+        }
+    }
+
+    pub fn set_concurrency(&mut self, jobs: usize) -> &mut Self {
+        *self.slots.free.get_mut().unwrap() = jobs.max(1);
+        self
+    }
+
+    /// Limit the children of all jobs together, see
+    /// `ProcessWaiter::set_max_children`. The limit is global, so the
+    /// "max_children" var of a job is ignored
+    pub fn set_max_children(&mut self, max: Option<usize>) -> &mut Self {
+        self.max_children = max;
+        self
+    }
+
+    /// Build solvers with `f` instead of by name, e.g. to offer solvers
+    /// that aren't built in
+    pub fn set_solvers<F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&OptsConfig) -> Option<Box<InstCounter>> + Send + Sync + 'static,
+    {
+        self.solvers = Box::new(f);
+        self
+    }
+
+    /// Accept connections until the listener fails, one thread each
+    pub fn serve(self, listener: UnixListener) -> Result<(), SolverError> {
+        process::block_signal();
+        process::WAITER.set_max_children(self.max_children);
+        fs::create_dir_all(&self.workdir)?;
+        let daemon = Arc::new(self);
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let daemon = daemon.clone();
+            thread::spawn(move || {
+                if let Err(e) = daemon.handle(stream) {
+                    warn!("Job failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Run the job sent over `stream`, see the module docs
+    pub fn handle(&self, mut stream: UnixStream) -> Result<(), SolverError> {
+        let json = String::from_utf8_lossy(&read_frame(&mut stream)?).into_owned();
+        let config = match Versioned::<OptsConfig>::from_json(&json) {
+            Ok(config) => config,
+            Err(e) => return self.fail(&mut stream, &e),
+        };
+        // so a job resumes from the counts of the client's last one
+        let memo = match config.memo_file {
+            Some(_) => Some(read_frame(&mut stream)?),
+            None => None,
+        };
+        let solver = match (self.solvers)(&config) {
+            Some(solver) => solver,
+            None => {
+                let e = SolverError::new(
+                    Runner::MissingArgs,
+                    &format!("unknown solver {}", config.solver),
+                );
+                return self.fail(&mut stream, &e);
+            }
+        };
+
+        let result = {
+            let _slot = self.slots.acquire();
+            self.run_job(&mut stream, config, memo, solver)
+        };
+        match result {
+            Ok(results) => write_message(&mut stream, &Response::Done(results)),
+            Err(e) => self.fail(&mut stream, &e),
+        }
+    }
+
+    fn fail(&self, stream: &mut UnixStream, e: &SolverError) -> Result<(), SolverError> {
+        write_message(
+            stream,
+            &Response::Error {
+                message: e.to_string(),
+            },
+        )
+    }
+
+    fn run_job(
+        &self,
+        stream: &mut UnixStream,
+        mut config: OptsConfig,
+        memo: Option<Vec<u8>>,
+        solver: Box<InstCounter>,
+    ) -> Result<B7Results, SolverError> {
+        let id = self.jobs.fetch_add(1, Ordering::SeqCst);
+        let dir = self.workdir.join(format!("job-{}", id));
+        fs::create_dir_all(&dir)?;
+        // output files are written in the job's directory, and sent
+        // back to the client once the job is done
+        let mut files = Vec::new();
+        if let Some(path) = config.memo_file.take() {
+            let file = JobFile::new(&dir, "memo", path);
+            // an empty memo is a client that has none yet
+            if let Some(memo) = memo.filter(|memo| !memo.is_empty()) {
+                fs::write(&file.job, memo)?;
+            }
+            config.memo_file = Some(file.job.clone());
+            files.push(file);
+        }
+        if let Some(path) = config.heatmap.take() {
+            let file = JobFile::new(&dir, "heatmap", path);
+            config.heatmap = Some(file.job.clone());
+            files.push(file);
+        }
+        if let Some(path) = config.output_path.take() {
+            let file = JobFile::new(&dir, "output", path);
+            config.output_path = Some(file.job.clone());
+            files.push(file);
+        }
+        // set for all jobs by set_max_children
+        config.vars.remove("max_children");
+        info!("Job {} started for {}", id, config.path);
+
+        // a client that goes away cancels its job. Nothing else is read
+        // from the connection, so EOF or an error means it was closed
+        let cancel = CancelToken::new();
+        {
+            let mut conn = stream.try_clone()?;
+            let cancel = cancel.clone();
+            thread::spawn(move || {
+                let mut buf = [0; 64];
+                while let Ok(n) = conn.read(&mut buf) {
+                    if n == 0 {
+                        break;
+                    }
+                }
+                cancel.cancel();
+            });
+        }
+
+        let results = {
+            let mut out = stream.try_clone()?;
+            let progress_cancel = cancel.clone();
+            let mut ui = Progress::new(|event| {
+                if write_message(&mut out, &Response::Progress(event)).is_err() {
+                    progress_cancel.cancel();
+                }
+                true
+            });
+            let mut opts = config.into_opts_with(solver, &mut ui);
+            opts.set_cancel_token(cancel.clone());
+            opts.run()
+        };
+
+        if cancel.is_cancelled() {
+            info!("Job {} cancelled by the client", id);
+        }
+        // also after an error, the memo is written either way
+        let sent = send_files(stream, &files);
+        let _ = fs::remove_dir_all(&dir);
+        sent?;
+        results
+    }
+}
+
+fn send_files(stream: &mut UnixStream, files: &[JobFile]) -> Result<(), SolverError> {
+    for file in files {
+        for (path, contents) in file.collect()? {
+            write_message(stream, &Response::File { path, contents })?;
+        }
+    }
+    Ok(())
+}
+
+/// Send `config` to the daemon at `socket` and wait for its results,
+/// calling `on_progress` for every round. The memo, heatmap and output
+/// files of `config` are written here, as if the job ran locally
+pub fn submit<F: FnMut(&ProgressEvent)>(
+    socket: &Path,
+    config: OptsConfig,
+    on_progress: F,
+) -> Result<B7Results, SolverError> {
+    submit_over(UnixStream::connect(socket)?, config, on_progress)
+}
+
+/// Like `submit`, over an already connected stream
+pub fn submit_over<F: FnMut(&ProgressEvent)>(
+    mut stream: UnixStream,
+    config: OptsConfig,
+    mut on_progress: F,
+) -> Result<B7Results, SolverError> {
+    let memo = match &config.memo_file {
+        Some(path) if path.exists() => Some(fs::read(path)?),
+        Some(_) => Some(Vec::new()),
+        None => None,
+    };
+    write_frame(&mut stream, Versioned::to_json(config)?.as_bytes())?;
+    if let Some(memo) = memo {
+        write_frame(&mut stream, &memo)?;
+    }
+    loop {
+        match read_message(&mut stream)? {
+            Response::Progress(event) => on_progress(&event),
+            Response::File { path, contents } => {
+                if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                    fs::create_dir_all(dir)?;
+                }
+                fs::write(&path, contents)?;
+            }
+            Response::Done(results) => return Ok(results),
+            Response::Error { message } => {
+                return Err(SolverError::new(Runner::RunnerError, &message))
+            }
+        }
+    }
+}
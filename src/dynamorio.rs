@@ -1,51 +1,286 @@
+use crate::binary::{Binary, Bits};
 use crate::brute::*;
 use crate::errors::*;
-use crate::process::Process;
-use std::ffi::OsStr;
-use std::os::unix::ffi::OsStrExt;
+use crate::process::{absolute_path, OutputMode, ProcessTemplate};
+use regex::{Captures, Regex};
+use std::collections::HashMap;
+use std::path::Path;
 
-#[derive(Copy, Clone)]
-pub struct DynamorioSolver;
+// bytes of stderr quoted when the count can't be parsed
+const STDERR_EXCERPT: usize = 300;
+// what libinscount prints, the last group is the count
+const INSCOUNT_REGEX: &str = "Instrumentation results: (\\d+) instructions executed";
 
-impl InstCounter for DynamorioSolver {
-    // Handles basic proc spawning and running under dino
-    // only works on 64 bit for now
-    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
-        let dynpath = data.vars.get("dynpath").unwrap();
-        let drrun = format!("{}/bin64/drrun", dynpath);
-        let libinscount = format!("{}/api/bin/libinscount.so", dynpath);
-        let mut proccess = Process::new(&drrun);
-        proccess.arg("-c");
-        proccess.arg(libinscount);
-        proccess.arg("--");
-        proccess.arg(&data.path);
-        for arg in data.inp.argv.iter() {
-            proccess.arg(OsStr::from_bytes(arg));
+#[derive(Clone, Default)]
+pub struct DynamorioSolver {
+    template: Option<ProcessTemplate>,
+    regex: Option<Regex>,
+}
+
+impl DynamorioSolver {
+    pub fn new() -> DynamorioSolver {
+        DynamorioSolver {
+            template: None,
+            regex: None,
+        }
+    }
+}
+
+// The "drregex" var, or libinscount's output. Its last capture group
+// has to match the count
+fn count_regex(vars: &HashMap<String, String>) -> Result<Regex, SolverError> {
+    let pattern = vars
+        .get("drregex")
+        .map_or(INSCOUNT_REGEX, |pattern| pattern.as_str());
+    let re = Regex::new(pattern).map_err(|e| {
+        SolverError::new(
+            Runner::MissingArgs,
+            &format!("bad drregex {}: {}", pattern, e),
+        )
+    })?;
+    if re.captures_len() < 2 {
+        return Err(SolverError::new(
+            Runner::MissingArgs,
+            &format!("drregex {} has no group to capture the count", pattern),
+        ));
+    }
+    Ok(re)
+}
+
+// drrun and the inscount client matching the word size of the target.
+// Release packages keep the client in samples/bin32 or samples/bin64,
+// older source builds in api/bin
+fn client_paths(dynpath: &str, bits: Bits) -> (String, String) {
+    let dir = match bits {
+        Bits::Bits32 => "bin32",
+        Bits::Bits64 => "bin64",
+    };
+    let drrun = format!("{}/{}/drrun", dynpath, dir);
+    let libinscount = format!("{}/samples/{}/libinscount.so", dynpath, dir);
+    let legacy = format!("{}/api/bin/libinscount.so", dynpath);
+    if !Path::new(&libinscount).exists() && Path::new(&legacy).exists() {
+        return (drrun, legacy);
+    }
+    (drrun, libinscount)
+}
+
+// drrun's command line is the same for every run, only the
+// target's own argv and stdin change
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let dynpath = match vars.get("dynpath") {
+        Some(x) => x,
+        None => {
+            return Err(SolverError::new(
+                Runner::MissingArgs,
+                "dynamorio solver needs dynpath",
+            ))
         }
-        proccess.input(data.inp.stdin.clone());
+    };
+    let (drrun, libinscount) = client_paths(dynpath, Binary::new(path).bits()?);
+    // a client of the user's own, e.g. one counting basic blocks
+    let client = vars.get("drclient").unwrap_or(&libinscount);
+    let mut template = ProcessTemplate::new(&drrun);
+    // the child may run in the "cwd" var's directory
+    template.arg("-c");
+    template.arg(absolute_path(client));
+    template.arg("--");
+    template.arg(absolute_path(path));
+    // a "wrap" command goes in front of drrun, so it isn't counted and
+    // whatever it sets up is inherited by the target
+    template.apply_vars(vars)?;
+    // the count is parsed from stdout, and stderr says why it's missing
+    template.stdout_mode(OutputMode::Piped);
+    template.stderr_mode(OutputMode::Piped);
+    Ok(template)
+}
 
-        let mut handle = proccess.spawn();
-        handle.finish(data.timeout)?;
+// the start of drrun's stderr, which says why no count was printed,
+// e.g. a missing libinscount.so
+pub(crate) fn stderr_excerpt(stderr: &[u8]) -> String {
+    let excerpt = String::from_utf8_lossy(&stderr[..stderr.len().min(STDERR_EXCERPT)]);
+    let excerpt = excerpt.trim();
+    if excerpt.is_empty() {
+        String::from("(empty)")
+    } else if stderr.len() > STDERR_EXCERPT {
+        format!("{}...", excerpt)
+    } else {
+        excerpt.to_string()
+    }
+}
 
-        let mut buf: Vec<u8> = Vec::new();
-        handle.read_stdout(&mut buf)?;
+impl InstCounter for DynamorioSolver {
+    fn name(&self) -> &str {
+        "dynamorio"
+    }
 
-        let stdout = String::from_utf8_lossy(buf.as_slice());
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.template.as_ref()
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.template = Some(make_template(path, vars)?);
+        self.regex = Some(count_regex(vars)?);
+        Ok(())
+    }
 
-        let re =
-            regex::Regex::new("Instrumentation results: (\\d+) instructions executed").unwrap();
+    // Handles basic proc spawning and running under dino
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let template = template_for(self.template.as_ref(), data, make_template)?;
+        let fallback_re;
+        let re = match &self.regex {
+            Some(re) => re,
+            None => {
+                fallback_re = count_regex(&data.vars)?;
+                &fallback_re
+            }
+        };
+
+        let output = match run_template(template, data)? {
+            (ChildOutcome::TimedOut, _) => {
+                return Err(SolverError::new(Runner::Timeout, "child timed out"));
+            }
+            // drrun passes on how the target ended, and a crash can
+            // still have printed the count of the code that ran
+            (
+                ChildOutcome::Signaled {
+                    signal,
+                    core_dumped,
+                },
+                output,
+            ) => {
+                debug!(
+                    "Target killed by {:?} (core dumped: {}) on {:?}",
+                    signal, core_dumped, data.inp
+                );
+                output
+            }
+            (_, output) => output,
+        };
+
+        // the count is printed last, so it is gone with the cut off part
+        if output.stdout_truncated {
+            return Err(SolverError::new(
+                Runner::IoError,
+                "target printed more than the max_output var allows, its count was cut off",
+            ));
+        }
+        let stdout = String::from_utf8_lossy(output.stdout.as_slice());
         let caps = match re.captures(&stdout) {
             Some(x) => x,
             None => {
                 return Err(SolverError::new(
                     Runner::IoError,
-                    "Could not parse dynamorio Instruction count",
+                    &format!(
+                        "Could not parse dynamorio Instruction count, stderr: {}",
+                        stderr_excerpt(&output.stderr)
+                    ),
                 ));
             }
         };
-        let cap = &caps[caps.len() - 1];
-        let num2: i64 = cap.parse().unwrap();
+        parse_count(&caps)
+    }
+}
+
+// the count is the last group of the regex, which can be an optional
+// one in a user's drregex that didn't take part in the match
+fn parse_count(caps: &Captures) -> Result<i64, SolverError> {
+    match caps.get(caps.len() - 1) {
+        Some(cap) => Ok(cap.as_str().parse()?),
+        None => Err(SolverError::new(
+            Runner::ParseError,
+            "the count group of the dynamorio regex did not match",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        client_paths, count_regex, make_template, parse_count, stderr_excerpt, STDERR_EXCERPT,
+    };
+    use crate::binary::Binary;
+    use crate::errors::Runner;
+    use std::collections::HashMap;
+
+    fn paths_for(sample: &str) -> (String, String) {
+        let path = format!("{}/tests/{}", env!("CARGO_MANIFEST_DIR"), sample);
+        client_paths("/opt/dr", Binary::new(&path).bits().unwrap())
+    }
+
+    // relative paths still name the same files with another cwd
+    #[test]
+    fn relative_target() {
+        let mut vars = HashMap::new();
+        vars.insert(String::from("dynpath"), String::from("/opt/dr"));
+        vars.insert(String::from("drclient"), String::from("libcount.so"));
+        vars.insert(String::from("cwd"), String::from("/"));
+        let template = make_template("tests/strlen_test_x64", &vars).unwrap();
+        let cwd = std::env::current_dir().unwrap();
+        let shown = format!("{:?}", template);
+        assert!(shown.contains(&*cwd.join("tests/strlen_test_x64").to_string_lossy()));
+        assert!(shown.contains(&*cwd.join("libcount.so").to_string_lossy()));
+    }
+
+    #[test]
+    fn drrun_for_target() {
+        assert_eq!(
+            paths_for("strlen_test_x86"),
+            (
+                String::from("/opt/dr/bin32/drrun"),
+                String::from("/opt/dr/samples/bin32/libinscount.so")
+            )
+        );
+        assert_eq!(
+            paths_for("strlen_test_x64"),
+            (
+                String::from("/opt/dr/bin64/drrun"),
+                String::from("/opt/dr/samples/bin64/libinscount.so")
+            )
+        );
+    }
+
+    #[test]
+    fn custom_regex() {
+        let mut vars = HashMap::new();
+        let re = count_regex(&vars).unwrap();
+        let caps = re
+            .captures("Instrumentation results: 1234 instructions executed")
+            .unwrap();
+        assert_eq!(&caps[caps.len() - 1], "1234");
+
+        vars.insert(String::from("drregex"), String::from("(\\d+) basic blocks"));
+        let re = count_regex(&vars).unwrap();
+        let caps = re.captures("ran 56 basic blocks").unwrap();
+        assert_eq!(&caps[caps.len() - 1], "56");
+
+        // an optional count that isn't there is an error, not a panic
+        vars.insert(String::from("drregex"), String::from("(\\d+)?$"));
+        let re = count_regex(&vars).unwrap();
+        let caps = re.captures("no count here").unwrap();
+        assert_eq!(
+            *parse_count(&caps).unwrap_err().runner(),
+            Runner::ParseError
+        );
+        let caps = re.captures("ran 78").unwrap();
+        assert_eq!(parse_count(&caps).unwrap(), 78);
+
+        vars.insert(String::from("drregex"), String::from("\\d+ basic blocks"));
+        assert!(count_regex(&vars).is_err());
+        vars.insert(String::from("drregex"), String::from("(\\d+"));
+        assert!(count_regex(&vars).is_err());
+    }
 
-        Ok(num2)
+    #[test]
+    fn excerpt() {
+        assert_eq!(stderr_excerpt(b""), "(empty)");
+        assert_eq!(
+            stderr_excerpt(b"ERROR: failed to load client library\n"),
+            "ERROR: failed to load client library"
+        );
+        let long = vec![b'x'; STDERR_EXCERPT * 2];
+        assert_eq!(stderr_excerpt(&long).len(), STDERR_EXCERPT + 3);
     }
 }
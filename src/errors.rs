@@ -1,11 +1,14 @@
 use std::error;
 use std::fmt;
 use std::io;
+use std::num::ParseIntError;
 
 #[derive(Debug)]
 pub struct SolverError {
     runner: Runner,
     message: String,
+    // the error this one was converted from, if any
+    source: Option<Box<dyn error::Error + Send + Sync>>,
 }
 
 impl SolverError {
@@ -14,23 +17,49 @@ impl SolverError {
         SolverError {
             runner,
             message: message2,
+            source: None,
         }
     }
+
+    /// Error caused by `source`, which is kept for `Error::source`
+    pub fn with_source<E>(runner: Runner, message: &str, source: E) -> SolverError
+    where
+        E: error::Error + Send + Sync + 'static,
+    {
+        SolverError {
+            source: Some(Box::new(source)),
+            ..SolverError::new(runner, message)
+        }
+    }
+
+    pub fn runner(&self) -> &Runner {
+        &self.runner
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Runner {
     RunnerError,
     MissingArgs,
+    // the target, or the tool running it, does not exist
+    NotFound,
     IoError,
     NixError,
     Timeout,
+    // the child ran into a limit set with Process::with_limits
+    ResourceLimit,
+    // no child slot was free, see ProcessWaiter::try_spawn_process
+    WouldBlock,
+    // the child was killed by a signal, see ExitStatus::check_crash
+    Crashed,
+    ParseError,
+    Aborted,
     Unknown,
 }
 
 impl fmt::Display for SolverError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "filler display TODO")
+        write!(f, "{:?}: {}", self.runner, self.message)
     }
 }
 
@@ -39,18 +68,67 @@ impl error::Error for SolverError {
         &self.message
     }
     fn source(&self) -> Option<&(dyn error::Error + 'static)> {
-        None
+        match &self.source {
+            Some(source) => Some(&**source),
+            None => None,
+        }
     }
 }
 
 impl From<io::Error> for SolverError {
     fn from(error: io::Error) -> Self {
-        SolverError::new(Runner::IoError, error::Error::description(&error))
+        let message = error::Error::description(&error).to_string();
+        SolverError::with_source(Runner::IoError, &message, error)
     }
 }
 
 impl From<nix::Error> for SolverError {
     fn from(error: nix::Error) -> Self {
-        SolverError::new(Runner::NixError, error::Error::description(&error))
+        let message = error::Error::description(&error).to_string();
+        SolverError::with_source(Runner::NixError, &message, error)
+    }
+}
+
+impl From<ParseIntError> for SolverError {
+    fn from(error: ParseIntError) -> Self {
+        let message = error::Error::description(&error).to_string();
+        SolverError::with_source(Runner::ParseError, &message, error)
+    }
+}
+
+impl From<serde_json::Error> for SolverError {
+    fn from(error: serde_json::Error) -> Self {
+        SolverError::with_source(Runner::ParseError, &error.to_string(), error)
+    }
+}
+
+impl From<toml::de::Error> for SolverError {
+    fn from(error: toml::de::Error) -> Self {
+        SolverError::with_source(Runner::ParseError, &error.to_string(), error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Runner, SolverError};
+    use std::error::Error;
+    use std::io;
+
+    #[test]
+    fn io_source() {
+        let err: SolverError = io::Error::from_raw_os_error(libc::ENOENT).into();
+        assert_eq!(*err.runner(), Runner::IoError);
+        let source = err.source().unwrap().downcast_ref::<io::Error>().unwrap();
+        assert_eq!(source.raw_os_error(), Some(libc::ENOENT));
+    }
+
+    #[test]
+    fn nix_source() {
+        let err: SolverError = nix::Error::Sys(nix::errno::Errno::EACCES).into();
+        let source = err.source().unwrap().downcast_ref::<nix::Error>().unwrap();
+        assert_eq!(source.as_errno(), Some(nix::errno::Errno::EACCES));
+        assert!(SolverError::new(Runner::Unknown, "no cause")
+            .source()
+            .is_none());
     }
 }
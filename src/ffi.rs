@@ -0,0 +1,345 @@
+//! C API for embedding B7, see include/b7.h.
+//!
+//! Every function returns a B7Status. On failure, the message of the
+//! error is kept per thread and can be read with b7_last_error_message.
+//! Panics are caught here and reported as B7_PANIC, they never unwind
+//! into the caller.
+use crate::b7tui::Env;
+use crate::brute::InstCounter;
+use crate::errors::*;
+use crate::mock::MockSolver;
+use crate::{solver_by_name, B7Opts};
+use libc::{c_char, c_int};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::time::Duration;
+
+#[repr(C)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum B7Status {
+    B7_OK = 0,
+    B7_INVALID_ARGUMENT = 1,
+    B7_SOLVER_ERROR = 2,
+    B7_TIMEOUT = 3,
+    B7_PANIC = 4,
+}
+
+/// Options for a single run, built up with the b7_opts_* functions
+pub struct B7FfiOpts {
+    path: String,
+    solver: FfiSolver,
+    argstate: bool,
+    stdinstate: bool,
+    vars: HashMap<String, String>,
+    timeout: Duration,
+    min_len: u32,
+    max_len: Option<u32>,
+    charset: Option<(u16, u16)>,
+}
+
+// what the solver is made from, so every run gets a fresh one
+enum FfiSolver {
+    Named(String),
+    Mock(Vec<u8>),
+}
+
+impl FfiSolver {
+    fn build(&self) -> Box<InstCounter> {
+        match self {
+            // checked by b7_opts_set_solver
+            FfiSolver::Named(name) => solver_by_name(name).unwrap(),
+            FfiSolver::Mock(flag) => Box::new(MockSolver::new(flag)),
+        }
+    }
+}
+
+/// Solved input of a run
+pub struct B7FfiResult {
+    argv: Vec<Vec<u8>>,
+    stdin: Vec<u8>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+fn set_last_error(message: &str) {
+    // interior NULs would cut the message short, so drop them
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+// run f, turning errors and panics into a status
+fn guard<F: FnOnce() -> Result<(), B7Status>>(f: F) -> B7Status {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => B7Status::B7_OK,
+        Ok(Err(status)) => status,
+        Err(cause) => {
+            let message = cause
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| cause.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| String::from("unknown panic"));
+            set_last_error(&format!("panic: {}", message));
+            B7Status::B7_PANIC
+        }
+    }
+}
+
+fn invalid(message: &str) -> B7Status {
+    set_last_error(message);
+    B7Status::B7_INVALID_ARGUMENT
+}
+
+fn solver_status(error: &SolverError) -> B7Status {
+    set_last_error(&error.to_string());
+    match error.runner() {
+        Runner::Timeout => B7Status::B7_TIMEOUT,
+        _ => B7Status::B7_SOLVER_ERROR,
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char, what: &str) -> Result<&'a str, B7Status> {
+    if s.is_null() {
+        return Err(invalid(&format!("{} is NULL", what)));
+    }
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| invalid(&format!("{} is not valid UTF-8", what)))
+}
+
+unsafe fn opts_mut<'a>(opts: *mut B7FfiOpts) -> Result<&'a mut B7FfiOpts, B7Status> {
+    opts.as_mut().ok_or_else(|| invalid("opts is NULL"))
+}
+
+/// Create options for brute forcing the binary at `path`, with the perf
+/// solver, stdin solving only and a 5 second timeout
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_new(
+    path: *const c_char,
+    opts_out: *mut *mut B7FfiOpts,
+) -> B7Status {
+    guard(|| {
+        let path = to_str(path, "path")?;
+        if opts_out.is_null() {
+            return Err(invalid("opts_out is NULL"));
+        }
+        let opts = B7FfiOpts {
+            path: path.to_string(),
+            solver: FfiSolver::Named("perf".to_string()),
+            argstate: false,
+            stdinstate: true,
+            vars: HashMap::new(),
+            timeout: Duration::new(5, 0),
+            min_len: 0,
+            max_len: None,
+            charset: None,
+        };
+        *opts_out = Box::into_raw(Box::new(opts));
+        Ok(())
+    })
+}
+
+/// Use one of the built in solvers, e.g. "perf" or "dynamorio"
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_solver(opts: *mut B7FfiOpts, name: *const c_char) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        let name = to_str(name, "solver")?;
+        if solver_by_name(name).is_none() {
+            return Err(invalid(&format!("unknown solver {}", name)));
+        }
+        opts.solver = FfiSolver::Named(name.to_string());
+        Ok(())
+    })
+}
+
+/// Use the mock solver, which never runs the target and solves to `flag`
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_mock_solver(
+    opts: *mut B7FfiOpts,
+    flag: *const u8,
+    len: usize,
+) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        if flag.is_null() && len > 0 {
+            return Err(invalid("flag is NULL"));
+        }
+        let flag = if len > 0 {
+            slice::from_raw_parts(flag, len)
+        } else {
+            &[]
+        };
+        opts.solver = FfiSolver::Mock(flag.to_vec());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_phases(
+    opts: *mut B7FfiOpts,
+    argv: c_int,
+    stdin: c_int,
+) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        opts.argstate = argv != 0;
+        opts.stdinstate = stdin != 0;
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_timeout_ms(opts: *mut B7FfiOpts, timeout_ms: u64) -> B7Status {
+    guard(|| {
+        opts_mut(opts)?.timeout = Duration::from_millis(timeout_ms);
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_len(
+    opts: *mut B7FfiOpts,
+    min_len: u32,
+    max_len: u32,
+) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        opts.min_len = min_len;
+        opts.max_len = Some(max_len);
+        Ok(())
+    })
+}
+
+/// Inclusive range of byte values tried at every position
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_charset(opts: *mut B7FfiOpts, min: u8, max: u8) -> B7Status {
+    guard(|| {
+        opts_mut(opts)?.charset = Some((u16::from(min), u16::from(max)));
+        Ok(())
+    })
+}
+
+/// Set a solver variable, e.g. "dynpath" for the dynamorio solver
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_set_var(
+    opts: *mut B7FfiOpts,
+    key: *const c_char,
+    value: *const c_char,
+) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        let key = to_str(key, "key")?;
+        let value = to_str(value, "value")?;
+        opts.vars.insert(key.to_string(), value.to_string());
+        Ok(())
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_opts_free(opts: *mut B7FfiOpts) {
+    if !opts.is_null() {
+        drop(Box::from_raw(opts));
+    }
+}
+
+/// Run the brute force. The options can be reused for another run,
+/// which gets a fresh solver of the same kind. On success `*result_out` must
+/// be freed with b7_result_free
+#[no_mangle]
+pub unsafe extern "C" fn b7_run(
+    opts: *mut B7FfiOpts,
+    result_out: *mut *mut B7FfiResult,
+) -> B7Status {
+    guard(|| {
+        let opts = opts_mut(opts)?;
+        if result_out.is_null() {
+            return Err(invalid("result_out is NULL"));
+        }
+        let mut term = Env::new();
+        let mut b7 = B7Opts::new(
+            opts.path.clone(),
+            opts.argstate,
+            opts.stdinstate,
+            opts.solver.build(),
+            &mut term,
+            opts.vars.clone(),
+            opts.timeout,
+        );
+        b7.set_min_len(opts.min_len).set_max_len(opts.max_len);
+        if let Some((min, max)) = opts.charset {
+            b7.set_charset(min, max);
+        }
+        let results = b7.run().map_err(|e| solver_status(&e))?;
+
+        *result_out = Box::into_raw(Box::new(B7FfiResult {
+            argv: results.argv,
+            stdin: results.stdin,
+        }));
+        Ok(())
+    })
+}
+
+/// Solved stdin. The bytes are not NUL terminated and stay valid
+/// until the result is freed
+#[no_mangle]
+pub unsafe extern "C" fn b7_result_get_stdin(
+    result: *const B7FfiResult,
+    len_out: *mut usize,
+) -> *const u8 {
+    match result.as_ref() {
+        Some(result) => {
+            if !len_out.is_null() {
+                *len_out = result.stdin.len();
+            }
+            result.stdin.as_ptr()
+        }
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_result_get_argc(result: *const B7FfiResult) -> usize {
+    result.as_ref().map_or(0, |result| result.argv.len())
+}
+
+/// Solved argument `idx`, or NULL if there is no such argument
+#[no_mangle]
+pub unsafe extern "C" fn b7_result_get_arg(
+    result: *const B7FfiResult,
+    idx: usize,
+    len_out: *mut usize,
+) -> *const u8 {
+    match result.as_ref().and_then(|result| result.argv.get(idx)) {
+        Some(arg) => {
+            if !len_out.is_null() {
+                *len_out = arg.len();
+            }
+            arg.as_ptr()
+        }
+        None => ptr::null(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn b7_result_free(result: *mut B7FfiResult) {
+    if !result.is_null() {
+        drop(Box::from_raw(result));
+    }
+}
+
+/// Message of the last error on this thread, or NULL if there was none.
+/// Valid until the next failing call on the same thread
+#[no_mangle]
+pub extern "C" fn b7_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|e| match &*e.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
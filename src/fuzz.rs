@@ -0,0 +1,147 @@
+//! Handing what B7 recovered to a fuzzer: an AFL/libFuzzer dictionary
+//! with --emit-dict and a seed corpus with --emit-seeds
+use crate::errors::*;
+use crate::statistics::PhaseStats;
+use crate::B7Results;
+use std::fs;
+use std::path::Path;
+
+// fragments shorter than this are too common to be worth a dictionary entry
+const MIN_FRAGMENT: usize = 2;
+
+/// Escape bytes for the value of a dictionary entry. AFL only knows
+/// `\\`, `\"` and `\xNN`, so everything outside printable ascii is hex
+pub fn afl_escape(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for &b in bytes {
+        match b {
+            b'\\' => out.push_str("\\\\"),
+            b'"' => out.push_str("\\\""),
+            0x20..=0x7e => out.push(b as char),
+            _ => out.push_str(&format!("\\x{:02x}", b)),
+        }
+    }
+    out
+}
+
+// Positions of the stdin phase whose winner was in doubt, with the
+// candidate that came closest to it. A round is in doubt if it was
+// flagged against the reference count, or if the runner-up tied
+fn ambiguous(phase: &PhaseStats) -> Vec<(usize, u8)> {
+    let mut out = Vec::new();
+    for decision in phase.decisions() {
+        if let Some((runner_up, _)) = decision.runner_up {
+            if phase.uncertain.contains(&(decision.round as u32)) || decision.margin == Some(0) {
+                out.push((decision.round, runner_up as u8));
+            }
+        }
+    }
+    out
+}
+
+fn stdin_ambiguous(results: &B7Results) -> Vec<(usize, u8)> {
+    results
+        .stats
+        .phases
+        .iter()
+        .find(|p| p.name == "stdin")
+        .map(ambiguous)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|&(pos, _)| pos < results.stdin.len())
+        .collect()
+}
+
+/// Dictionary with the solved stdin and arguments, plus every run of
+/// stdin bytes between two positions that were in doubt
+pub fn dictionary(results: &B7Results) -> String {
+    let mut entries: Vec<(String, &[u8])> = Vec::new();
+    if !results.stdin.is_empty() {
+        entries.push((String::from("stdin"), &results.stdin[..]));
+    }
+    for (i, arg) in results.argv.iter().enumerate() {
+        if !arg.is_empty() {
+            entries.push((format!("arg{}", i + 1), &arg[..]));
+        }
+    }
+
+    let doubts = stdin_ambiguous(results);
+    if !doubts.is_empty() {
+        let mut start = 0;
+        let ends = doubts.iter().map(|d| d.0).chain(Some(results.stdin.len()));
+        for (part, end) in ends.enumerate() {
+            if end - start >= MIN_FRAGMENT {
+                entries.push((
+                    format!("stdin_part{}", part + 1),
+                    &results.stdin[start..end],
+                ));
+            }
+            start = end + 1;
+        }
+    }
+
+    let mut out = String::from("# recovered by b7\n");
+    let mut seen: Vec<&[u8]> = Vec::new();
+    for (name, value) in entries {
+        if seen.contains(&value) {
+            continue;
+        }
+        seen.push(value);
+        out.push_str(&format!("{}=\"{}\"\n", name, afl_escape(value)));
+    }
+    out
+}
+
+pub fn write_dictionary(path: &Path, results: &B7Results) -> Result<(), SolverError> {
+    fs::write(path, dictionary(results))?;
+    Ok(())
+}
+
+/// Write the solved stdin and arguments to `dir`, one file each, plus a
+/// copy of stdin with the runner-up byte for every position in doubt.
+/// Returns the number of seeds written
+pub fn write_seeds(dir: &Path, results: &B7Results) -> Result<usize, SolverError> {
+    fs::create_dir_all(dir)?;
+    let mut seeds: Vec<(String, Vec<u8>)> = Vec::new();
+    seeds.push((String::from("stdin"), results.stdin.clone()));
+    for (i, arg) in results.argv.iter().enumerate() {
+        seeds.push((format!("arg{}", i + 1), arg.clone()));
+    }
+    for (pos, byte) in stdin_ambiguous(results) {
+        let mut alt = results.stdin.clone();
+        alt[pos] = byte;
+        seeds.push((format!("stdin_alt{}", pos), alt));
+    }
+
+    for (name, seed) in &seeds {
+        fs::write(dir.join(name), seed)?;
+    }
+    Ok(seeds.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{afl_escape, ambiguous};
+    use crate::statistics::PhaseStats;
+
+    #[test]
+    fn escaping() {
+        assert_eq!(afl_escape(b"abc"), "abc");
+        assert_eq!(afl_escape(b"a\"b\\c"), "a\\\"b\\\\c");
+        assert_eq!(afl_escape(b"\n\0\x7f\xff"), "\\x0a\\x00\\x7f\\xff");
+        assert_eq!(afl_escape(b" ~"), " ~");
+    }
+
+    #[test]
+    fn runner_up_on_ties() {
+        let phase = PhaseStats {
+            rounds: vec![
+                vec![(0x41, 100), (0x42, 100), (0x43, 150)],
+                vec![(0x41, 100), (0x42, 150), (0x43, 150), (0x44, 100)],
+            ],
+            picks: vec![0x43, 0x41],
+            ..PhaseStats::default()
+        };
+        assert_eq!(ambiguous(&phase), vec![(1, 0x44)]);
+    }
+}
@@ -1,15 +1,98 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
 type StringType = Vec<u8>;
 type ArgumentType = Vec<StringType>;
 
-#[derive(Debug, Clone)]
+/// A single candidate to run the target with
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Input {
+    /// Arguments after argv[0], as raw bytes. They are passed to the
+    /// target unchanged, so they must not contain NUL bytes
     pub argv: ArgumentType,
-    pub stdin: StringType,
+    /// Written to the target's stdin in full, which is then closed.
+    /// Shared so that handing the input to a Process doesn't copy it
+    pub stdin: Arc<[u8]>,
+    /// Environment variables set on top of B7's own environment
+    #[serde(default)]
+    pub envp: Vec<(StringType, StringType)>,
+    /// Contents of a file whose path is passed after the arguments
+    #[serde(default)]
+    pub file: Option<FileInput>,
+}
+
+/// A file the target reads its input from. Every run gets its own
+/// temporary copy, which is removed again once the run is over
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct FileInput {
+    pub contents: Arc<[u8]>,
 }
 
 impl Input {
     pub fn new(argv: ArgumentType, stdin: StringType) -> Input {
-        Input { argv, stdin }
+        Input {
+            argv,
+            stdin: Arc::from(stdin),
+            envp: Vec::new(),
+            file: None,
+        }
+    }
+
+    /// Empty stdin and no arguments but the path of a file holding `contents`
+    pub fn from_file(contents: StringType) -> Input {
+        Input {
+            file: Some(FileInput {
+                contents: Arc::from(contents),
+            }),
+            ..Input::new(vec![], vec![])
+        }
+    }
+
+    /// No arguments and empty stdin, to be filled in with `arg` and `stdin`
+    pub fn builder() -> InputBuilder {
+        InputBuilder::default()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InputBuilder {
+    argv: ArgumentType,
+    stdin: StringType,
+    envp: Vec<(StringType, StringType)>,
+    file: Option<StringType>,
+}
+
+impl InputBuilder {
+    /// Append an argument
+    pub fn arg<T: Into<StringType>>(&mut self, arg: T) -> &mut Self {
+        self.argv.push(arg.into());
+        self
+    }
+
+    pub fn stdin<T: Into<StringType>>(&mut self, stdin: T) -> &mut Self {
+        self.stdin = stdin.into();
+        self
+    }
+
+    pub fn env<K: Into<StringType>, V: Into<StringType>>(&mut self, key: K, value: V) -> &mut Self {
+        self.envp.push((key.into(), value.into()));
+        self
+    }
+
+    /// Pass the path of a file with these contents after the arguments
+    pub fn file<T: Into<StringType>>(&mut self, contents: T) -> &mut Self {
+        self.file = Some(contents.into());
+        self
+    }
+
+    pub fn build(&self) -> Input {
+        Input {
+            envp: self.envp.clone(),
+            file: self.file.clone().map(|contents| FileInput {
+                contents: Arc::from(contents),
+            }),
+            ..Input::new(self.argv.clone(), self.stdin.clone())
+        }
     }
 }
 
@@ -27,7 +110,8 @@ impl Input {
  *
  * generators follow this spec:
  *   iteration: should return (Id, Input)
- *     Id is an arbitrary type, an identifier the generator uses to identify the input
+ *     Id identifies the input to the generator. It is a number (a length, a
+ *     byte), which the brute keeps in its statistics as a u64
  *   update: when brute forcer chooses the (argv, stdin) pair that was best,
  *     it calls update(Id) passing the associated Id of the chosen input
  */
@@ -36,6 +120,15 @@ impl Input {
 pub trait Update: Iterator {
     type Id;
     fn update(&mut self, chosen: &Self::Id) -> bool;
+    // Undo the last update, so the brute can choose again for that
+    // round. False if there is nothing to undo or the generator can't
+    fn retract(&mut self) -> bool {
+        false
+    }
+    // How many rounds follow the one being run, if that is known
+    fn rounds_left(&self) -> Option<u32> {
+        None
+    }
 }
 
 // Generate trait: has iteration and updating with right Id type
@@ -54,6 +147,7 @@ impl<T: Iterator<Item = (U, Input)> + Update<Id = U>, U> Generate<U> for T {}
 pub struct StdinLenGenerator {
     len: u32,
     max: u32,
+    padchr: u8,
     correct: u32,
 }
 
@@ -68,10 +162,17 @@ impl StdinLenGenerator {
         StdinLenGenerator {
             len: min,
             max,
+            padchr: 0x41,
             correct: 0,
         }
     }
 
+    // Byte the lengths are filled with, one the target does nothing
+    // special with
+    pub fn set_padchr(&mut self, padchr: u8) {
+        self.padchr = padchr;
+    }
+
     // return the number figured out so far
     pub fn get_length(&self) -> u32 {
         self.correct
@@ -88,7 +189,7 @@ impl Iterator for StdinLenGenerator {
         }
         let sz = self.len;
         self.len += 1;
-        Some((sz, Input::new(vec![], vec![0x41; sz as usize])))
+        Some((sz, Input::new(vec![], vec![self.padchr; sz as usize])))
     }
 }
 
@@ -108,6 +209,10 @@ impl Update for StdinLenGenerator {
         self.on_update();
         false
     }
+
+    fn rounds_left(&self) -> Option<u32> {
+        Some(0)
+    }
 }
 
 #[derive(Debug)]
@@ -221,6 +326,119 @@ impl Update for StdinCharGenerator {
         self.on_update();
         self.idx < self.padlen
     }
+
+    fn retract(&mut self) -> bool {
+        if self.correct.pop().is_none() {
+            return false;
+        }
+        self.idx -= 1;
+        self.cur = self.min;
+        true
+    }
+
+    fn rounds_left(&self) -> Option<u32> {
+        Some(self.padlen.saturating_sub(self.idx + 1))
+    }
+}
+
+/* code for file generators */
+// The file phases solve exactly like the stdin ones, only the
+// candidate bytes end up in the input file instead of stdin
+
+fn stdin_to_file(inp: Input) -> Input {
+    Input::from_file(inp.stdin.to_vec())
+}
+
+#[derive(Debug)]
+pub struct FileLenGenerator(StdinLenGenerator);
+
+impl std::fmt::Display for FileLenGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FileLenGenerator {
+    pub fn new(min: u32, max: u32) -> FileLenGenerator {
+        FileLenGenerator(StdinLenGenerator::new(min, max))
+    }
+
+    pub fn set_padchr(&mut self, padchr: u8) {
+        self.0.set_padchr(padchr);
+    }
+
+    pub fn get_length(&self) -> u32 {
+        self.0.get_length()
+    }
+}
+
+impl Iterator for FileLenGenerator {
+    type Item = (u32, Input);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(id, inp)| (id, stdin_to_file(inp)))
+    }
+}
+
+impl Update for FileLenGenerator {
+    type Id = u32;
+
+    fn update(&mut self, chosen: &u32) -> bool {
+        self.0.correct = *chosen;
+        info!("file length: {}", chosen);
+        false
+    }
+
+    fn rounds_left(&self) -> Option<u32> {
+        Some(0)
+    }
+}
+
+#[derive(Debug)]
+pub struct FileCharGenerator(StdinCharGenerator);
+
+impl std::fmt::Display for FileCharGenerator {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl FileCharGenerator {
+    pub fn new(padlen: u32, min: u16, max: u16) -> FileCharGenerator {
+        FileCharGenerator(StdinCharGenerator::new(padlen, min, max))
+    }
+
+    pub fn set_padchr(&mut self, padchr: u8) {
+        self.0.set_padchr(padchr);
+    }
+
+    pub fn get_input(&self) -> &StringType {
+        self.0.get_input()
+    }
+}
+
+impl Iterator for FileCharGenerator {
+    type Item = (u8, Input);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(id, inp)| (id, stdin_to_file(inp)))
+    }
+}
+
+impl Update for FileCharGenerator {
+    type Id = u8;
+
+    fn update(&mut self, chosen: &u8) -> bool {
+        self.0.update(chosen)
+    }
+
+    fn retract(&mut self) -> bool {
+        self.0.retract()
+    }
+
+    fn rounds_left(&self) -> Option<u32> {
+        self.0.rounds_left()
+    }
 }
 
 /* code for argv generators */
@@ -283,6 +501,10 @@ impl Update for ArgcGenerator {
         self.on_update();
         false
     }
+
+    fn rounds_left(&self) -> Option<u32> {
+        Some(0)
+    }
 }
 
 #[derive(Debug)]
@@ -368,6 +590,10 @@ impl Update for ArgvLenGenerator {
         self.on_update();
         (self.pos as u32) < self.argc
     }
+
+    fn rounds_left(&self) -> Option<u32> {
+        Some(self.argc.saturating_sub(self.pos as u32 + 1))
+    }
 }
 
 #[derive(Debug)]
@@ -494,4 +720,14 @@ impl Update for ArgvGenerator {
 
         (self.pos as u32) < self.argc
     }
+
+    fn rounds_left(&self) -> Option<u32> {
+        // the rest of this argument, and all of the ones after it
+        let later: u32 = self.len.iter().skip(self.pos + 1).sum();
+        let rest = self
+            .len
+            .get(self.pos)
+            .map_or(0, |len| len.saturating_sub(self.idx + 1));
+        Some(rest + later)
+    }
 }
@@ -0,0 +1,183 @@
+use crate::brute::*;
+use crate::errors::*;
+use crate::generators::Input;
+use crate::process::{OutputMode, ProcessHandle, ProcessTemplate};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Written by a harness once it is ready for candidates
+pub const HARNESS_MAGIC: &[u8; 4] = b"B7H1";
+
+// how long to wait for a killed harness to be reaped
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Measures many candidates per spawned process, by talking to a target
+/// that has been wrapped in a harness loop (like AFL's persistent mode).
+/// See docs/harness.md for the protocol.
+///
+/// Harnesses are started on demand, one per worker thread at most, and
+/// reused until one fails or times out, at which point it is killed
+#[derive(Default)]
+pub struct HarnessSolver {
+    template: Option<ProcessTemplate>,
+    idle: Mutex<Vec<Harness>>,
+}
+
+impl HarnessSolver {
+    pub fn new() -> HarnessSolver {
+        HarnessSolver {
+            template: None,
+            idle: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let mut template = ProcessTemplate::new(path);
+    template.apply_vars(vars)?;
+    template.interactive(true);
+    // nothing reads it, and a full pipe would stop a long lived harness
+    if !vars.contains_key("stderr_mode") {
+        template.stderr_mode(OutputMode::Null);
+    }
+    Ok(template)
+}
+
+// wait until fd can be read without blocking
+fn wait_readable(fd: i32, timeout: Duration) -> Result<(), SolverError> {
+    let mut pfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let ms = timeout.as_secs() * 1000 + u64::from(timeout.subsec_millis());
+    let ms = ms.min(i32::max_value() as u64) as i32;
+    match unsafe { libc::poll(&mut pfd, 1, ms) } {
+        0 => Err(SolverError::new(Runner::Timeout, "harness timed out")),
+        -1 => Err(std::io::Error::last_os_error().into()),
+        _ => Ok(()),
+    }
+}
+
+fn push_bytes(msg: &mut Vec<u8>, bytes: &[u8]) {
+    msg.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    msg.extend_from_slice(bytes);
+}
+
+// one candidate as sent to the harness: argc, then each argument and
+// stdin as a length followed by the bytes, all little endian u32
+fn encode(inp: &Input) -> Vec<u8> {
+    let mut msg = Vec::new();
+    msg.extend_from_slice(&(inp.argv.len() as u32).to_le_bytes());
+    for arg in &inp.argv {
+        push_bytes(&mut msg, arg);
+    }
+    push_bytes(&mut msg, &inp.stdin);
+    msg
+}
+
+struct Harness {
+    handle: ProcessHandle,
+}
+
+impl Harness {
+    fn start(template: &ProcessTemplate, timeout: Duration) -> Result<Harness, SolverError> {
+        let mut harness = Harness {
+            handle: template.instantiate(&Input::new(vec![], vec![])).spawn()?,
+        };
+        let mut magic = [0; 4];
+        harness.read(&mut magic, timeout)?;
+        if &magic != HARNESS_MAGIC {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "target did not start the harness protocol",
+            ));
+        }
+        Ok(harness)
+    }
+
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> Result<(), SolverError> {
+        let stdout = match self.handle.stdout() {
+            Some(stdout) => stdout,
+            None => return Err(SolverError::new(Runner::IoError, "harness has no stdout")),
+        };
+        wait_readable(stdout.as_raw_fd(), timeout)?;
+        stdout.read_exact(buf)?;
+        Ok(())
+    }
+
+    fn measure(&mut self, inp: &Input, timeout: Duration) -> Result<i64, SolverError> {
+        {
+            let stdin = match self.handle.stdin() {
+                Some(stdin) => stdin,
+                None => return Err(SolverError::new(Runner::IoError, "harness has no stdin")),
+            };
+            stdin.write_all(&encode(inp))?;
+            stdin.flush()?;
+        }
+
+        let mut count = [0; 8];
+        self.read(&mut count, timeout)?;
+        Ok(i64::from_le_bytes(count))
+    }
+}
+
+impl Drop for Harness {
+    fn drop(&mut self) {
+        // the harness loops until stdin is closed, which only happens
+        // once the handle is gone, so it has to be killed
+        if let Ok(None) = self.handle.try_finish() {
+            let _ = self.handle.kill(KILL_TIMEOUT);
+        }
+    }
+}
+
+impl InstCounter for HarnessSolver {
+    fn name(&self) -> &str {
+        "harness"
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.template = Some(make_template(path, vars)?);
+        Ok(())
+    }
+
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let idle = self.idle.lock().unwrap().pop();
+        let mut harness = match idle {
+            Some(harness) => harness,
+            None => {
+                let template = template_for(self.template.as_ref(), data, make_template)?;
+                Harness::start(&template, data.timeout)?
+            }
+        };
+
+        // a harness that failed is in an unknown state, so it is
+        // dropped (and killed) rather than reused
+        let count = harness.measure(&data.inp, data.timeout)?;
+        self.idle.lock().unwrap().push(harness);
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encode;
+    use crate::generators::Input;
+
+    #[test]
+    fn encoding() {
+        let inp = Input::new(vec![b"ab".to_vec(), vec![]], b"xyz".to_vec());
+        let mut expected = vec![2, 0, 0, 0];
+        expected.extend_from_slice(&[2, 0, 0, 0, b'a', b'b']);
+        expected.extend_from_slice(&[0, 0, 0, 0]);
+        expected.extend_from_slice(&[3, 0, 0, 0, b'x', b'y', b'z']);
+        assert_eq!(encode(&inp), expected);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::errors::*;
+use png::HasParameters;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+// size in pixels of a single (position, candidate) cell
+const CELL: usize = 4;
+// candidates are bytes
+const CANDIDATES: usize = 256;
+
+// map 0.0 (lowest count of the row) to blue and 1.0 (highest) to red
+fn heat_color(v: f64) -> [u8; 3] {
+    let v = v.max(0.0).min(1.0);
+    [(255.0 * v) as u8, 0, (255.0 * (1.0 - v)) as u8]
+}
+
+// RGB pixels with one row of cells per position and one
+// column of cells per candidate byte. Untried candidates are black
+fn render(rounds: &[Vec<(u64, i64)>]) -> (usize, usize, Vec<u8>) {
+    let width = CANDIDATES * CELL;
+    let height = rounds.len() * CELL;
+    let mut pixels = vec![0u8; width * height * 3];
+
+    for (row, round) in rounds.iter().enumerate() {
+        let min = round.iter().map(|r| r.1).min().unwrap_or(0);
+        let max = round.iter().map(|r| r.1).max().unwrap_or(0);
+        let spread = (max - min).max(1) as f64;
+        for (candidate, count) in round {
+            if *candidate as usize >= CANDIDATES {
+                continue;
+            }
+            let color = heat_color((count - min) as f64 / spread);
+            for y in row * CELL..(row + 1) * CELL {
+                for x in *candidate as usize * CELL..(*candidate as usize + 1) * CELL {
+                    let idx = (y * width + x) * 3;
+                    pixels[idx..idx + 3].copy_from_slice(&color);
+                }
+            }
+        }
+    }
+    (width, height, pixels)
+}
+
+/// Write the per position candidate counts of a phase as a PNG heatmap
+pub fn write_heatmap(path: &Path, rounds: &[Vec<(u64, i64)>]) -> Result<(), SolverError> {
+    let (width, height, pixels) = render(rounds);
+    let to_err = |e: png::EncodingError| SolverError::new(Runner::IoError, &e.to_string());
+
+    let file = BufWriter::new(File::create(path)?);
+    let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+    encoder.set(png::ColorType::RGB).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(to_err)?;
+    writer.write_image_data(&pixels).map_err(to_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{heat_color, render, CANDIDATES, CELL};
+
+    #[test]
+    fn colors() {
+        assert_eq!(heat_color(0.0), [0, 0, 255]);
+        assert_eq!(heat_color(1.0), [255, 0, 0]);
+        assert_eq!(heat_color(2.0), [255, 0, 0]);
+    }
+
+    #[test]
+    fn render_rounds() {
+        let rounds = vec![vec![(0x41, 10), (0x42, 20)], vec![(0x41, 5)]];
+        let (width, height, pixels) = render(&rounds);
+        assert_eq!(width, CANDIDATES * CELL);
+        assert_eq!(height, 2 * CELL);
+
+        let pixel = |x: usize, y: usize| {
+            let idx = (y * CELL * width + x * CELL) * 3;
+            [pixels[idx], pixels[idx + 1], pixels[idx + 2]]
+        };
+        assert_eq!(pixel(0x41, 0), [0, 0, 255]);
+        assert_eq!(pixel(0x42, 0), [255, 0, 0]);
+        assert_eq!(pixel(0x43, 0), [0, 0, 0]);
+        assert_eq!(pixel(0x41, 1), [0, 0, 255]);
+    }
+}
@@ -1,36 +1,196 @@
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "async")]
+pub mod async_run;
 pub mod b7tui;
 pub mod binary;
 pub mod bindings;
+#[cfg(target_arch = "x86_64")]
+pub mod breakpoint;
 pub mod brute;
+pub mod cancel;
+pub mod config;
+pub mod daemon;
 pub mod dynamorio;
 pub mod errors;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod fuzz;
 pub mod generators;
+pub mod harness;
+#[cfg(feature = "heatmap")]
+pub mod heatmap;
+pub mod logging;
+pub mod memo;
+pub mod mock;
 pub mod perf;
 pub mod process;
+pub mod qemu;
+pub mod repro;
 pub mod statistics;
+#[cfg(target_arch = "x86_64")]
+pub mod syscalls;
+pub mod timing;
 
-use crate::brute::{brute, InstCounter};
+#[cfg(feature = "async")]
+pub use crate::async_run::{run_async, run_async_with};
+use crate::brute::{brute, Detector, InstCountData, InstCounter, Output, Session, Verdict};
+use crate::cancel::CancelToken;
 use crate::errors::*;
 use crate::generators::*;
+use crate::memo::Memo;
+use crate::process::{KillPolicy, OutputMode, ProcessTemplate};
+use crate::statistics::{Decision, PhaseStats, RunStats, Tie};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::time::Duration;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Default upper bound for argument length detection
+pub const DEFAULT_MAX_LEN: u32 = 20;
+/// Default upper bound for stdin and file length detection, which
+/// tend to be longer than arguments
+pub const DEFAULT_INPUT_MAX_LEN: u32 = 51;
+
+// largest argc tried when detecting the argument count, unless the
+// "max_argc" var says otherwise
+const MAX_ARGC: u32 = 5;
+// printable ascii range tried for every input byte by default
+const CHAR_MIN: u16 = 0x20;
+const CHAR_MAX: u16 = 0x7e;
 
 pub struct B7Opts<'a, B: b7tui::Ui> {
     path: String,
     argstate: bool,
     stdinstate: bool,
+    filestate: bool,
     solver: Box<InstCounter>,
     terminal: &'a mut B,
     timeout: Duration,
     vars: HashMap<String, String>,
+    min_len: u32,
+    max_len: Option<u32>,
+    heatmap: Option<PathBuf>,
+    output_path: Option<PathBuf>,
+    detector: Option<Detector>,
+    memo: Memo,
+    memo_file: Option<PathBuf>,
+    strip_trailing_newline: bool,
+    charset: (u16, u16),
+    cancel: CancelToken,
+}
+
+/// What a run would do, as reported by --dry-run
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub path: String,
+    // hex sha256 of the target, so a plan names the exact binary
+    pub target_sha256: String,
+    pub solver: String,
+    pub charset_size: u32,
+    pub phases: Vec<(String, u64)>,
+    pub workers: usize,
+    pub per_run: Option<Duration>,
+}
+
+impl Plan {
+    // upper bound on the number of target executions
+    pub fn total_runs(&self) -> u64 {
+        self.phases.iter().map(|p| p.1).sum()
+    }
+
+    pub fn estimate(&self) -> Option<Duration> {
+        self.per_run
+            .map(|per_run| statistics::estimate_duration(self.total_runs(), per_run, self.workers))
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "target:  {}", self.path)?;
+        writeln!(f, "sha256:  {}", self.target_sha256)?;
+        writeln!(f, "solver:  {}", self.solver)?;
+        writeln!(f, "charset: {} bytes", self.charset_size)?;
+        writeln!(f, "workers: {}", self.workers)?;
+        writeln!(f, "phases (at most):")?;
+        for (name, runs) in &self.phases {
+            writeln!(f, "  {:<12} {:>10} runs", name, runs)?;
+        }
+        writeln!(f, "total:   {} runs", self.total_runs())?;
+        match self.estimate() {
+            Some(eta) => write!(f, "estimated time: {}s", eta.as_secs()),
+            None => write!(f, "estimated time: unknown (not calibrated)"),
+        }
+    }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
 pub struct B7Results {
     pub arg_brute: String,
     pub stdin_brute: String,
+    #[serde(default)]
+    pub file_brute: String,
+    // the same answers as raw bytes, for inputs that aren't valid UTF-8
+    pub argv: Vec<Vec<u8>>,
+    pub stdin: Vec<u8>,
+    // contents of the input file, see B7Opts::set_file_brute
+    #[serde(default)]
+    pub file: Vec<u8>,
+    pub min_len: u32,
+    // None if the default lengths were used, see B7Opts::set_max_len
+    #[serde(default)]
+    pub max_len: Option<u32>,
+    pub stats: RunStats,
+    // true if a CancelToken stopped the run, so the inputs are partial
+    #[serde(default)]
+    pub cancelled: bool,
+    // what the solver fell back to measuring, see InstCounter::fallback
+    #[serde(default)]
+    pub fallback: Option<String>,
+}
+
+impl B7Results {
+    /// How every round of the phase `name` (e.g. "stdin") was decided:
+    /// the winning candidate, its count and the margin over the runner-up.
+    /// Empty if no phase of that name ran
+    pub fn decisions(&self, name: &str) -> Vec<Decision> {
+        self.stats
+            .phases
+            .iter()
+            .find(|p| p.name == name)
+            .map(PhaseStats::decisions)
+            .unwrap_or_default()
+    }
+
+    /// Rounds of the phase `name` whose winner was tied with other
+    /// candidates, see `B7Opts::set_tie_threshold`
+    pub fn ties(&self, name: &str) -> Vec<Tie> {
+        self.stats
+            .phases
+            .iter()
+            .find(|p| p.name == name)
+            .map(|p| p.ties.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Built in solver with the given name, as used by --solver
+pub fn solver_by_name(name: &str) -> Option<Box<InstCounter>> {
+    let solver: Box<InstCounter> = match name {
+        "perf" => Box::new(perf::PerfSolver::new()),
+        "dynamorio" => Box::new(dynamorio::DynamorioSolver::new()),
+        #[cfg(target_arch = "x86_64")]
+        "breakpoint" => Box::new(breakpoint::BreakpointSolver::new()),
+        "timing" => Box::new(timing::TimingSolver::new()),
+        "harness" => Box::new(harness::HarnessSolver::new()),
+        "qemu" => Box::new(qemu::QemuSolver::new()),
+        _ => return None,
+    };
+    Some(solver)
 }
 
 impl<'a, B: b7tui::Ui> B7Opts<'a, B> {
@@ -48,126 +208,870 @@ impl<'a, B: b7tui::Ui> B7Opts<'a, B> {
             path,
             argstate,
             stdinstate,
+            filestate: false,
             solver,
             terminal,
             vars,
             timeout,
+            min_len: 0,
+            max_len: None,
+            heatmap: None,
+            output_path: None,
+            detector: None,
+            memo: Memo::new(),
+            memo_file: None,
+            strip_trailing_newline: false,
+            charset: (CHAR_MIN, CHAR_MAX),
+            cancel: CancelToken::new(),
         }
     }
 
-    pub fn run(&mut self) -> B7Results {
-        let mut arg_brute = String::new();
-        let mut stdin_brute = String::new();
-        if self.argstate {
-            arg_brute = default_arg_brute(
-                &self.path,
-                &*self.solver,
-                self.vars.clone(),
-                self.timeout,
-                self.terminal,
-            )
-            .unwrap();
+    /// First length tried when detecting input lengths, 0 by default.
+    /// With `set_max_len` set to the same value the length is fixed
+    pub fn set_min_len(&mut self, min_len: u32) -> &mut Self {
+        self.min_len = min_len;
+        self
+    }
+
+    /// Last length tried when detecting input lengths. None keeps the
+    /// defaults, `DEFAULT_MAX_LEN` for arguments and
+    /// `DEFAULT_INPUT_MAX_LEN` for stdin and the input file
+    pub fn set_max_len(&mut self, max_len: Option<u32>) -> &mut Self {
+        self.max_len = max_len;
+        self
+    }
+
+    // the (min, max) lengths tried by a phase whose default max is `default`
+    fn lens(&self, default: u32) -> (u32, u32) {
+        let max_len = self.max_len.unwrap_or(default);
+        (self.min_len, max_len.max(self.min_len))
+    }
+
+    /// Largest number of arguments tried by the argc phase, 5 by default.
+    /// Every later argv phase runs once per argument, so raising it only
+    /// costs runs for targets that do take that many
+    pub fn set_max_argc(&mut self, max_argc: Option<u32>) -> &mut Self {
+        match max_argc {
+            Some(max_argc) => self
+                .vars
+                .insert(String::from("max_argc"), max_argc.to_string()),
+            None => self.vars.remove("max_argc"),
+        };
+        self
+    }
+
+    /// Also solve the contents of a file, for targets that open the
+    /// path passed as their last argument. Each candidate is written to
+    /// a temporary file that is removed after the run
+    pub fn set_file_brute(&mut self, filestate: bool) -> &mut Self {
+        self.filestate = filestate;
+        self
+    }
+
+    /// Render the candidate counts of the stdin phase (or of argv if
+    /// stdin wasn't solved) to a PNG at `path` when done. Without the
+    /// heatmap feature this only logs a warning
+    pub fn set_heatmap(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.heatmap = path;
+        self
+    }
+
+    /// Write the recovered inputs into this directory when done, as raw
+    /// bytes: `stdin`, `file` and `argv`, whose arguments are NUL
+    /// terminated like /proc/<pid>/cmdline. Only solved phases are written
+    pub fn set_output_path(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.output_path = path;
+        self
+    }
+
+    /// Load measured counts from this file before running and write them
+    /// back afterwards, even if the run fails, so a resumed session
+    /// doesn't measure the same inputs again. The file records a hash of
+    /// the target, and its counts are ignored once the target changed
+    pub fn set_memo_file(&mut self, path: Option<PathBuf>) -> &mut Self {
+        self.memo_file = path;
+        self
+    }
+
+    /// Run the target in `dir`, for targets that open files relative to
+    /// their own directory. A relative target path still resolves from
+    /// b7's working directory
+    pub fn set_cwd(&mut self, dir: Option<PathBuf>) -> &mut Self {
+        match dir {
+            Some(dir) => {
+                self.vars
+                    .insert(String::from("cwd"), dir.to_string_lossy().into_owned());
+            }
+            None => {
+                self.vars.remove("cwd");
+            }
         }
+        self
+    }
 
-        if self.stdinstate {
-            stdin_brute = default_stdin_brute(
-                &self.path,
-                &*self.solver,
-                self.vars.clone(),
-                self.timeout,
-                self.terminal,
-            )
-            .unwrap();
+    /// Only try byte values from `min` to `max` (inclusive) at every
+    /// position, e.g. 0x20 to 0x7e for printable input
+    pub fn set_charset(&mut self, min: u16, max: u16) -> &mut Self {
+        self.charset = (min, max);
+        self
+    }
+
+    /// Drop a trailing newline from the solved stdin, for targets that
+    /// read a line. Ignored when min and max length are equal, since
+    /// then the newline is part of a length the user asked for
+    pub fn set_strip_trailing_newline(&mut self, strip: bool) -> &mut Self {
+        self.strip_trailing_newline = strip;
+        self
+    }
+
+    /// Check the best candidate of every round with `detector`, and stop
+    /// as soon as it reports success. The candidate is run once more
+    /// without instrumentation to get its exit code, stdout and stderr
+    pub fn set_detector<F>(&mut self, detector: F) -> &mut Self
+    where
+        F: Fn(&Output) -> Option<Verdict> + Send + Sync + 'static,
+    {
+        self.detector = Some(Box::new(detector));
+        self
+    }
+
+    /// Pin each target to a single CPU, so the counts of multithreaded
+    /// targets vary less. Without the pin-cpu feature the targets run
+    /// unpinned with a warning, see `Process::pin_cpu`
+    pub fn set_pin_cpu(&mut self, pin: bool) -> &mut Self {
+        if pin {
+            self.vars.insert(String::from("pin_cpu"), String::from("1"));
+        } else {
+            self.vars.remove("pin_cpu");
         }
+        self
+    }
+
+    /// Measure at most `jobs` candidates at once, instead of one per CPU
+    pub fn set_max_parallel(&mut self, jobs: Option<usize>) -> &mut Self {
+        match jobs {
+            Some(jobs) => self
+                .vars
+                .insert(String::from("max_parallel"), jobs.max(1).to_string()),
+            None => self.vars.remove("max_parallel"),
+        };
+        self
+    }
 
+    /// Run every target on CPU `core`, or anywhere with None.
+    /// `run` fails if b7 can't use that core
+    pub fn set_cpu_affinity(&mut self, core: Option<usize>) -> &mut Self {
+        match core {
+            Some(core) => self.vars.insert(String::from("cpu"), core.to_string()),
+            None => self.vars.remove("cpu"),
+        };
+        self
+    }
+
+    /// Run the targets without ASLR, so they load at the same addresses
+    /// every run. The loader's relocation work for a PIE varies with
+    /// the base, which shows up in the counts
+    pub fn set_disable_aslr(&mut self, disable: bool) -> &mut Self {
+        if disable {
+            self.vars.insert(String::from("no_aslr"), String::from("1"));
+        } else {
+            self.vars.remove("no_aslr");
+        }
+        self
+    }
+
+    /// What to do with targets that run into the timeout, SIGKILL by
+    /// default. See `KillPolicy`
+    pub fn set_kill_policy(&mut self, policy: KillPolicy) -> &mut Self {
+        self.vars
+            .insert(String::from("kill_policy"), policy.to_string());
+        self
+    }
+
+    /// Where the targets' stdout goes, a pipe by default. `Null` saves
+    /// reading output that the solver doesn't look at. Solvers that
+    /// parse the output, like dynamorio, keep theirs piped regardless
+    pub fn set_stdout_mode(&mut self, mode: OutputMode) -> &mut Self {
+        if mode == OutputMode::Piped {
+            self.vars.remove("stdout_mode");
+        } else {
+            self.vars
+                .insert(String::from("stdout_mode"), mode.to_string());
+        }
+        self
+    }
+
+    /// Where the targets' stderr goes, like `set_stdout_mode`
+    pub fn set_stderr_mode(&mut self, mode: OutputMode) -> &mut Self {
+        if mode == OutputMode::Piped {
+            self.vars.remove("stderr_mode");
+        } else {
+            self.vars
+                .insert(String::from("stderr_mode"), mode.to_string());
+        }
+        self
+    }
+
+    /// Run the targets through `command`, e.g. "stdbuf -o0", which is
+    /// split on whitespace and gets the target's path as its last
+    /// argument. An empty command runs the targets directly
+    pub fn set_wrapper(&mut self, command: &str) -> &mut Self {
+        if command.trim().is_empty() {
+            self.vars.remove("wrap");
+        } else {
+            self.vars
+                .insert(String::from("wrap"), String::from(command));
+        }
+        self
+    }
+
+    /// Report candidates whose count is within `threshold` of a round's
+    /// winner as tied with it, see `B7Results::ties`. 0, the default,
+    /// only reports equal counts
+    pub fn set_tie_threshold(&mut self, threshold: u32) -> &mut Self {
+        self.vars
+            .insert(String::from("tie_threshold"), threshold.to_string());
+        self
+    }
+
+    /// When no candidate of a round stands out (all within the tie
+    /// threshold), retry the round before it with its next best
+    /// candidate, at most `max` times per phase. 0, the default, never
+    /// does. Only the stdin and file contents phases can go back
+    pub fn set_max_backtrack(&mut self, max: u32) -> &mut Self {
+        if max == 0 {
+            self.vars.remove("max_backtrack");
+        } else {
+            self.vars
+                .insert(String::from("max_backtrack"), max.to_string());
+        }
+        self
+    }
+
+    /// Run every target in a network namespace of its own, so it can't
+    /// reach the network and waiting on it adds no noise to the counts.
+    /// Needs CAP_SYS_ADMIN, `run` fails early without
+    pub fn set_isolate_network(&mut self, isolate: bool) -> &mut Self {
+        if isolate {
+            self.vars
+                .insert(String::from("no_network"), String::from("1"));
+        } else {
+            self.vars.remove("no_network");
+        }
+        self
+    }
+
+    /// Run nothing, but record the command line and stdin the solver
+    /// would run for every candidate in the `commands` of each phase's
+    /// stats, and log them. Every candidate scores 0, so the length
+    /// phases settle on min_len if it is set, else on max_len, and each
+    /// phase stops after its first round
+    pub fn set_dry_run(&mut self, dry_run: bool) -> &mut Self {
+        if dry_run {
+            self.vars.insert(String::from("dry_run"), String::from("1"));
+        } else {
+            self.vars.remove("dry_run");
+        }
+        self
+    }
+
+    /// Let crashing targets write core files. Off by default, since a
+    /// brute that crashes the target thousands of times would spend
+    /// most of its time dumping cores
+    pub fn set_core_dumps(&mut self, core_dumps: bool) -> &mut Self {
+        if core_dumps {
+            self.vars
+                .insert(String::from("core_dumps"), String::from("1"));
+        } else {
+            self.vars.remove("core_dumps");
+        }
+        self
+    }
+
+    /// Let at most `max` targets exist at once across the whole process,
+    /// also those a solver spawns on its own, where `set_max_parallel`
+    /// only limits the candidates measured at once. The limit is kept
+    /// by `process::WAITER`, so it stays in place after `run`
+    pub fn set_max_children(&mut self, max: Option<usize>) -> &mut Self {
+        match max {
+            Some(max) => self
+                .vars
+                .insert(String::from("max_children"), max.max(1).to_string()),
+            None => self.vars.remove("max_children"),
+        };
+        self
+    }
+
+    /// Run targets as another user, given as a name, a uid or
+    /// "uid:gid" (see `process::lookup_user`). Needs root, e.g. when b7
+    /// only runs as root for perf counters or network isolation
+    pub fn set_run_as(&mut self, user: Option<&str>) -> &mut Self {
+        match user {
+            Some(user) => {
+                self.vars.insert(String::from("run_as"), String::from(user));
+            }
+            None => {
+                self.vars.remove("run_as");
+            }
+        }
+        self
+    }
+
+    /// Token to stop `run` from another thread. Running targets are
+    /// killed, and `run` returns what was solved so far with
+    /// `cancelled` set in the results
+    pub fn cancel_token(&self) -> CancelToken {
+        self.cancel.clone()
+    }
+
+    /// Use `cancel` instead of the options' own token, to share one that
+    /// was created before them, e.g. by `run_async`
+    pub fn set_cancel_token(&mut self, cancel: CancelToken) -> &mut Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Describe the run without starting the brute force.
+    /// The target is executed `calibration_runs` times to estimate how
+    /// long a single run takes; pass 0 to skip executing it entirely
+    pub fn plan(&mut self, calibration_runs: u32) -> Result<Plan, SolverError> {
+        self.check_opts()?;
+        let path = fs::canonicalize(&self.path)?;
+        let hash = Sha256::digest(&fs::read(&path)?);
+
+        let per_run = if calibration_runs > 0 {
+            // measure the way a run would, not through a solver's fallback
+            self.solver.setup(&self.path, &self.vars)?;
+            let data = InstCountData {
+                path: self.path.clone(),
+                inp: Input::new(vec![], vec![]),
+                vars: self.vars.clone(),
+                timeout: self.timeout,
+            };
+            let start = Instant::now();
+            for _ in 0..calibration_runs {
+                self.solver.get_inst_count(&data)?;
+            }
+            Some(start.elapsed() / calibration_runs)
+        } else {
+            None
+        };
+
+        Ok(Plan {
+            path: path.to_string_lossy().into_owned(),
+            target_sha256: format!("{:x}", hash),
+            solver: self.solver.name().to_string(),
+            charset_size: charset_size(self.charset),
+            phases: plan_phases(
+                max_argc(&self.vars)?,
+                self.argstate,
+                self.stdinstate,
+                self.filestate,
+                self.lens(DEFAULT_MAX_LEN),
+                self.lens(DEFAULT_INPUT_MAX_LEN),
+                charset_size(self.charset),
+            ),
+            workers: brute::worker_count(&self.vars)?,
+            per_run,
+        })
+    }
+
+    fn check_opts(&self) -> Result<(), SolverError> {
+        if self.charset.0 > self.charset.1 || self.charset.1 > 0xff {
+            return Err(SolverError::new(
+                Runner::MissingArgs,
+                &format!(
+                    "charset {:#x}-{:#x} is not a range of bytes",
+                    self.charset.0, self.charset.1
+                ),
+            ));
+        }
+        if let Some(max_len) = self.max_len.filter(|&max_len| self.min_len > max_len) {
+            return Err(SolverError::new(
+                Runner::MissingArgs,
+                &format!(
+                    "min length {} is larger than max length {}",
+                    self.min_len, max_len
+                ),
+            ));
+        }
+        if let Some(cpu) = self.vars.get("cpu") {
+            let core = cpu.parse().map_err(|_| {
+                SolverError::new(Runner::MissingArgs, &format!("bad CPU number {}", cpu))
+            })?;
+            process::check_cpu(core)?;
+        }
+        // every run applies these, so a typo fails here and not per run
+        ProcessTemplate::new(&self.path).apply_vars(&self.vars)?;
+        brute::worker_count(&self.vars)?;
+        brute::reference_margin(&self.vars)?;
+        brute::reference(&self.vars)?;
+        brute::tie_threshold(&self.vars)?;
+        brute::max_backtrack(&self.vars)?;
+        if let Some(wrap) = self.vars.get("wrap") {
+            if wrap.trim().is_empty() {
+                return Err(SolverError::new(
+                    Runner::MissingArgs,
+                    "the wrapper command is empty",
+                ));
+            }
+        }
+        pad_byte(&self.vars)?;
+        max_argc(&self.vars)?;
+        // or every spawn would fail on its own
+        if let Some(dir) = self.vars.get("cwd") {
+            if !Path::new(dir).is_dir() {
+                return Err(SolverError::new(
+                    Runner::MissingArgs,
+                    &format!("working directory {} is not a directory", dir),
+                ));
+            }
+        }
+        if self.vars.contains_key("no_network") {
+            process::check_network_isolation()?;
+        }
+        if let Some(user) = self.vars.get("run_as") {
+            process::lookup_user(user)?;
+        }
+        if let Some(max) = self.vars.get("max_children") {
+            max.parse::<usize>().map_err(|_| {
+                SolverError::new(
+                    Runner::MissingArgs,
+                    &format!("bad number of children {}", max),
+                )
+            })?;
+        }
+        Ok(())
+    }
+
+    pub fn run(&mut self) -> Result<B7Results, SolverError> {
+        self.check_opts()?;
+        // the waiter is global, so the limit is only this run's
+        let _max_children = match self.vars.get("max_children") {
+            Some(max) => {
+                let guard = MaxChildrenGuard(process::WAITER.max_children());
+                process::WAITER.set_max_children(max.parse().ok());
+                Some(guard)
+            }
+            None => None,
+        };
+        self.solver.setup(&self.path, &self.vars)?;
+        if let Some(path) = &self.memo_file {
+            self.memo = Memo::load(path, &self.path)?;
+        }
+
+        let mut stats = RunStats::new();
+        let solved = self.solve(&mut stats);
+        if let Some(path) = &self.memo_file {
+            self.memo.save(path)?;
+        }
+        let (argv, mut stdin, file) = solved?;
+        if self.strip_trailing_newline
+            && self.max_len != Some(self.min_len)
+            && stdin.ends_with(b"\n")
+        {
+            stdin.pop();
+        }
+
+        if let Some(path) = &self.heatmap {
+            write_heatmap(path, &stats)?;
+        }
+        if let Some(dir) = &self.output_path {
+            self.write_output(dir, &argv, &stdin, &file)?;
+        }
+
+        let fallback = self.solver.fallback();
+        self.terminal
+            .stats(&stats.report(fallback.as_ref().map(String::as_str)));
         // let terminal decide if it should wait for user
         self.terminal.done();
 
-        B7Results {
-            arg_brute,
-            stdin_brute,
+        Ok(B7Results {
+            arg_brute: format_argv(&argv),
+            stdin_brute: String::from_utf8_lossy(&stdin).into_owned(),
+            file_brute: String::from_utf8_lossy(&file).into_owned(),
+            argv,
+            stdin,
+            file,
+            min_len: self.min_len,
+            max_len: self.max_len,
+            cancelled: stats.phases.iter().any(|p| p.cancelled),
+            fallback,
+            stats,
+        })
+    }
+
+    fn write_output(
+        &self,
+        dir: &Path,
+        argv: &[Vec<u8>],
+        stdin: &[u8],
+        file: &[u8],
+    ) -> Result<(), SolverError> {
+        fs::create_dir_all(dir)?;
+        if self.argstate {
+            let mut cmdline = Vec::new();
+            for arg in argv {
+                cmdline.extend_from_slice(arg);
+                cmdline.push(0);
+            }
+            fs::write(dir.join("argv"), cmdline)?;
         }
+        if self.stdinstate {
+            fs::write(dir.join("stdin"), stdin)?;
+        }
+        if self.filestate {
+            fs::write(dir.join("file"), file)?;
+        }
+        Ok(())
+    }
+
+    // run the default phases, returning the solved argv, stdin and file
+    fn solve(
+        &mut self,
+        stats: &mut RunStats,
+    ) -> Result<(Vec<Vec<u8>>, Vec<u8>, Vec<u8>), SolverError> {
+        let mut argv = Vec::new();
+        let mut stdin = Vec::new();
+        let mut file = Vec::new();
+        let session = Session {
+            path: &self.path,
+            counter: &*self.solver,
+            timeout: self.timeout,
+            vars: self.vars.clone(),
+            detector: self.detector.as_ref(),
+            memo: &self.memo,
+            cancel: self.cancel.clone(),
+        };
+        if self.argstate {
+            let lens = self.lens(DEFAULT_MAX_LEN);
+            argv = default_arg_brute(&session, lens, self.charset, stats, self.terminal)?;
+        }
+        let lens = self.lens(DEFAULT_INPUT_MAX_LEN);
+
+        // nothing left to solve once the detector is satisfied
+        let solved = stats.phases.iter().any(|p| p.success.is_some());
+        if self.stdinstate && !solved && !self.cancel.is_cancelled() {
+            stdin = default_stdin_brute(&session, lens, self.charset, stats, self.terminal)?;
+        }
+
+        let solved = stats.phases.iter().any(|p| p.success.is_some());
+        if self.filestate && !solved && !self.cancel.is_cancelled() {
+            file = default_file_brute(&session, lens, self.charset, stats, self.terminal)?;
+        }
+        Ok((argv, stdin, file))
+    }
+}
+
+// puts back the waiter's child limit from before a run that set its own
+struct MaxChildrenGuard(Option<usize>);
+
+impl Drop for MaxChildrenGuard {
+    fn drop(&mut self) {
+        process::WAITER.set_max_children(self.0);
+    }
+}
+
+// heatmap of the stdin contents phase, or the argv one if stdin wasn't solved
+#[cfg(feature = "heatmap")]
+fn write_heatmap(path: &Path, stats: &RunStats) -> Result<(), SolverError> {
+    let phase = stats
+        .phases
+        .iter()
+        .find(|p| p.name == "stdin")
+        .or_else(|| stats.phases.iter().find(|p| p.name == "argv"));
+    match phase {
+        Some(phase) => heatmap::write_heatmap(path, &phase.rounds),
+        None => {
+            warn!("Nothing was solved, not writing a heatmap");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(not(feature = "heatmap"))]
+fn write_heatmap(_path: &Path, _stats: &RunStats) -> Result<(), SolverError> {
+    warn!("Built without the heatmap feature, not writing a heatmap");
+    Ok(())
+}
+
+// Upper bound on the runs of every phase the default brute would do.
+// Lengths aren't known up front, so the max length is assumed. The
+// (min, max) lengths are those of the argv and the stdin/file phases
+fn plan_phases(
+    max_argc: u32,
+    argstate: bool,
+    stdinstate: bool,
+    filestate: bool,
+    arg_lens: (u32, u32),
+    input_lens: (u32, u32),
+    charset_size: u32,
+) -> Vec<(String, u64)> {
+    let tried = |lens: (u32, u32)| u64::from(lens.1 - lens.0 + 1);
+    let chars = u64::from(charset_size);
+    let mut phases = Vec::new();
+    if argstate {
+        let argc = u64::from(max_argc);
+        phases.push(("argc".to_string(), argc + 1));
+        phases.push(("argv length".to_string(), argc * tried(arg_lens) * 5));
+        phases.push(("argv".to_string(), argc * u64::from(arg_lens.1) * chars * 5));
+    }
+    if stdinstate {
+        phases.push(("stdin length".to_string(), tried(input_lens)));
+        phases.push(("stdin".to_string(), u64::from(input_lens.1) * chars));
+    }
+    if filestate {
+        phases.push(("file length".to_string(), tried(input_lens)));
+        phases.push(("file".to_string(), u64::from(input_lens.1) * chars));
+    }
+    phases
+}
+
+fn charset_size(charset: (u16, u16)) -> u32 {
+    u32::from(charset.1 - charset.0) + 1
+}
+
+// Byte stdin and file lengths are filled with while detecting them,
+// from the "pad" var (decimal or 0x hex), 'A' by default
+fn pad_byte(vars: &HashMap<String, String>) -> Result<u8, SolverError> {
+    let pad = match vars.get("pad") {
+        Some(pad) => pad,
+        None => return Ok(b'A'),
+    };
+    let parsed = if pad.starts_with("0x") {
+        u8::from_str_radix(&pad[2..], 16)
+    } else {
+        pad.parse()
+    };
+    parsed.map_err(|_| SolverError::new(Runner::MissingArgs, &format!("bad pad byte {}", pad)))
+}
+
+// Largest argc the argc phase tries, from the "max_argc" var
+fn max_argc(vars: &HashMap<String, String>) -> Result<u32, SolverError> {
+    match vars.get("max_argc") {
+        Some(max) => max
+            .parse()
+            .map_err(|_| SolverError::new(Runner::MissingArgs, &format!("bad max argc {}", max))),
+        None => Ok(MAX_ARGC),
+    }
+}
+
+// error out if length detection grew all the way to the cap, since the
+// real length is probably past it. A fixed length (min == max) is never
+// grown into, so it is taken as given
+// length a dry run carries on with, since every length scores the same:
+// min_len if it is set, else max_len
+fn dry_run_len(lens: (u32, u32)) -> u32 {
+    if lens.0 > 0 {
+        lens.0
+    } else {
+        lens.1
+    }
+}
+
+fn check_len_cap(len: u32, lens: (u32, u32)) -> Result<(), SolverError> {
+    let (min_len, max_len) = lens;
+    if max_len > min_len && len >= max_len {
+        return Err(SolverError::new(
+            Runner::RunnerError,
+            &format!(
+                "count was still increasing at length {}; raise --max-len",
+                max_len
+            ),
+        ));
     }
+    Ok(())
+}
+
+// argv in the same form as ArgvGenerator prints it
+fn format_argv(argv: &[Vec<u8>]) -> String {
+    argv.iter()
+        .map(|arg| format!("[{}], ", String::from_utf8_lossy(arg)))
+        .collect()
 }
 
 // solves "default" arguement case
 fn default_arg_brute<B: b7tui::Ui>(
-    path: &str,
-    solver: &InstCounter,
-    vars: HashMap<String, String>,
-    timeout: Duration,
+    session: &Session,
+    lens: (u32, u32),
+    charset: (u16, u16),
+    stats: &mut RunStats,
     terminal: &mut B,
-) -> Result<String, SolverError> {
+) -> Result<Vec<Vec<u8>>, SolverError> {
     // Solve for argc
-    let mut argcgen = ArgcGenerator::new(0, 5);
-    brute(
-        path,
-        1,
-        &mut argcgen,
-        solver,
-        terminal,
-        timeout,
-        vars.clone(),
-    )?;
-    let argc = argcgen.get_length();
+    let max_argc = max_argc(&session.vars)?;
+    let mut argcgen = ArgcGenerator::new(0, max_argc);
+    logging::set_phase("argc");
+    let phase = brute(session, 1, &mut argcgen, terminal)?;
+    if let Some(inp) = &phase.success {
+        let solved = inp.argv.clone();
+        stats.push("argc", phase);
+        return Ok(solved);
+    }
+    let cancelled = phase.cancelled;
+    stats.push("argc", phase);
+    if cancelled {
+        return Ok(Vec::new());
+    }
+    let dry_run = session.vars.contains_key("dry_run");
+    // a dry run scores every argc the same, one argument still shows
+    // the argv phases
+    let argc = if dry_run {
+        max_argc.min(1)
+    } else {
+        argcgen.get_length()
+    };
+    // unlike lengths, the cap is a plausible argc, so only warn
+    if argc > 0 && argc == max_argc {
+        warn!(
+            "argc settled on the max of {}, the target may take more (see max_argc)",
+            max_argc
+        );
+    }
 
     // check if there is something to be solved
     if argc > 0 {
         // solve argv length
-        let mut argvlengen = ArgvLenGenerator::new(argc, 0, 20);
-        brute(
-            path,
-            5,
-            &mut argvlengen,
-            solver,
-            terminal,
-            timeout,
-            vars.clone(),
-        )?;
-        let argvlens = argvlengen.get_lengths();
+        let mut argvlengen = ArgvLenGenerator::new(argc, lens.0, lens.1);
+        logging::set_phase("argv length");
+        let phase = brute(session, 5, &mut argvlengen, terminal)?;
+        if let Some(inp) = &phase.success {
+            let solved = inp.argv.clone();
+            stats.push("argv length", phase);
+            return Ok(solved);
+        }
+        let cancelled = phase.cancelled;
+        stats.push("argv length", phase);
+        if cancelled {
+            return Ok(Vec::new());
+        }
+        let argvlens = if dry_run {
+            vec![dry_run_len(lens); argc as usize]
+        } else {
+            argvlengen.get_lengths().clone()
+        };
+        if !dry_run {
+            for len in &argvlens {
+                check_len_cap(*len, lens)?;
+            }
+        }
 
         // solve argv values
-        let mut argvgen = ArgvGenerator::new(argc, argvlens, 0x20, 0x7e);
-        brute(
-            path,
-            5,
-            &mut argvgen,
-            solver,
-            terminal,
-            timeout,
-            vars.clone(),
-        )?;
+        let mut argvgen = ArgvGenerator::new(argc, &argvlens, charset.0, charset.1);
+        logging::set_phase("argv");
+        let phase = brute(session, 5, &mut argvgen, terminal)?;
+        let solved = match &phase.success {
+            Some(inp) => inp.argv.clone(),
+            None => argvgen.get_argv().clone(),
+        };
+        stats.push("argv", phase);
 
-        return Ok(argvgen.to_string());
+        return Ok(solved);
     }
-    Ok(String::new()) //TODO should be an error
+    Ok(Vec::new()) //TODO should be an error
 }
 
 // solves "default" stdin case
 fn default_stdin_brute<B: b7tui::Ui>(
-    path: &str,
-    solver: &InstCounter,
-    vars: HashMap<String, String>,
-    timeout: Duration,
+    session: &Session,
+    lens: (u32, u32),
+    charset: (u16, u16),
+    stats: &mut RunStats,
     terminal: &mut B,
-) -> Result<String, SolverError> {
+) -> Result<Vec<u8>, SolverError> {
     // solve stdin len
-    let mut lgen = StdinLenGenerator::new(0, 51);
-    brute(path, 1, &mut lgen, solver, terminal, timeout, vars.clone())?;
-    let stdinlen = lgen.get_length();
+    let pad = pad_byte(&session.vars)?;
+    let mut lgen = StdinLenGenerator::new(lens.0, lens.1);
+    lgen.set_padchr(pad);
+    logging::set_phase("stdin length");
+    let phase = brute(session, 1, &mut lgen, terminal)?;
+    if let Some(inp) = &phase.success {
+        let solved = inp.stdin.to_vec();
+        stats.push("stdin length", phase);
+        return Ok(solved);
+    }
+    let cancelled = phase.cancelled;
+    stats.push("stdin length", phase);
+    if cancelled {
+        return Ok(Vec::new());
+    }
+    let stdinlen = if session.vars.contains_key("dry_run") {
+        dry_run_len(lens)
+    } else {
+        let stdinlen = lgen.get_length();
+        check_len_cap(stdinlen, lens)?;
+        stdinlen
+    };
     // solve strin if there is stuff to solve
     if stdinlen > 0 {
-        // TODO: We should have a good way of configuring the range
         let empty = String::new();
-        let stdin_input = vars.get("start").unwrap_or(&empty);
+        let stdin_input = session.vars.get("start").unwrap_or(&empty);
         let mut gen = if stdin_input == "" {
-            StdinCharGenerator::new(stdinlen, 0x20, 0x7e)
+            StdinCharGenerator::new(stdinlen, charset.0, charset.1)
         } else {
-            StdinCharGenerator::new_start(stdinlen, 0x20, 0x7e, stdin_input.as_bytes())
+            StdinCharGenerator::new_start(stdinlen, charset.0, charset.1, stdin_input.as_bytes())
         };
-        brute(path, 1, &mut gen, solver, terminal, timeout, vars.clone())?;
+        gen.set_padchr(pad);
+        logging::set_phase("stdin");
+        let phase = brute(session, 1, &mut gen, terminal)?;
+        let solved = match &phase.success {
+            Some(inp) => inp.stdin.to_vec(),
+            None => gen.get_input().clone(),
+        };
+        stats.push("stdin", phase);
+
+        return Ok(solved);
+    }
+    Ok(Vec::new()) //TODO should be an error
+}
+
+// solves the contents of the input file, the same way as stdin
+fn default_file_brute<B: b7tui::Ui>(
+    session: &Session,
+    lens: (u32, u32),
+    charset: (u16, u16),
+    stats: &mut RunStats,
+    terminal: &mut B,
+) -> Result<Vec<u8>, SolverError> {
+    let file_contents = |inp: &Input| match &inp.file {
+        Some(file) => file.contents.to_vec(),
+        None => Vec::new(),
+    };
 
-        return Ok(gen.to_string());
+    let pad = pad_byte(&session.vars)?;
+    let mut lgen = FileLenGenerator::new(lens.0, lens.1);
+    lgen.set_padchr(pad);
+    logging::set_phase("file length");
+    let phase = brute(session, 1, &mut lgen, terminal)?;
+    if let Some(inp) = &phase.success {
+        let solved = file_contents(inp);
+        stats.push("file length", phase);
+        return Ok(solved);
     }
-    Ok(String::new()) //TODO should be an error
+    let cancelled = phase.cancelled;
+    stats.push("file length", phase);
+    if cancelled {
+        return Ok(Vec::new());
+    }
+    let filelen = if session.vars.contains_key("dry_run") {
+        dry_run_len(lens)
+    } else {
+        let filelen = lgen.get_length();
+        check_len_cap(filelen, lens)?;
+        filelen
+    };
+    if filelen == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut gen = FileCharGenerator::new(filelen, charset.0, charset.1);
+    gen.set_padchr(pad);
+    logging::set_phase("file");
+    let phase = brute(session, 1, &mut gen, terminal)?;
+    let solved = match &phase.success {
+        Some(inp) => file_contents(inp),
+        None => gen.get_input().clone(),
+    };
+    stats.push("file", phase);
+    Ok(solved)
 }
@@ -0,0 +1,154 @@
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use serde::Serialize;
+use std::cell::RefCell;
+use std::env;
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What the current thread is working on. Attached to every record
+/// written by the JSON logger; the brute loop and the process code
+/// fill it in as they go
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct LogContext {
+    pub phase: Option<String>,
+    pub round: Option<u32>,
+    pub candidate: Option<String>,
+    pub pid: Option<i32>,
+}
+
+thread_local! {
+    static CONTEXT: RefCell<LogContext> = RefCell::new(LogContext::default());
+}
+
+pub fn context() -> LogContext {
+    CONTEXT.with(|c| c.borrow().clone())
+}
+
+// replace the context, e.g. with one copied from the thread handing out work
+pub fn set_context(ctx: LogContext) {
+    CONTEXT.with(|c| *c.borrow_mut() = ctx);
+}
+
+pub fn update<F: FnOnce(&mut LogContext)>(f: F) {
+    CONTEXT.with(|c| f(&mut c.borrow_mut()));
+}
+
+pub fn set_phase(phase: &str) {
+    update(|c| {
+        c.phase = Some(phase.to_string());
+        c.round = None;
+        c.candidate = None;
+    });
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    timestamp: f64,
+    level: &'a str,
+    target: &'a str,
+    message: String,
+    #[serde(flatten)]
+    context: LogContext,
+}
+
+/// Logger writing one JSON object per line, for --log-format json
+pub struct JsonLogger {
+    level: LevelFilter,
+    out: Mutex<Box<dyn Write + Send>>,
+}
+
+impl JsonLogger {
+    pub fn new(level: LevelFilter, out: Box<dyn Write + Send>) -> JsonLogger {
+        JsonLogger {
+            level,
+            out: Mutex::new(out),
+        }
+    }
+
+    /// Install as the global logger. The level is taken from RUST_LOG
+    /// if it is a plain level like "debug", and is info otherwise
+    pub fn init(out: Box<dyn Write + Send>) -> Result<(), SetLoggerError> {
+        let level = env::var("RUST_LOG")
+            .ok()
+            .and_then(|l| l.parse().ok())
+            .unwrap_or(LevelFilter::Info);
+        log::set_boxed_logger(Box::new(JsonLogger::new(level, out)))?;
+        log::set_max_level(level);
+        Ok(())
+    }
+
+    fn format(&self, record: &Record) -> String {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as f64 + f64::from(d.subsec_micros()) / 1e6)
+            .unwrap_or(0.0);
+        let json = JsonRecord {
+            timestamp,
+            level: record.level().as_str(),
+            target: record.target(),
+            message: record.args().to_string(),
+            context: context(),
+        };
+        serde_json::to_string(&json).unwrap_or_default()
+    }
+}
+
+impl Log for JsonLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = self.format(record);
+        let mut out = self.out.lock().unwrap();
+        let _ = writeln!(out, "{}", line);
+    }
+
+    fn flush(&self) {
+        let _ = self.out.lock().unwrap().flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use log::Level;
+
+    #[test]
+    fn record_has_context() {
+        let logger = JsonLogger::new(LevelFilter::Info, Box::new(std::io::sink()));
+        set_phase("stdin");
+        update(|c| {
+            c.round = Some(3);
+            c.pid = Some(1234);
+        });
+        let line = logger.format(
+            &Record::builder()
+                .args(format_args!("inst_count: {}", 42))
+                .level(Level::Info)
+                .target("b7::brute")
+                .build(),
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "b7::brute");
+        assert_eq!(json["message"], "inst_count: 42");
+        assert_eq!(json["phase"], "stdin");
+        assert_eq!(json["round"], 3);
+        assert_eq!(json["pid"], 1234);
+        assert!(json["candidate"].is_null());
+        assert!(json["timestamp"].as_f64().unwrap() > 0.0);
+    }
+
+    #[test]
+    fn level_filter() {
+        let logger = JsonLogger::new(LevelFilter::Warn, Box::new(std::io::sink()));
+        assert!(logger.enabled(&Metadata::builder().level(Level::Error).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Info).build()));
+    }
+}
@@ -1,18 +1,19 @@
 #[macro_use]
 extern crate log;
 
-use b7::brute::InstCounter;
 use b7::*;
 
-use clap::{App, Arg};
+use clap::{App, AppSettings, Arg, SubCommand};
 use std::collections::HashMap;
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::time::Duration;
 
 fn handle_cli_args<'a>() -> clap::ArgMatches<'a> {
     App::new("B7")
         .version("0.1.0")
+        .setting(AppSettings::SubcommandsNegateReqs)
         .arg(
             Arg::with_name("binary")
                 .help("Binary to brute force input for")
@@ -24,7 +25,7 @@ fn handle_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .short("s")
                 .long("solver")
                 .value_name("solver")
-                .help("Sets which solver to use (default perf)")
+                .help("Sets which solver to use: perf, dynamorio, breakpoint, timing, harness or qemu (default perf)")
                 .takes_value(true),
         )
         .arg(
@@ -52,21 +53,434 @@ fn handle_cli_args<'a>() -> clap::ArgMatches<'a> {
                 .long("no-stdin")
                 .help("toggle running stdin checks"),
         )
+        .arg(
+            Arg::with_name("file")
+                .long("file")
+                .help("Also solve the contents of a file passed as the last argument"),
+        )
         .arg(
             Arg::with_name("dynpath")
                 .long("dynpath")
                 .help("Path to DynamoRio build folder")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("qemu")
+                .long("qemu")
+                .value_name("PATH")
+                .help("qemu-user binary for the qemu solver (default qemu-ARCH)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("qemu-arch")
+                .long("qemu-arch")
+                .value_name("ARCH")
+                .help("architecture of the target for the qemu solver, e.g. arm (default from the ELF header)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("qemu-plugin")
+                .long("qemu-plugin")
+                .value_name("LIB")
+                .help("QEMU's libinsn.so, for exact counts with the qemu solver")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("drclient")
+                .long("drclient")
+                .value_name("LIB")
+                .help("DynamoRio client to count with instead of libinscount.so")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("drregex")
+                .long("drregex")
+                .value_name("REGEX")
+                .help("regex matching the count in the client's output, the last group is the count")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min-len")
+                .long("min-len")
+                .value_name("N")
+                .help("Shortest input length to try when detecting lengths (default 0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-len")
+                .long("max-len")
+                .value_name("N")
+                .help(
+                    "Longest input length to try when detecting lengths \
+                     (default 20 for arguments, 51 for stdin and files)",
+                )
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("pin-cpu")
+                .long("pin-cpu")
+                .help("pin each target to a single CPU, so multithreaded counts vary less (needs the pin-cpu feature)"),
+        )
+        .arg(
+            Arg::with_name("max-parallel")
+                .long("max-parallel")
+                .value_name("N")
+                .help("run at most N targets at once (default: one per CPU)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-children")
+                .long("max-children")
+                .value_name("N")
+                .help("let at most N target processes exist at once (default: no limit)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cpu")
+                .long("cpu")
+                .value_name("N")
+                .help("pin targets to CPU N")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no-aslr")
+                .long("no-aslr")
+                .help("run targets with address space randomization disabled"),
+        )
+        .arg(
+            Arg::with_name("core-dumps")
+                .long("core-dumps")
+                .help("let crashing targets write core files"),
+        )
+        .arg(
+            Arg::with_name("run-as-user")
+                .long("run-as-user")
+                .takes_value(true)
+                .value_name("USER")
+                .help("run targets as USER, a name, uid or uid:gid (needs root)"),
+        )
+        .arg(
+            Arg::with_name("no-network")
+                .long("no-network")
+                .help("run targets without network access (needs root)"),
+        )
+        .arg(
+            Arg::with_name("pty")
+                .long("pty")
+                .help("run targets on a pseudo-terminal, for ones that read /dev/tty"),
+        )
+        .arg(
+            Arg::with_name("prompts")
+                .long("prompts")
+                .help("write stdin a line at a time, each after the target prints a prompt"),
+        )
+        .arg(
+            Arg::with_name("limit-memory")
+                .long("limit-memory")
+                .value_name("MB")
+                .help("limit the address space of every target run")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("limit-cpu")
+                .long("limit-cpu")
+                .value_name("SECS")
+                .help("limit the CPU time of every target run")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cwd")
+                .long("cwd")
+                .value_name("DIR")
+                .help("run the target in DIR, for targets that open files relative to it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("allow-fallback")
+                .long("allow-fallback")
+                .help("if perf counters are unavailable, measure CPU or wall clock time instead"),
+        )
+        .arg(
+            Arg::with_name("dry-run")
+                .long("dry-run")
+                .value_name("strict")
+                .help("--dry-run[=strict|commands]: print what would be run and exit, strict skips calibration runs, commands prints the command lines of the first round of each phase")
+                .takes_value(true)
+                .min_values(0)
+                .require_equals(true)
+                .possible_values(&["strict", "commands"]),
+        )
+        .arg(
+            Arg::with_name("cmp-addr")
+                .long("cmp-addr")
+                .value_name("addr")
+                .help("address of the comparison to count for the breakpoint solver")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .long("reference")
+                .value_name("count")
+                .help("instruction count of an input with no signal, used to flag uncertain bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("reference-margin")
+                .long("reference-margin")
+                .value_name("count")
+                .help("how far a winner must be from --reference to be trusted (default 0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tie-threshold")
+                .long("tie-threshold")
+                .value_name("count")
+                .help("report candidates this close to a winner as tied with it (default 0)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-backtrack")
+                .long("max-backtrack")
+                .value_name("n")
+                .help("retry the previous byte with its runner-up when no byte stands out, up to n times")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("syscall-diff")
+                .long("syscall-diff")
+                .help("log where the syscalls of each round's winner and runner-up diverge (slow)"),
+        )
+        .arg(
+            Arg::with_name("pad")
+                .long("pad")
+                .value_name("BYTE")
+                .help("byte to fill inputs with while detecting their length, e.g. 0x00 (default 0x41)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max-argc")
+                .long("max-argc")
+                .value_name("N")
+                .help("try at most N arguments when detecting argc (default 5)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("strip-newline")
+                .long("strip-newline")
+                .help("drop a trailing newline from the solved stdin"),
+        )
+        .arg(
+            Arg::with_name("memo")
+                .long("memo")
+                .value_name("path")
+                .help("keep measured counts in this file so a rerun doesn't measure them again")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output-dir")
+                .long("output-dir")
+                .value_name("dir")
+                .help("write the solved argv, stdin and file into this directory as raw bytes")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("heatmap")
+                .long("heatmap")
+                .value_name("path")
+                .help("write a PNG heatmap of the candidate counts (needs the heatmap feature)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stats")
+                .long("stats")
+                .help("print run statistics to stderr when done"),
+        )
+        .arg(
+            Arg::with_name("emit-repro")
+                .long("emit-repro")
+                .value_name("path")
+                .help("write a shell script that replays the solved input against the binary")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("emit-dict")
+                .long("emit-dict")
+                .value_name("path")
+                .help("write the solved input as an AFL/libFuzzer dictionary")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("emit-seeds")
+                .long("emit-seeds")
+                .value_name("dir")
+                .help("write the solved input and its runner-up variants as fuzzer seeds")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stdin-file")
+                .long("stdin-file")
+                .value_name("path")
+                .help("feed this file on stdin, with the solved bytes written over it at --stdin-offset")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("stdin-offset")
+                .long("stdin-offset")
+                .value_name("N")
+                .help("where in --stdin-file the solved bytes go (default 0)")
+                .takes_value(true)
+                .requires("stdin-file"),
+        )
+        .arg(
+            Arg::with_name("max-output")
+                .long("max-output")
+                .value_name("BYTES")
+                .help("keep at most this much of a target's stdout (default unlimited)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("discard-output")
+                .long("discard-output")
+                .help("send the target's stdout and stderr to /dev/null unless the solver reads them"),
+        )
+        .arg(
+            Arg::with_name("wrap")
+                .long("wrap")
+                .value_name("command")
+                .help("run the target through a command, e.g. 'stdbuf -o0'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("kill-policy")
+                .long("kill-policy")
+                .value_name("policy")
+                .help("kill, term:SECS to send SIGTERM first, or none for timed out targets (default kill)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .long("output")
+                .value_name("format")
+                .help("json to print the results, counts and timings to stdout (default none)")
+                .takes_value(true)
+                .possible_values(&["none", "json"]),
+        )
+        .arg(
+            Arg::with_name("log-format")
+                .long("log-format")
+                .value_name("format")
+                .help("plain, or json for one JSON object per log record (default plain)")
+                .takes_value(true)
+                .possible_values(&["plain", "json"]),
+        )
+        .arg(
+            Arg::with_name("log-file")
+                .long("log-file")
+                .value_name("path")
+                .help("write json logs here instead of stderr (default <binary>.log.json with the tui)")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("timeout")
                 .long("timeout")
-                .help("per-thread timeout to use when waiting for results, in seconds")
+                .value_name("SECS")
+                .help("how long a single run of the target may take, e.g. 0.5 (default 5)")
                 .takes_value(true),
         )
+        .subcommand(
+            SubCommand::with_name("daemon")
+                .about("run brute jobs submitted over a unix socket")
+                .arg(
+                    Arg::with_name("listen")
+                        .long("listen")
+                        .value_name("socket")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("jobs")
+                        .long("jobs")
+                        .help("how many jobs may run at once (default 1)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-children")
+                        .long("max-children")
+                        .value_name("N")
+                        .help("let the jobs together run at most N targets at once (default: no limit)")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("workdir")
+                        .long("workdir")
+                        .value_name("dir")
+                        .help("where per-job directories are created (default $TMPDIR/b7-daemon)")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("submit")
+                .about("send a job to a b7 daemon and print its results as JSON")
+                .arg(
+                    Arg::with_name("connect")
+                        .long("connect")
+                        .value_name("socket")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .value_name("job.toml")
+                        .help("versioned OptsConfig in TOML, or in JSON as written by B7Opts::config if it ends in .json")
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
         .get_matches()
 }
 
+fn run_daemon(matches: &clap::ArgMatches) -> Result<(), errors::SolverError> {
+    let socket = matches.value_of("listen").unwrap();
+    let workdir = match matches.value_of("workdir") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::temp_dir().join("b7-daemon"),
+    };
+    let jobs = matches.value_of("jobs").unwrap_or("1").parse()?;
+    let max_children = match matches.value_of("max-children") {
+        Some(max) => Some(max.parse()?),
+        None => None,
+    };
+
+    let _ = b7tui::Env::new();
+    // a socket left behind by an earlier daemon would fail the bind
+    let _ = std::fs::remove_file(socket);
+    let listener = std::os::unix::net::UnixListener::bind(socket)?;
+    info!("Listening on {}", socket);
+    let mut daemon = daemon::Daemon::new(workdir);
+    daemon.set_concurrency(jobs).set_max_children(max_children);
+    daemon.serve(listener)
+}
+
+fn run_submit(matches: &clap::ArgMatches) -> Result<(), errors::SolverError> {
+    let socket = matches.value_of("connect").unwrap();
+    let path = matches.value_of("config").unwrap();
+    let text = std::fs::read_to_string(path)?;
+    let job = if path.ends_with(".json") {
+        config::Versioned::<config::OptsConfig>::from_json(&text)?
+    } else {
+        config::Versioned::<config::OptsConfig>::from_toml(&text)?
+    };
+
+    let results = daemon::submit(Path::new(socket), job, |event| {
+        if let (Some(phase), Some(round)) = (&event.phase, event.round) {
+            eprintln!("{} round {}", phase, round);
+        }
+    })?;
+    println!("{}", config::Versioned::to_json(results)?);
+    Ok(())
+}
+
 fn print_usage(matches: &clap::ArgMatches) -> ! {
     println!("{}", matches.usage());
     exit(-1);
@@ -76,37 +490,266 @@ fn main() {
     // handle command line arguements
     let matches = handle_cli_args();
 
+    let subcommand = match matches.subcommand() {
+        ("daemon", Some(sub)) => Some(run_daemon(sub)),
+        ("submit", Some(sub)) => Some(run_submit(sub)),
+        _ => None,
+    };
+    if let Some(result) = subcommand {
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            exit(-1);
+        }
+        exit(0);
+    }
+
     let path = match matches.value_of("binary") {
         Some(a) => a,
         None => print_usage(&matches),
     };
+    // before anything, e.g. the cache file, is created next to it
+    if !Path::new(path).exists() {
+        eprintln!("target {} not found", path);
+        exit(-1);
+    }
 
     let argstate = matches.occurrences_of("argstate") < 1;
     let stdinstate = matches.occurrences_of("stdinstate") < 1;
+    let filestate = matches.is_present("file");
 
     let solvername = matches.value_of("solver").unwrap_or("perf");
-    let solver = match solvername {
-        "perf" => Box::new(perf::PerfSolver) as Box<InstCounter>,
-        "dynamorio" => Box::new(dynamorio::DynamorioSolver) as Box<InstCounter>,
-        _ => panic!("unknown solver"),
+    let solver = match solver_by_name(solvername) {
+        Some(solver) => solver,
+        None => panic!("unknown solver"),
     };
-    let timeout = Duration::new(
-        matches
-            .value_of("timeout")
-            .unwrap_or("5")
-            .parse()
-            .expect("Failed to parse duration!"),
-        0,
-    );
+    // fractions of a second are fine, fast targets rarely need a whole one
+    let timeout: f64 = matches
+        .value_of("timeout")
+        .unwrap_or("5")
+        .parse()
+        .expect("Failed to parse duration!");
+    // a Duration holds less than 2^64 seconds, and no inf or NaN
+    if !(timeout > 0.0 && timeout < std::u64::MAX as f64) {
+        eprintln!("--timeout must be a positive number of seconds");
+        print_usage(&matches);
+    }
+    let timeout = Duration::from_secs_f64(timeout);
+
+    let min_len: u32 = matches
+        .value_of("min-len")
+        .unwrap_or("0")
+        .parse()
+        .expect("Failed to parse min-len!");
+    let max_len: Option<u32> = matches
+        .value_of("max-len")
+        .map(|x| x.parse().expect("Failed to parse max-len!"));
+    if let Some(max_len) = max_len {
+        if min_len > max_len {
+            eprintln!(
+                "--min-len ({}) must not be larger than --max-len ({})",
+                min_len, max_len
+            );
+            print_usage(&matches);
+        }
+    }
 
     let stdin_input = matches.value_of("start").unwrap_or("");
     let mut vars = HashMap::new();
     let dynpath = matches.value_of("dynpath").unwrap_or("");
     vars.insert(String::from("dynpath"), String::from(dynpath));
+    for (arg, var) in &[
+        ("qemu", "qemu"),
+        ("qemu-arch", "qemu_arch"),
+        ("qemu-plugin", "qemu_plugin"),
+    ] {
+        if let Some(value) = matches.value_of(arg) {
+            vars.insert(String::from(*var), String::from(value));
+        }
+    }
+    if let Some(client) = matches.value_of("drclient") {
+        vars.insert(String::from("drclient"), String::from(client));
+    }
+    if let Some(regex) = matches.value_of("drregex") {
+        vars.insert(String::from("drregex"), String::from(regex));
+    }
     vars.insert(String::from("stdininput"), String::from(stdin_input));
+    if let Some(addr) = matches.value_of("cmp-addr") {
+        vars.insert(String::from("cmp_addr"), String::from(addr));
+    }
+    if let Some(reference) = matches.value_of("reference") {
+        vars.insert(String::from("reference"), String::from(reference));
+    }
+    if let Some(max) = matches.value_of("max-backtrack") {
+        vars.insert(String::from("max_backtrack"), String::from(max));
+    }
+    if let Some(threshold) = matches.value_of("tie-threshold") {
+        vars.insert(String::from("tie_threshold"), String::from(threshold));
+    }
+    if let Some(margin) = matches.value_of("reference-margin") {
+        vars.insert(String::from("reference_margin"), String::from(margin));
+    }
+    if matches.is_present("syscall-diff") {
+        vars.insert(String::from("syscall_diff"), String::from("1"));
+    }
+    if matches.is_present("pin-cpu") {
+        vars.insert(String::from("pin_cpu"), String::from("1"));
+    }
+    if let Some(jobs) = matches.value_of("max-parallel") {
+        let jobs: usize = jobs.parse().expect("Failed to parse max-parallel!");
+        vars.insert(String::from("max_parallel"), jobs.max(1).to_string());
+    }
+    if let Some(max) = matches.value_of("max-children") {
+        let max: usize = max.parse().expect("Failed to parse max-children!");
+        vars.insert(String::from("max_children"), max.max(1).to_string());
+    }
+    if let Some(cpu) = matches.value_of("cpu") {
+        vars.insert(String::from("cpu"), String::from(cpu));
+    }
+    if matches.is_present("no-aslr") {
+        vars.insert(String::from("no_aslr"), String::from("1"));
+    }
+    if matches.is_present("core-dumps") {
+        vars.insert(String::from("core_dumps"), String::from("1"));
+    }
+    if let Some(user) = matches.value_of("run-as-user") {
+        vars.insert(String::from("run_as"), String::from(user));
+    }
+    if matches.is_present("no-network") {
+        vars.insert(String::from("no_network"), String::from("1"));
+    }
+    if matches.is_present("pty") {
+        vars.insert(String::from("pty"), String::from("1"));
+    }
+    if matches.is_present("prompts") {
+        vars.insert(String::from("prompts"), String::from("1"));
+    }
+    if let Some(mb) = matches.value_of("limit-memory") {
+        let mb: u64 = mb.parse().expect("Failed to parse limit-memory!");
+        vars.insert(String::from("memory_limit"), (mb << 20).to_string());
+    }
+    if let Some(secs) = matches.value_of("limit-cpu") {
+        let secs: u64 = secs.parse().expect("Failed to parse limit-cpu!");
+        vars.insert(String::from("cpu_limit"), secs.to_string());
+    }
+    if let Some(path) = matches.value_of("stdin-file") {
+        vars.insert(String::from("stdin_file"), String::from(path));
+    }
+    if let Some(offset) = matches.value_of("stdin-offset") {
+        let offset: u64 = offset.parse().expect("Failed to parse stdin-offset!");
+        vars.insert(String::from("stdin_offset"), offset.to_string());
+    }
+    if let Some(bytes) = matches.value_of("max-output") {
+        let bytes: usize = bytes.parse().expect("Failed to parse max-output!");
+        vars.insert(String::from("max_output"), bytes.to_string());
+    }
+    if matches.is_present("discard-output") {
+        vars.insert(String::from("stdout_mode"), String::from("null"));
+        vars.insert(String::from("stderr_mode"), String::from("null"));
+    }
+    if let Some(wrap) = matches.value_of("wrap") {
+        vars.insert(String::from("wrap"), String::from(wrap));
+    }
+    if let Some(policy) = matches.value_of("kill-policy") {
+        vars.insert(String::from("kill_policy"), String::from(policy));
+    }
+    if let Some(max) = matches.value_of("max-argc") {
+        vars.insert(String::from("max_argc"), String::from(max));
+    }
+    if let Some(pad) = matches.value_of("pad") {
+        vars.insert(String::from("pad"), String::from(pad));
+    }
+    if let Some(dir) = matches.value_of("cwd") {
+        vars.insert(String::from("cwd"), String::from(dir));
+    }
+    if matches.is_present("allow-fallback") {
+        vars.insert(String::from("allow_fallback"), String::from("1"));
+    }
 
     let terminal = String::from(matches.value_of("ui").unwrap_or("tui")).to_lowercase();
 
+    if matches.value_of("log-format") == Some("json") {
+        // the tui owns the terminal, so its logs always go to a file
+        let log_file = match matches.value_of("log-file") {
+            Some(file) => Some(String::from(file)),
+            None if terminal == "tui" => Some(format!("{}.log.json", path)),
+            None => None,
+        };
+        let out: Box<dyn std::io::Write + Send> = match log_file {
+            Some(file) => {
+                Box::new(std::fs::File::create(file).expect("log file cannot be created"))
+            }
+            None => Box::new(std::io::stderr()),
+        };
+        logging::JsonLogger::init(out).expect("Failed to install the json logger!");
+    }
+
+    if matches.value_of("dry-run") == Some("commands") {
+        let results = B7Opts::new(
+            path.to_string(),
+            argstate,
+            stdinstate,
+            solver,
+            &mut b7tui::Env::new(),
+            vars,
+            timeout,
+        )
+        .set_min_len(min_len)
+        .set_max_len(max_len)
+        .set_file_brute(filestate)
+        .set_dry_run(true)
+        .run();
+        match results {
+            Ok(results) => {
+                for phase in &results.stats.phases {
+                    println!("{}:", phase.name);
+                    for command in &phase.commands {
+                        println!("  {}", command);
+                    }
+                }
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(-1);
+            }
+        }
+    }
+
+    if matches.is_present("dry-run") {
+        let calibration_runs = match matches.value_of("dry-run") {
+            Some("strict") => 0,
+            _ => 3,
+        };
+        let plan = B7Opts::new(
+            path.to_string(),
+            argstate,
+            stdinstate,
+            solver,
+            &mut b7tui::Env::new(),
+            vars,
+            timeout,
+        )
+        .set_min_len(min_len)
+        .set_max_len(max_len)
+        .set_file_brute(filestate)
+        .plan(calibration_runs);
+        match plan {
+            Ok(plan) => {
+                println!("{}", plan);
+                exit(0);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                exit(-1);
+            }
+        }
+    }
+
+    let heatmap = matches.value_of("heatmap").map(PathBuf::from);
+    let memo = matches.value_of("memo").map(PathBuf::from);
+    let output = matches.value_of("output-dir").map(PathBuf::from);
+    let strip_newline = matches.is_present("strip-newline");
+
     let mut file = std::fs::OpenOptions::new()
         .write(true)
         .create(true)
@@ -120,9 +763,16 @@ fn main() {
             stdinstate,
             solver,
             &mut b7tui::Tui::new(Some(String::from(path))),
-            vars,
+            vars.clone(),
             timeout,
         )
+        .set_min_len(min_len)
+        .set_max_len(max_len)
+        .set_heatmap(heatmap)
+        .set_output_path(output)
+        .set_memo_file(memo)
+        .set_strip_trailing_newline(strip_newline)
+        .set_file_brute(filestate)
         .run(),
         "env" => B7Opts::new(
             path.to_string(),
@@ -130,13 +780,28 @@ fn main() {
             stdinstate,
             solver,
             &mut b7tui::Env::new(),
-            vars,
+            vars.clone(),
             timeout,
         )
+        .set_min_len(min_len)
+        .set_max_len(max_len)
+        .set_heatmap(heatmap)
+        .set_output_path(output)
+        .set_memo_file(memo)
+        .set_strip_trailing_newline(strip_newline)
+        .set_file_brute(filestate)
         .run(),
         _ => panic!("unknown tui {}", terminal),
     };
 
+    let results = match results {
+        Ok(x) => x,
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(-1);
+        }
+    };
+
     if !results.arg_brute.is_empty() {
         info!("Writing argv to cache");
         write!(file, "argv: {}", results.arg_brute).expect("Failed to write argv to cache!");
@@ -146,4 +811,57 @@ fn main() {
         info!("Writing stdin to cache");
         write!(file, "stdin: {}", results.stdin_brute).expect("Failed to write stdin to cache!");
     };
+
+    if !results.file_brute.is_empty() {
+        info!("Writing file to cache");
+        write!(file, "file: {}", results.file_brute).expect("Failed to write file to cache!");
+    };
+
+    if let Some(repro) = matches.value_of("emit-repro") {
+        if let Err(e) = repro::write_script(
+            Path::new(repro),
+            Path::new(path),
+            solvername,
+            &vars,
+            &results,
+        ) {
+            eprintln!("Failed to write {}: {}", repro, e);
+            exit(-1);
+        }
+    }
+
+    if let Some(dict) = matches.value_of("emit-dict") {
+        if let Err(e) = fuzz::write_dictionary(Path::new(dict), &results) {
+            eprintln!("Failed to write {}: {}", dict, e);
+            exit(-1);
+        }
+    }
+
+    if let Some(seeds) = matches.value_of("emit-seeds") {
+        match fuzz::write_seeds(Path::new(seeds), &results) {
+            Ok(n) => info!("Wrote {} seeds to {}", n, seeds),
+            Err(e) => {
+                eprintln!("Failed to write seeds to {}: {}", seeds, e);
+                exit(-1);
+            }
+        }
+    }
+
+    if matches.is_present("stats") {
+        let fallback = results.fallback.as_ref().map(String::as_str);
+        for line in results.stats.report(fallback) {
+            eprintln!("{}", line);
+        }
+    }
+
+    // last, so the results are the only thing on stdout
+    if matches.value_of("output") == Some("json") {
+        match config::Versioned::to_json(results) {
+            Ok(json) => println!("{}", json),
+            Err(e) => {
+                eprintln!("Failed to serialize results: {}", e);
+                exit(-1);
+            }
+        }
+    }
 }
@@ -0,0 +1,433 @@
+use crate::brute::{InstCountData, InstCounter};
+use crate::errors::*;
+use crate::generators::{FileInput, Input};
+use crate::process::ProcessTemplate;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// first line of a memo file, followed by the target's fingerprint
+const MEMO_HEADER: &str = "b7-memo 3";
+
+/// Instruction counts already measured in this session, shared by every
+/// phase so that an input is only ever run once per solver. Counts are
+/// keyed by a sha256 of the target, the input, the solver name and the
+/// vars that configure the solver and the run, since those all change
+/// the count
+#[derive(Debug, Default)]
+pub struct Memo {
+    counts: Mutex<HashMap<u64, i64>>,
+    // sha256 of the target's path and contents, see `for_target`
+    target: Vec<u8>,
+}
+
+// vars that only steer the search, so changing them between sessions
+// keeps the counts. Every other var is part of the key
+const SEARCH_VARS: &[&str] = &[
+    "dry_run",
+    "max_argc",
+    "max_backtrack",
+    "max_children",
+    "max_parallel",
+    "pad",
+    "reference",
+    "reference_margin",
+    "start",
+    "syscall_diff",
+    "tie_threshold",
+];
+
+// feed a length and then the bytes, so fields can't run into each other
+fn hash_field(hasher: &mut Sha256, bytes: &[u8]) {
+    hasher.input(&(bytes.len() as u64).to_le_bytes());
+    hasher.input(bytes);
+}
+
+impl Memo {
+    pub fn new() -> Memo {
+        Memo {
+            counts: Mutex::new(HashMap::new()),
+            target: Vec::new(),
+        }
+    }
+
+    /// Empty memo for the target at `path`. Its keys change with the
+    /// path and the contents of the target, so a rebuilt target isn't
+    /// given the counts of the old one. A missing target, e.g. for a
+    /// solver that doesn't run a file, is identified by its path alone
+    pub fn for_target(path: &str) -> Memo {
+        let mut hasher = Sha256::new();
+        hash_field(&mut hasher, path.as_bytes());
+        if let Ok(contents) = fs::read(path) {
+            hash_field(&mut hasher, &contents);
+        }
+        Memo {
+            counts: Mutex::new(HashMap::new()),
+            target: hasher.result().to_vec(),
+        }
+    }
+
+    /// Hex sha256 identifying the target, written to memo files
+    pub fn target_hash(&self) -> String {
+        self.target.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    pub fn key(&self, solver: &str, vars: &HashMap<String, String>, inp: &Input) -> u64 {
+        let mut hasher = Sha256::new();
+        hash_field(&mut hasher, &self.target);
+        hash_field(&mut hasher, solver.as_bytes());
+        let mut vars: Vec<_> = vars
+            .iter()
+            .filter(|(name, _)| !SEARCH_VARS.contains(&name.as_str()))
+            .collect();
+        vars.sort();
+        hasher.input(&(vars.len() as u64).to_le_bytes());
+        for (name, value) in vars {
+            hash_field(&mut hasher, name.as_bytes());
+            hash_field(&mut hasher, value.as_bytes());
+        }
+        hasher.input(&(inp.argv.len() as u64).to_le_bytes());
+        for arg in &inp.argv {
+            hash_field(&mut hasher, arg);
+        }
+        hash_field(&mut hasher, &inp.stdin);
+        hasher.input(&(inp.envp.len() as u64).to_le_bytes());
+        for (key, value) in &inp.envp {
+            hash_field(&mut hasher, key);
+            hash_field(&mut hasher, value);
+        }
+        match &inp.file {
+            Some(file) => hash_field(&mut hasher, &file.contents),
+            None => hasher.input(&[0xff]),
+        }
+        let mut key = [0; 8];
+        key.copy_from_slice(&hasher.result()[..8]);
+        u64::from_le_bytes(key)
+    }
+
+    pub fn get(&self, key: u64) -> Option<i64> {
+        self.counts.lock().unwrap().get(&key).cloned()
+    }
+
+    pub fn insert(&self, key: u64, count: i64) {
+        self.counts.lock().unwrap().insert(key, count);
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Read counts written by `save` into a memo for the target at
+    /// `target`. A missing file is an empty memo, so the first run of a
+    /// session can use the same path, and so is a file written for a
+    /// different target or build of it
+    pub fn load(path: &Path, target: &str) -> Result<Memo, SolverError> {
+        let memo = Memo::for_target(target);
+        if !path.exists() {
+            return Ok(memo);
+        }
+        let contents = fs::read_to_string(path)?;
+        let mut lines = contents.lines();
+        let header = lines.next().unwrap_or("");
+        let hash = match header.rfind(' ') {
+            Some(end) if &header[..end] == MEMO_HEADER => &header[end + 1..],
+            _ => {
+                return Err(SolverError::new(
+                    Runner::IoError,
+                    &format!(
+                        "{} is not a memo file of this b7 version, remove it to start over",
+                        path.display()
+                    ),
+                ))
+            }
+        };
+        if hash != memo.target_hash() {
+            warn!(
+                "Memo file {} is for another target or build of it, ignoring it",
+                path.display()
+            );
+            return Ok(memo);
+        }
+        for line in lines {
+            let mut parts = line.split_whitespace();
+            let entry = match (parts.next(), parts.next()) {
+                (Some(key), Some(count)) => u64::from_str_radix(key, 16)
+                    .ok()
+                    .and_then(|key| count.parse().ok().map(|count| (key, count))),
+                _ => None,
+            };
+            match entry {
+                Some((key, count)) => memo.insert(key, count),
+                None => {
+                    return Err(SolverError::new(
+                        Runner::IoError,
+                        &format!("bad line in memo file: {}", line),
+                    ))
+                }
+            }
+        }
+        Ok(memo)
+    }
+
+    // the header with the target's hash, then one "key count" line per
+    // input
+    pub fn save(&self, path: &Path) -> Result<(), SolverError> {
+        let mut out = format!("{} {}\n", MEMO_HEADER, self.target_hash());
+        for (key, count) in self.counts.lock().unwrap().iter() {
+            out.push_str(&format!("{:016x} {}\n", key, count));
+        }
+        fs::write(path, out)?;
+        Ok(())
+    }
+}
+
+// what a count depends on besides the solver
+type CountKey = (
+    String,
+    Vec<Vec<u8>>,
+    Arc<[u8]>,
+    Vec<(Vec<u8>, Vec<u8>)>,
+    Option<FileInput>,
+);
+
+/// Wraps any solver and remembers its count for every (path, argv,
+/// stdin) it was asked about, so an input is only measured once even
+/// when used outside of `B7Opts`, whose memo does the same by hash
+pub struct CachingCounter<C: InstCounter> {
+    inner: C,
+    counts: Mutex<HashMap<CountKey, i64>>,
+    clear_each_phase: bool,
+}
+
+impl<C: InstCounter> CachingCounter<C> {
+    pub fn new(inner: C) -> CachingCounter<C> {
+        CachingCounter {
+            inner,
+            counts: Mutex::new(HashMap::new()),
+            clear_each_phase: false,
+        }
+    }
+
+    /// Forget all counts when a brute phase starts, for solvers whose
+    /// count of the same bytes depends on what the phase is solving
+    pub fn set_clear_each_phase(&mut self, clear: bool) -> &mut Self {
+        self.clear_each_phase = clear;
+        self
+    }
+
+    pub fn clear(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.counts.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.inner
+    }
+}
+
+impl<C: InstCounter> InstCounter for CachingCounter<C> {
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let key = (
+            data.path.clone(),
+            data.inp.argv.clone(),
+            data.inp.stdin.clone(),
+            data.inp.envp.clone(),
+            data.inp.file.clone(),
+        );
+        if let Some(&count) = self.counts.lock().unwrap().get(&key) {
+            return Ok(count);
+        }
+        // not locked while measuring, so workers run in parallel. Errors
+        // like timeouts are not cached, the next try may succeed
+        let count = self.inner.get_inst_count(data)?;
+        self.counts.lock().unwrap().insert(key, count);
+        Ok(count)
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.inner.setup(path, vars)
+    }
+
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.inner.template()
+    }
+
+    fn fallback(&self) -> Option<String> {
+        self.inner.fallback()
+    }
+
+    fn start_phase(&self) {
+        if self.clear_each_phase {
+            self.clear();
+        }
+        self.inner.start_phase();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CachingCounter, Memo};
+    use crate::brute::{InstCountData, InstCounter};
+    use crate::errors::SolverError;
+    use crate::generators::Input;
+    use crate::process::ProcessTemplate;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // counts how often it was asked
+    #[derive(Default)]
+    struct Runs(AtomicUsize);
+
+    impl InstCounter for Runs {
+        fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(data.inp.stdin.len() as i64)
+        }
+    }
+
+    // a solver that runs the target through another program
+    struct Wrapped(ProcessTemplate);
+
+    impl InstCounter for Wrapped {
+        fn get_inst_count(&self, _data: &InstCountData) -> Result<i64, SolverError> {
+            Ok(0)
+        }
+
+        fn template(&self) -> Option<&ProcessTemplate> {
+            Some(&self.0)
+        }
+    }
+
+    fn data(stdin: &str) -> InstCountData {
+        InstCountData::builder("./target")
+            .input(Input::builder().stdin(stdin).build())
+            .build()
+    }
+
+    #[test]
+    fn caching_counter() {
+        let mut counter = CachingCounter::new(Runs::default());
+        assert_eq!(counter.get_inst_count(&data("abc")).unwrap(), 3);
+        assert_eq!(counter.get_inst_count(&data("abc")).unwrap(), 3);
+        assert_eq!(counter.get_inst_count(&data("abcd")).unwrap(), 4);
+        assert_eq!(counter.inner().0.load(Ordering::SeqCst), 2);
+        assert_eq!(counter.len(), 2);
+
+        // kept across phases unless asked otherwise
+        counter.start_phase();
+        assert_eq!(counter.len(), 2);
+        counter.set_clear_each_phase(true).start_phase();
+        assert!(counter.is_empty());
+        counter.get_inst_count(&data("abc")).unwrap();
+        assert_eq!(counter.inner().0.load(Ordering::SeqCst), 3);
+    }
+
+    // dry runs and repro scripts show the inner solver's command
+    #[test]
+    fn caching_counter_template() {
+        let mut template = ProcessTemplate::new("/opt/dr/bin64/drrun");
+        template.arg("./target");
+        let counter = CachingCounter::new(Wrapped(template));
+        let shown = format!("{:?}", counter.template().unwrap());
+        assert!(shown.contains("drrun"));
+        assert!(CachingCounter::new(Runs::default()).template().is_none());
+    }
+
+    fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn keys() {
+        let memo = Memo::new();
+        let none = HashMap::new();
+        let a = Input::new(vec![], b"AAAA".to_vec());
+        let b = Input::new(vec![], b"AAAB".to_vec());
+        assert_eq!(memo.key("perf", &none, &a), memo.key("perf", &none, &a));
+        assert_ne!(memo.key("perf", &none, &a), memo.key("perf", &none, &b));
+        assert_ne!(
+            memo.key("perf", &none, &a),
+            memo.key("perf", &vars(&[("pin_cpu", "")]), &a)
+        );
+        assert_ne!(
+            memo.key("perf", &none, &a),
+            memo.key("dynamorio", &none, &a)
+        );
+        // fields can't shift into each other
+        let split = Input::new(vec![b"ab".to_vec(), b"c".to_vec()], vec![]);
+        let joined = Input::new(vec![b"a".to_vec(), b"bc".to_vec()], vec![]);
+        assert_ne!(
+            memo.key("perf", &none, &split),
+            memo.key("perf", &none, &joined)
+        );
+
+        // stable across builds of b7, since keys are saved
+        assert_eq!(memo.key("perf", &none, &a), 0x53a9_99cb_2c89_60ca);
+
+        // the target is part of the key
+        let other = Memo::for_target("/bin/true");
+        assert_ne!(memo.key("perf", &none, &a), other.key("perf", &none, &a));
+    }
+
+    // a count measured with another solver config is another count
+    #[test]
+    fn keys_vars() {
+        let memo = Memo::new();
+        let a = Input::new(vec![], b"AAAA".to_vec());
+        let cmp = vars(&[("cmp_addr", "0x401000")]);
+        let key = memo.key("breakpoint", &cmp, &a);
+        memo.insert(key, 7);
+
+        let other_cmp = vars(&[("cmp_addr", "0x402000")]);
+        assert_eq!(memo.get(memo.key("breakpoint", &other_cmp, &a)), None);
+        let drclient = vars(&[("cmp_addr", "0x401000"), ("drclient", "./libcount.so")]);
+        assert_eq!(memo.get(memo.key("breakpoint", &drclient, &a)), None);
+
+        // vars that only steer the search keep it
+        let parallel = vars(&[("cmp_addr", "0x401000"), ("max_parallel", "2")]);
+        assert_eq!(memo.get(memo.key("breakpoint", &parallel, &a)), Some(7));
+    }
+
+    #[test]
+    fn save_load() {
+        let path = std::env::temp_dir().join(format!("b7-memo-unit-{}", std::process::id()));
+        let memo = Memo::for_target("/bin/true");
+        memo.insert(1, 100);
+        memo.insert(u64::max_value(), -5);
+        memo.save(&path).unwrap();
+
+        let loaded = Memo::load(&path, "/bin/true").unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.get(1), Some(100));
+        assert_eq!(loaded.get(u64::max_value()), Some(-5));
+        assert_eq!(loaded.get(2), None);
+
+        // counts of another target are dropped
+        assert!(Memo::load(&path, "/bin/false").unwrap().is_empty());
+
+        // as are files without the versioned header, with an error
+        std::fs::write(&path, "0000000000000001 100\n").unwrap();
+        assert!(Memo::load(&path, "/bin/true").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
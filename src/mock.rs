@@ -0,0 +1,67 @@
+use crate::brute::{InstCountData, InstCounter};
+use crate::errors::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Solver that never runs the target, for testing code built on B7.
+/// The flag's stdin length stands out, and every leading byte that
+/// matches the flag raises the count, so B7 recovers the flag exactly
+#[derive(Clone, Debug)]
+pub struct MockSolver {
+    flag: Vec<u8>,
+    // score the input file's contents instead of stdin
+    file: bool,
+    // shared by clones, so a boxed copy can still be counted
+    runs: Arc<AtomicUsize>,
+}
+
+impl MockSolver {
+    pub fn new(flag: &[u8]) -> MockSolver {
+        MockSolver {
+            flag: flag.to_vec(),
+            file: false,
+            runs: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Like `new`, but the flag is the contents of the input file, see
+    /// `B7Opts::set_file_brute`
+    pub fn for_file(flag: &[u8]) -> MockSolver {
+        MockSolver {
+            file: true,
+            ..MockSolver::new(flag)
+        }
+    }
+
+    /// How many counts this solver and its clones were asked for
+    pub fn runs(&self) -> usize {
+        self.runs.load(Ordering::SeqCst)
+    }
+}
+
+impl InstCounter for MockSolver {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        self.runs.fetch_add(1, Ordering::SeqCst);
+        let inp = if self.file {
+            match &data.inp.file {
+                Some(file) => &file.contents[..],
+                None => return Ok(0),
+            }
+        } else {
+            &data.inp.stdin[..]
+        };
+        if inp.len() != self.flag.len() {
+            return Ok(inp.len() as i64);
+        }
+        let correct = inp
+            .iter()
+            .zip(&self.flag)
+            .take_while(|(a, b)| a == b)
+            .count();
+        Ok(100 + 10 * correct as i64)
+    }
+}
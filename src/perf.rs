@@ -1,16 +1,19 @@
 use crate::bindings::*;
 use crate::brute::*;
 use crate::errors::*;
-use crate::process::Process;
-use libc::{c_int, c_void, ioctl, pid_t, syscall};
-use std::ffi::OsStr;
-use std::fs::File;
+use crate::process::{Process, ProcessTemplate};
+use crate::timing::TimingSolver;
+use libc::{c_void, ioctl, pid_t, syscall};
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
 use std::mem;
-use std::os::unix::ffi::OsStrExt;
-use std::os::unix::io::FromRawFd;
-
-// syscall number for perf syscall
-const PERF_EVENT_OPEN_SYSCALL: i64 = 298;
+use std::os::unix::io::{AsRawFd, FromRawFd};
+use std::rc::Rc;
+use std::time::Duration;
 
 // initiliaze perf on a process
 fn perf_event_open(
@@ -20,40 +23,234 @@ fn perf_event_open(
     group_fd: i32,
     flags: u64,
 ) -> i32 {
-    unsafe { syscall(PERF_EVENT_OPEN_SYSCALL, hw_event, pid, cpu, group_fd, flags) as i32 }
+    unsafe {
+        syscall(
+            libc::SYS_perf_event_open,
+            hw_event,
+            pid,
+            cpu,
+            group_fd,
+            flags,
+        ) as i32
+    }
+}
+
+/// The perf_event_attr used for every run. It never changes between
+/// runs, so it is built once in `PerfSolver::setup` and shared
+#[derive(Copy, Clone)]
+pub struct PreparedAttr {
+    attr: perf_event_attr,
 }
 
-// perform struct setup and clear the perf file descriptor
-fn get_perf_fd(pid: pid_t) -> Result<i32, SolverError> {
-    let mut pe: perf_event_attr = unsafe { mem::zeroed() };
+impl PreparedAttr {
+    pub fn new() -> PreparedAttr {
+        PreparedAttr::with_event(
+            perf_type_id_PERF_TYPE_HARDWARE,
+            u64::from(perf_hw_id_PERF_COUNT_HW_INSTRUCTIONS),
+        )
+    }
+
+    /// CPU time in nanoseconds instead of instructions. A software
+    /// event, so it works where hardware counters are not exposed
+    pub fn task_clock() -> PreparedAttr {
+        PreparedAttr::with_event(
+            perf_type_id_PERF_TYPE_SOFTWARE,
+            u64::from(perf_sw_ids_PERF_COUNT_SW_TASK_CLOCK),
+        )
+    }
 
-    // perf struct setup
-    pe.type_ = perf_type_id_PERF_TYPE_HARDWARE;
-    pe.size = mem::size_of::<perf_event_attr>() as u32;
-    pe.config = u64::from(perf_hw_id_PERF_COUNT_HW_INSTRUCTIONS);
-    pe.set_disabled(1);
-    pe.set_exclude_kernel(1);
-    pe.set_exclude_hv(1);
-    pe.set_exclude_idle(1);
-    pe.set_exclude_callchain_kernel(1);
+    fn with_event(type_: u32, config: u64) -> PreparedAttr {
+        let mut pe: perf_event_attr = unsafe { mem::zeroed() };
+
+        // perf struct setup
+        pe.type_ = type_;
+        pe.size = mem::size_of::<perf_event_attr>() as u32;
+        pe.config = config;
+        pe.set_disabled(1);
+        pe.set_exclude_kernel(1);
+        pe.set_exclude_hv(1);
+        pe.set_exclude_idle(1);
+        pe.set_exclude_callchain_kernel(1);
+
+        PreparedAttr { attr: pe }
+    }
+}
+
+impl Default for PreparedAttr {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Whether perf counters can be opened here, found by `probe`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerfProbe {
+    /// Contents of /proc/sys/kernel/perf_event_paranoid, if readable
+    pub paranoid: Option<i32>,
+    /// errno of opening the instruction counter, or None if it opened
+    pub hardware: Option<i32>,
+    /// Same for the task-clock software event
+    pub software: Option<i32>,
+}
+
+/// Try to open both kinds of counter on B7 itself, which is allowed
+/// exactly when it is allowed on the targets
+pub fn probe() -> PerfProbe {
+    let try_open = |attr: PreparedAttr| {
+        let fd = perf_event_open(&attr.attr as *const perf_event_attr, 0, -1, -1, 0);
+        if fd == -1 {
+            return io::Error::last_os_error().raw_os_error();
+        }
+        drop(unsafe { File::from_raw_fd(fd) });
+        None
+    };
+    PerfProbe {
+        paranoid: fs::read_to_string("/proc/sys/kernel/perf_event_paranoid")
+            .ok()
+            .and_then(|s| s.trim().parse().ok()),
+        hardware: try_open(PreparedAttr::new()),
+        software: try_open(PreparedAttr::task_clock()),
+    }
+}
 
-    let fd = perf_event_open(&pe as *const perf_event_attr, pid, -1, -1, 0);
+impl PerfProbe {
+    /// What the user can do about a failed probe
+    pub fn advice(&self) -> String {
+        match self.hardware {
+            None => String::from("perf counters are available"),
+            Some(libc::EACCES) => format!(
+                "perf_event_open was denied (perf_event_paranoid is {}). Allow it with \
+                 `sudo sysctl kernel.perf_event_paranoid=1`, or give b7 the capability with \
+                 `sudo setcap cap_perfmon,cap_sys_ptrace+ep $(which b7)`",
+                self.paranoid
+                    .map_or_else(|| String::from("unknown"), |p| p.to_string())
+            ),
+            Some(libc::EPERM) => String::from(
+                "perf_event_open is blocked, probably by a container's seccomp profile. \
+                 Run the container with `--cap-add PERFMON` or a profile that allows \
+                 perf_event_open",
+            ),
+            Some(libc::ENOENT) | Some(libc::ENODEV) | Some(libc::EOPNOTSUPP) => String::from(
+                "this CPU or VM exposes no instruction counter. Enable the PMU for the VM, \
+                 or use --allow-fallback or another solver",
+            ),
+            Some(errno) => format!(
+                "perf_event_open failed: {}",
+                io::Error::from_raw_os_error(errno)
+            ),
+        }
+    }
+}
+
+/// What the perf solver measures with, decided in `PerfSolver::setup`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PerfMode {
+    Instructions,
+    /// Hardware counters are unavailable, CPU time from task-clock instead
+    TaskClock,
+    /// No perf at all, wall clock time as measured by the timing solver
+    Timing,
+}
+
+// pick a mode for the probe, or explain why there is none. Falling back
+// changes what is measured, so it has to be asked for
+fn decide(probe: &PerfProbe, allow_fallback: bool) -> Result<PerfMode, SolverError> {
+    if probe.hardware.is_none() {
+        return Ok(PerfMode::Instructions);
+    }
+    if !allow_fallback {
+        return Err(SolverError::new(Runner::IoError, &probe.advice()));
+    }
+    warn!("{}", probe.advice());
+    if probe.software.is_none() {
+        warn!("Falling back to the task-clock software event");
+        Ok(PerfMode::TaskClock)
+    } else {
+        warn!("Falling back to the timing solver");
+        Ok(PerfMode::Timing)
+    }
+}
+
+// open perf on the process and clear the perf file descriptor. The
+// File owns the fd, so it is closed on every path out of a run
+fn get_perf_fd(attr: &PreparedAttr, pid: pid_t) -> Result<File, SolverError> {
+    let fd = perf_event_open(&attr.attr as *const perf_event_attr, pid, -1, -1, 0);
     if fd == -1 {
         return Err(SolverError::new(Runner::IoError, "perf_event_open failed!"));
     }
+    let file = unsafe { File::from_raw_fd(fd) };
 
     // reset perf to make sure it is zero
     unsafe {
         ioctl(fd, 9219, 0); // PERF_EVENT_IOC_RESET
         ioctl(fd, 9216, 0); // PERF_EVENT_IOC_ENABLE
     }
-    Ok(fd)
+    Ok(file)
+}
+
+thread_local! {
+    // The counters of this worker thread, one per event. Each is opened
+    // with inherit and enable_on_exec, so every child the thread forks
+    // gets a copy that starts counting at its exec and is added to the
+    // thread's counter when the child exits. A run's count is how much
+    // the counter grew, which saves opening, resetting, enabling and
+    // closing a counter for every run
+    static COUNTERS: RefCell<Vec<((u32, u64), Rc<File>)>> = RefCell::new(Vec::new());
+}
+
+// this thread's inherited counter for `attr`, None if the kernel won't
+// open one
+fn thread_counter(attr: &PreparedAttr) -> Option<Rc<File>> {
+    let event = (attr.attr.type_, attr.attr.config);
+    COUNTERS.with(|counters| {
+        let mut counters = counters.borrow_mut();
+        if let Some(counter) = counters.iter().find(|c| c.0 == event) {
+            return Some(counter.1.clone());
+        }
+        let mut inherited = attr.attr;
+        inherited.set_inherit(1);
+        inherited.set_enable_on_exec(1);
+        let fd = perf_event_open(&inherited as *const perf_event_attr, 0, -1, -1, 0);
+        if fd == -1 {
+            return None;
+        }
+        let counter = Rc::new(unsafe { File::from_raw_fd(fd) });
+        counters.push((event, counter.clone()));
+        Some(counter)
+    })
+}
+
+// A failed run may leave its child behind, whose count would end up in
+// the next run's. The thread opens a new counter instead
+fn drop_thread_counter(attr: &PreparedAttr) {
+    let event = (attr.attr.type_, attr.attr.config);
+    COUNTERS.with(|counters| counters.borrow_mut().retain(|c| c.0 != event));
+}
+
+// Run `process` on the thread's counter. It counts the processes the
+// target forks too, unlike a counter opened on the target itself
+fn counted_run(
+    counter: &File,
+    attr: &PreparedAttr,
+    process: Process,
+    timeout: Duration,
+) -> Result<i64, SolverError> {
+    let run = || -> Result<i64, SolverError> {
+        let before = perf_get_inst_count(counter)?;
+        process.spawn()?.finish(timeout)?;
+        Ok(perf_get_inst_count(counter)? - before)
+    };
+    let count = run();
+    if count.is_err() {
+        drop_thread_counter(attr);
+    }
+    count
 }
 
 // read the instruction count stoed if perf is establised
-fn perf_get_inst_count(fd: c_int) -> Result<i64, SolverError> {
+fn perf_get_inst_count(fd: &File) -> Result<i64, SolverError> {
     let mut count: i64 = 0;
-    match unsafe { libc::read(fd, &mut count as *mut i64 as *mut c_void, 8) as i64 } {
+    match unsafe { libc::read(fd.as_raw_fd(), &mut count as *mut i64 as *mut c_void, 8) as i64 } {
         8 => Ok(count),
         x if x >= 0 => Err(SolverError::new(
             Runner::IoError,
@@ -66,28 +263,211 @@ fn perf_get_inst_count(fd: c_int) -> Result<i64, SolverError> {
     }
 }
 
-#[derive(Copy, Clone)]
-pub struct PerfSolver;
+#[derive(Clone, Default)]
+pub struct PerfSolver {
+    attr: Option<PreparedAttr>,
+    template: Option<ProcessTemplate>,
+    mode: Option<PerfMode>,
+    // probe result to use instead of probing, for tests
+    probe: Option<PerfProbe>,
+    timing: TimingSolver,
+}
+
+impl PerfSolver {
+    pub fn new() -> PerfSolver {
+        PerfSolver::default()
+    }
+
+    /// Decide the mode in `setup` from `probe` instead of probing
+    pub fn with_probe(probe: PerfProbe) -> PerfSolver {
+        PerfSolver {
+            probe: Some(probe),
+            ..PerfSolver::default()
+        }
+    }
+
+    /// What `setup` decided to measure with, None before it ran
+    pub fn mode(&self) -> Option<PerfMode> {
+        self.mode
+    }
+}
+
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let mut template = ProcessTemplate::new(path);
+    template.apply_vars(vars)?;
+    template.with_ptrace(true);
+    Ok(template)
+}
 
 impl InstCounter for PerfSolver {
+    fn name(&self) -> &str {
+        "perf"
+    }
+
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.template.as_ref()
+    }
+
+    // fails before any target is spawned if perf can't be used, unless
+    // the "allow_fallback" var is set
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        let probe = self.probe.unwrap_or_else(probe);
+        let mode = decide(&probe, vars.contains_key("allow_fallback"))?;
+        match mode {
+            PerfMode::Instructions => self.attr = Some(PreparedAttr::new()),
+            PerfMode::TaskClock => self.attr = Some(PreparedAttr::task_clock()),
+            PerfMode::Timing => self.timing.setup(path, vars)?,
+        }
+        self.mode = Some(mode);
+        self.template = Some(make_template(path, vars)?);
+        Ok(())
+    }
+
+    fn fallback(&self) -> Option<String> {
+        match self.mode {
+            Some(PerfMode::TaskClock) => Some(String::from("task-clock")),
+            Some(PerfMode::Timing) => Some(String::from("timing")),
+            _ => None,
+        }
+    }
+
     // Handles basic proc spawning and running under perf
     fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
-        // TODO: error checking...
-        let mut process = Process::new(&data.path);
-        for arg in data.inp.argv.iter() {
-            process.arg(OsStr::from_bytes(arg));
+        if self.mode == Some(PerfMode::Timing) {
+            return self.timing.get_inst_count(data);
         }
-        process.input(data.inp.stdin.clone());
-        process.with_ptrace(true);
+        // TODO: error checking...
+        let template = template_for(self.template.as_ref(), data, make_template)?;
+        let attr = self.attr.unwrap_or_default();
 
-        let handle = process.spawn();
-        let fd = get_perf_fd(handle.pid().as_raw())?;
-        handle.finish(data.timeout)?;
+        let process = template.instantiate(&data.inp);
+        // the wrapper's exec would already start an inherited counter
+        if process.wrapper_execs() == 0 {
+            if let Some(counter) = thread_counter(&attr) {
+                return counted_run(&counter, &attr, process, data.timeout);
+            }
+        }
+        let handle = process.spawn()?;
+        let pid = handle.pid().as_raw();
+        let wrapper_execs = handle.wrapper_execs();
+        let mut fd = None;
+        if wrapper_execs == 0 {
+            fd = Some(get_perf_fd(&attr, pid)?);
+        }
+        // with a wrapper, only count from the target's exec stop on
+        let mut execs = 0;
+        handle.finish_with(data.timeout, |status| {
+            if let WaitStatus::Stopped(_, Signal::SIGTRAP) = *status {
+                if fd.is_none() {
+                    execs += 1;
+                    if execs > wrapper_execs {
+                        fd = Some(get_perf_fd(&attr, pid)?);
+                    }
+                }
+            }
+            Ok(())
+        })?;
+        let fd = match fd {
+            Some(fd) => fd,
+            None => {
+                return Err(SolverError::new(
+                    Runner::RunnerError,
+                    "the wrapper exited without running the target",
+                ))
+            }
+        };
 
         // Process instruction count
-        let ret = perf_get_inst_count(fd);
-        drop(unsafe { File::from_raw_fd(fd) });
+        perf_get_inst_count(&fd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PerfMode, PerfProbe, PerfSolver, PreparedAttr};
+    use crate::bindings::*;
+    use crate::brute::InstCounter;
+    use std::collections::HashMap;
+    use std::mem;
+
+    fn setup(probe: PerfProbe, allow_fallback: bool) -> PerfSolver {
+        let mut vars = HashMap::new();
+        if allow_fallback {
+            vars.insert(String::from("allow_fallback"), String::from("1"));
+        }
+        let mut solver = PerfSolver::with_probe(probe);
+        let _ = solver.setup("/bin/true", &vars);
+        solver
+    }
+
+    const DENIED: PerfProbe = PerfProbe {
+        paranoid: Some(4),
+        hardware: Some(libc::EACCES),
+        software: None,
+    };
+
+    #[test]
+    fn hardware_available() {
+        let probe = PerfProbe {
+            hardware: None,
+            ..DENIED
+        };
+        let solver = setup(probe, false);
+        assert_eq!(solver.mode(), Some(PerfMode::Instructions));
+        assert_eq!(solver.fallback(), None);
+    }
+
+    #[test]
+    fn denied_fails_fast() {
+        let mut solver = PerfSolver::with_probe(DENIED);
+        let err = solver.setup("/bin/true", &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("kernel.perf_event_paranoid=1"));
+        assert!(err.to_string().contains("is 4"));
+        assert_eq!(solver.mode(), None);
+
+        let contained = PerfProbe {
+            hardware: Some(libc::EPERM),
+            ..DENIED
+        };
+        assert!(contained.advice().contains("container"));
+    }
+
+    #[test]
+    fn fallbacks() {
+        let solver = setup(DENIED, true);
+        assert_eq!(solver.mode(), Some(PerfMode::TaskClock));
+        assert_eq!(solver.fallback().unwrap(), "task-clock");
+
+        let no_perf = PerfProbe {
+            software: Some(libc::EACCES),
+            ..DENIED
+        };
+        let solver = setup(no_perf, true);
+        assert_eq!(solver.mode(), Some(PerfMode::Timing));
+        assert_eq!(solver.fallback().unwrap(), "timing");
+    }
+
+    #[test]
+    fn task_clock_attr() {
+        let attr = PreparedAttr::task_clock().attr;
+        assert_eq!(attr.type_, perf_type_id_PERF_TYPE_SOFTWARE);
+        assert_eq!(attr.config, u64::from(perf_sw_ids_PERF_COUNT_SW_TASK_CLOCK));
+        assert_eq!(attr.exclude_kernel(), 1);
+    }
 
-        ret
+    #[test]
+    fn prepared_attr() {
+        let attr = PreparedAttr::new().attr;
+        assert_eq!(attr.type_, perf_type_id_PERF_TYPE_HARDWARE);
+        assert_eq!(attr.size as usize, mem::size_of::<perf_event_attr>());
+        assert_eq!(
+            attr.config,
+            u64::from(perf_hw_id_PERF_COUNT_HW_INSTRUCTIONS)
+        );
+        assert_eq!(attr.disabled(), 1);
+        assert_eq!(attr.exclude_kernel(), 1);
     }
 }
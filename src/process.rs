@@ -1,22 +1,54 @@
 use crate::binary::Binary;
+use crate::cancel;
 use crate::errors::*;
+use crate::generators::Input;
+use crate::logging;
 use lazy_static::lazy_static;
 use nix::errno::Errno;
+use nix::pty::{self, Winsize};
 use nix::sys::ptrace;
 use nix::sys::signal::{self, SigSet, SigmaskHow, Signal};
-use nix::sys::wait::{waitpid, WaitPidFlag, WaitStatus};
+use nix::sys::termios::{self, LocalFlags, OutputFlags, SetArg, Termios};
+use nix::sys::wait::WaitStatus;
 use nix::unistd::Pid;
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
 use std::convert::Into;
-use std::ffi::OsStr;
-use std::io::{Error, Read, Write};
+use std::env;
+use std::error::Error as StdError;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, Read, Seek, SeekFrom, Write};
+use std::mem;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::os::unix::process::CommandExt;
-use std::process::{Child, Command, Stdio};
-use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::ptr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::task::Waker;
+use std::thread::{self, JoinHandle};
 use std::time::{Duration, Instant};
 
-// Represents data returned from a call to waitpid()
+// how often finish() checks whether the brute was cancelled
+const CANCEL_CHECK: Duration = Duration::from_millis(100);
+// how long to wait for a child killed on timeout or cancellation to be reaped
+const KILL_TIMEOUT: Duration = Duration::from_secs(1);
+// how long answer_prompts waits for a prompt before writing the next line
+const PROMPT_TIMEOUT: Duration = Duration::from_secs(1);
+// how much write_stdin reads from stdout at a time
+const PIPE_CHUNK: usize = 4096;
+// from linux/personality.h
+const ADDR_NO_RANDOMIZE: libc::c_ulong = 0x0040000;
+// passed to personality() to only read the current persona
+const PERSONALITY_QUERY: libc::c_ulong = 0xffff_ffff;
+
+// Represents data returned from a call to wait4()
 // For convenience, we include the pid directly in the
 // struct, to avoid needing to unwrap it from WaitStatus
 // repeatedly
@@ -24,6 +56,48 @@ use std::time::{Duration, Instant};
 struct WaitData {
     pub status: WaitStatus,
     pub pid: Pid,
+    // only for exits, the rusage of a stop says nothing useful
+    pub stats: Option<ProcessStats>,
+    // when the waiter thread reaped it, so the runtime doesn't depend
+    // on how late the handle gets around to looking
+    pub reaped: Instant,
+}
+
+// What the waiter thread sends a handle: its child's next wait4()
+// result, or the error that stopped wait4() from working at all
+type WaitMsg = Result<WaitData, Errno>;
+
+// The WaitData of a message, or an error for the handle to return
+fn wait_data(msg: WaitMsg) -> Result<WaitData, SolverError> {
+    msg.map_err(|errno| {
+        SolverError::new(
+            Runner::RunnerError,
+            &format!("waiting for the child failed: {:?}", errno),
+        )
+    })
+}
+
+/// Resources a child used over its whole run, as reported by wait4()
+/// when it was reaped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ProcessStats {
+    pub user_time: Duration,
+    pub system_time: Duration,
+    /// Peak resident set size, in kilobytes
+    pub max_rss: i64,
+}
+
+impl ProcessStats {
+    fn from_rusage(usage: &libc::rusage) -> ProcessStats {
+        let time = |t: libc::timeval| {
+            Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+        };
+        ProcessStats {
+            user_time: time(usage.ru_utime),
+            system_time: time(usage.ru_stime),
+            max_rss: usage.ru_maxrss as i64,
+        }
+    }
 }
 
 lazy_static! {
@@ -35,42 +109,281 @@ lazy_static! {
     /// See [ProcessWaiter::spawn_process] for details on how to use it
     pub static ref WAITER: ProcessWaiter = { ProcessWaiter::new() };
 }
+
+/// Shuts down the thread behind [WAITER], see [ProcessWaiter::shutdown].
+/// Useful before forking or exiting, when no more children are coming
+pub fn shutdown_waiter() {
+    WAITER.shutdown();
+}
 /// ProcessWaiter allows waiting on child processes
 /// while specifying a timeout. There is exactly
 /// one instance of this struct for the entire process -
 /// it's stored in [WAITER]
 pub struct ProcessWaiter {
-    started: bool,
-    inner: Arc<Mutex<ProcessWaiterInner>>,
+    thread: Mutex<Option<WaiterThread>>,
+    inner: Arc<ProcessWaiterInner>,
+}
+
+// A running waiter thread, and the flag that asks it to stop
+struct WaiterThread {
+    handle: JoinHandle<()>,
+    stop: Arc<AtomicBool>,
 }
 
-/// The Mutex-protected interior of a ProcessWaiter.
+// Number of independently locked buckets in ProcessWaiterInner
+const SHARDS: usize = 16;
+
+// How long the waiter thread sleeps in sigtimedwait. While children
+// are registered it wakes up often to drain wait4() even if a
+// SIGCHLD went missing, so a finished child is never reported late
+const IDLE_WAIT_NS: i64 = 1_000_000_000;
+const BUSY_WAIT_NS: i64 = 2_000_000;
+
+/// The shared interior of a ProcessWaiter.
 /// This is used to give the waiter thread access
-/// to the part of ProcessWaiter that it actually uses,
-/// avoiding the need to wrap the entire ProcessWaiter
-/// in a mutex
+/// to the part of ProcessWaiter that it actually uses.
+///
+/// The pid -> channel map is split into SHARDS buckets, each with
+/// its own mutex, so that many threads spawning and finishing
+/// children don't all serialize on a single lock
 struct ProcessWaiterInner {
-    proc_chans: HashMap<Pid, ChanPair>,
+    proc_chans: Vec<Mutex<HashMap<Pid, ChanPair>>>,
+    // read locked while spawning, write locked while reaping. See
+    // spawn_waiting_thread for why
+    reaping: RwLock<()>,
+    // set while the waiter wants to reap, so that a steady stream of
+    // spawners can't starve it on a reader preferring RwLock
+    reap_pending: AtomicBool,
+    // tells entries apart when a pid is reused
+    generation: AtomicUsize,
+    // children spawned through the waiter that have not been reaped by
+    // their handle yet. A stopping waiter thread waits for this to hit 0
+    outstanding: AtomicUsize,
+    // the most children outstanding at once, 0 for no limit. Taking a
+    // slot happens with `slots` locked, and `slot_freed` is signalled
+    // whenever outstanding drops or the limit changes
+    max_children: AtomicUsize,
+    slots: Mutex<()>,
+    slot_freed: Condvar,
+    // set when the waiter thread panicked. Its channels stay in the map,
+    // so without this, handles would only notice by timing out
+    waiter_died: AtomicBool,
+}
+
+// Sets the flag it holds if the thread panics while it's alive
+struct PanicFlag<'a>(&'a AtomicBool);
+
+impl<'a> Drop for PanicFlag<'a> {
+    fn drop(&mut self) {
+        if thread::panicking() {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl ProcessWaiterInner {
+    fn new() -> ProcessWaiterInner {
+        ProcessWaiterInner {
+            proc_chans: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            reaping: RwLock::new(()),
+            reap_pending: AtomicBool::new(false),
+            generation: AtomicUsize::new(0),
+            outstanding: AtomicUsize::new(0),
+            max_children: AtomicUsize::new(0),
+            slots: Mutex::new(()),
+            slot_freed: Condvar::new(),
+            waiter_died: AtomicBool::new(false),
+        }
+    }
+
+    // a child spawned through the waiter was reaped or failed to start
+    fn child_done(&self) {
+        self.outstanding.fetch_sub(1, Ordering::SeqCst);
+        let _slots = self.slots.lock().unwrap();
+        self.slot_freed.notify_all();
+    }
+
+    fn lock_for_spawn(&self) -> RwLockReadGuard<()> {
+        while self.reap_pending.load(Ordering::SeqCst) {
+            thread::yield_now();
+        }
+        self.reaping.read().unwrap()
+    }
+
+    fn lock_for_reap(&self) -> RwLockWriteGuard<()> {
+        self.reap_pending.store(true, Ordering::SeqCst);
+        let guard = self.reaping.write().unwrap();
+        self.reap_pending.store(false, Ordering::SeqCst);
+        guard
+    }
+
+    fn new_pair(&self) -> ChanPair {
+        ChanPair::new(self.generation.fetch_add(1, Ordering::SeqCst))
+    }
+
+    fn shard(&self, pid: Pid) -> MutexGuard<HashMap<Pid, ChanPair>> {
+        self.proc_chans[pid.as_raw() as usize % SHARDS]
+            .lock()
+            .unwrap()
+    }
+
+    // Create the channel pair for a child that was just spawned, and
+    // take the receiver end along with the generation of the pair.
+    // Must be called with `reaping` read locked since before the spawn
+    fn take_recv(&self, pid: Pid) -> (Receiver<WaitMsg>, usize) {
+        let mut pair = self.new_pair();
+        let generation = pair.generation;
+        let recv = pair.take_recv();
+        // the child can't have been reaped yet, so anything already
+        // there is about an earlier process with the same pid
+        if let Some(stale) = self.shard(pid).insert(pid, pair) {
+            debug!(
+                "Dropping stale wait data of an earlier process {} (generation {})",
+                pid, stale.generation
+            );
+        }
+        (recv, generation)
+    }
+
+    // Create the channel pair for pid if it does
+    // not exist, and send msg to it
+    fn send(&self, pid: Pid, msg: WaitMsg) {
+        let mut shard = self.shard(pid);
+        if !shard.contains_key(&pid) {
+            let pair = self.new_pair();
+            shard.insert(pid, pair);
+        }
+        let exited = match &msg {
+            Ok(data) => match data.status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => true,
+                _ => false,
+            },
+            Err(_) => false,
+        };
+        let pair = shard.get_mut(&pid).unwrap();
+        pair.reaped |= exited;
+        // nobody listens if the handle was dropped without reaping, and
+        // once it exited nobody ever will
+        if pair.sender.send(msg).is_err() {
+            if exited {
+                shard.remove(&pid);
+            }
+        } else {
+            pair.wake();
+        }
+    }
+
+    // Have the handle of pid woken by the next message for it, or right
+    // away if there is no entry to send one to anymore
+    #[cfg(feature = "async")]
+    fn set_waker(&self, pid: Pid, generation: usize, waker: &Waker) {
+        match self.shard(pid).get(&pid) {
+            Some(pair) if pair.generation == generation => {
+                *pair.waker.lock().unwrap() = Some(waker.clone());
+            }
+            _ => waker.wake_by_ref(),
+        }
+    }
+
+    // Whether the child with this entry was reaped, so its pid may
+    // belong to another process. Only stays true while `reaping` is
+    // read locked
+    fn reaped(&self, pid: Pid, generation: usize) -> bool {
+        self.shard(pid)
+            .get(&pid)
+            .map_or(true, |pair| pair.generation != generation || pair.reaped)
+    }
+
+    // Route the wait data of pid, a new tracee of the child leader, to
+    // the channel of leader, along with anything already sent for it.
+    // Returns the generation of its entry, None if leader was reaped
+    fn adopt(&self, pid: Pid, leader: Pid) -> Option<usize> {
+        let (sender, waker) = {
+            let shard = self.shard(leader);
+            let pair = shard.get(&leader)?;
+            (pair.sender.clone(), pair.waker.clone())
+        };
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst);
+        let mut shard = self.shard(pid);
+        // its first stop may have been reaped before the fork event
+        if let Some(receiver) = shard.get(&pid).and_then(|pair| pair.receiver.as_ref()) {
+            while let Ok(msg) = receiver.try_recv() {
+                let _ = sender.send(msg);
+            }
+        }
+        let pair = ChanPair {
+            sender,
+            receiver: None,
+            generation,
+            reaped: false,
+            waker,
+        };
+        pair.wake();
+        shard.insert(pid, pair);
+        Some(generation)
+    }
+
+    // Tell every registered handle that wait4() failed, so they return
+    // an error instead of waiting out their timeouts
+    fn send_all(&self, errno: Errno) {
+        for shard in &self.proc_chans {
+            for pair in shard.lock().unwrap().values() {
+                let _ = pair.sender.send(Err(errno));
+                pair.wake();
+            }
+        }
+    }
+
+    // Remove the entry of pid, unless it was replaced by one for a
+    // later process that got the same pid
+    fn remove(&self, pid: Pid, generation: usize) {
+        let mut shard = self.shard(pid);
+        if shard.get(&pid).map(|pair| pair.generation) == Some(generation) {
+            shard.remove(&pid);
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.proc_chans
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
 }
 
 /// Represents the two ends of an MPSC channel
 /// The 'receiver' field will be taken by
 /// the consumer (i.e. the caller of ProcessWaiter::spawn_process)
 struct ChanPair {
-    sender: Sender<WaitData>,
-    receiver: Option<Receiver<WaitData>>,
+    sender: Sender<WaitMsg>,
+    receiver: Option<Receiver<WaitMsg>>,
+    generation: usize,
+    // the waiter reaped the pid, which may be reused now
+    reaped: bool,
+    // woken on every message, for handles awaited from async code.
+    // Shared with the entries of the tracees sending to this channel
+    waker: Arc<Mutex<Option<Waker>>>,
 }
 
 impl ChanPair {
-    fn new() -> ChanPair {
+    fn new(generation: usize) -> ChanPair {
         let (sender, receiver) = channel();
         ChanPair {
             sender,
             receiver: Some(receiver),
+            generation,
+            reaped: false,
+            waker: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn wake(&self) {
+        if let Some(waker) = self.waker.lock().unwrap().take() {
+            waker.wake();
         }
     }
 
-    fn take_recv(&mut self) -> Receiver<WaitData> {
+    fn take_recv(&mut self) -> Receiver<WaitMsg> {
         self.receiver.take().expect("Already took receiver!")
     }
 }
@@ -101,23 +414,101 @@ pub fn block_signal() {
 
 impl ProcessWaiter {
     fn new() -> ProcessWaiter {
-        let mut waiter = ProcessWaiter {
-            inner: Arc::new(Mutex::new(ProcessWaiterInner {
-                proc_chans: HashMap::new(),
-            })),
-            started: false,
+        let waiter = ProcessWaiter {
+            inner: Arc::new(ProcessWaiterInner::new()),
+            thread: Mutex::new(None),
         };
         block_signal();
-        waiter.start_thread();
+        waiter.start_thread(&mut waiter.thread.lock().unwrap());
         waiter
     }
 
-    fn start_thread(&mut self) {
-        if self.started {
+    fn start_thread(&self, thread: &mut Option<WaiterThread>) {
+        if thread.is_some() {
             panic!("Already started waiter thread!");
         }
-        self.started = true;
-        ProcessWaiter::spawn_waiting_thread(self.inner.clone());
+        let stop = Arc::new(AtomicBool::new(false));
+        let handle = ProcessWaiter::spawn_waiting_thread(self.inner.clone(), stop.clone());
+        *thread = Some(WaiterThread { handle, stop });
+    }
+
+    // Counts a child about to be spawned, restarting the waiter
+    // thread first if it was shut down. Both happen under the thread
+    // lock, so a concurrent shutdown either sees the child or
+    // leaves a running thread behind for it.
+    //
+    // With a limit on children, waits for a free slot first, or fails
+    // with WouldBlock unless `block`
+    fn register_child(&self, block: bool) -> Result<(), SolverError> {
+        let mut slots = self.inner.slots.lock().unwrap();
+        loop {
+            let max = self.inner.max_children.load(Ordering::SeqCst);
+            if max == 0 || self.inner.outstanding.load(Ordering::SeqCst) < max {
+                break;
+            }
+            if !block {
+                return Err(SolverError::new(
+                    Runner::WouldBlock,
+                    &format!("all {} child slots are taken", max),
+                ));
+            }
+            slots = self.inner.slot_freed.wait(slots).unwrap();
+        }
+        let mut thread = self.thread.lock().unwrap();
+        if self.inner.waiter_died.load(Ordering::SeqCst) {
+            if let Some(dead) = thread.take() {
+                let _ = dead.handle.join();
+            }
+            self.inner.waiter_died.store(false, Ordering::SeqCst);
+            error!("Waiter thread panicked, starting a new one");
+        }
+        if thread.is_none() {
+            debug!("Restarting waiter thread");
+            self.start_thread(&mut thread);
+        }
+        self.inner.outstanding.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// Lets at most `max` children spawned through the waiter exist at
+    /// once, None for no limit (the default). A child takes its slot
+    /// until its handle finishes, kills or drops it, and `spawn_process`
+    /// blocks until one is free, so a thread must not wait for a slot
+    /// while it holds `max` unreaped handles itself
+    pub fn set_max_children(&self, max: Option<usize>) {
+        let _slots = self.inner.slots.lock().unwrap();
+        self.inner
+            .max_children
+            .store(max.map_or(0, |max| max.max(1)), Ordering::SeqCst);
+        self.inner.slot_freed.notify_all();
+    }
+
+    /// The limit set with `set_max_children`
+    pub fn max_children(&self) -> Option<usize> {
+        match self.inner.max_children.load(Ordering::SeqCst) {
+            0 => None,
+            max => Some(max),
+        }
+    }
+
+    /// Stops the waiter thread once every child spawned through it
+    /// has been reaped, and waits for it to exit. Children are only
+    /// reaped when their handle finishes, kills or drops them, so
+    /// this blocks until all outstanding ProcessHandles are done.
+    ///
+    /// Spawning another process afterwards starts a new thread.
+    pub fn shutdown(&self) {
+        let thread = self.thread.lock().unwrap().take();
+        if let Some(thread) = thread {
+            thread.stop.store(true, Ordering::SeqCst);
+            // wake it up rather than waiting out an idle sigtimedwait
+            if let Err(e) = signal::kill(Pid::this(), Signal::SIGCHLD) {
+                warn!("Failed to wake waiter thread: {}", e);
+            }
+            if thread.handle.join().is_err() {
+                error!("Waiter thread panicked");
+            }
+        }
     }
 
     // Block SIGCHLD for the calling thread
@@ -126,32 +517,77 @@ impl ProcessWaiter {
         block_signal();
     }
 
+    /// Number of processes the waiter currently holds channels for.
+    /// Entries are removed once a child is finished, so this
+    /// should drop back to zero when no children are running
+    pub fn tracked(&self) -> usize {
+        self.inner.len()
+    }
+
     /// Spawns a process, returing a ProcessHandle which can be
     /// used to interact with the spawned process.
-    pub fn spawn_process(&self, mut process: Process) -> ProcessHandle {
-        let mut recv;
-        process.start().expect("Failed to spawn process!");
-        process.write_input().unwrap();
-        process.close_stdin().unwrap();
-
-        let pid = Pid::from_raw(process.child_id().unwrap() as i32);
+    /// Blocks while `set_max_children` children are running
+    pub fn spawn_process(&self, process: Process) -> Result<ProcessHandle, SolverError> {
+        self.register_child(true)?;
+        self.spawn_registered(process)
+    }
 
-        {
-            // Critical section - create channel pair if it does
-            // not exist, and take the receiver end
-            let proc_chans = &mut self.inner.lock().unwrap().proc_chans;
+    /// Like `spawn_process`, but fails with `Runner::WouldBlock` instead
+    /// of waiting for a free slot
+    pub fn try_spawn_process(&self, process: Process) -> Result<ProcessHandle, SolverError> {
+        self.register_child(false)?;
+        self.spawn_registered(process)
+    }
 
-            recv = proc_chans
-                .entry(pid)
-                .or_insert_with(ChanPair::new)
-                .take_recv();
+    fn spawn_registered(&self, mut process: Process) -> Result<ProcessHandle, SolverError> {
+        // before the child exists, so that it can't be reaped earlier
+        // outside the spawn lock, so other workers can spawn meanwhile
+        if let Err(e) = process.write_inputs() {
+            self.inner.child_done();
+            return Err(e);
         }
-        ProcessHandle {
+        let spawned = Instant::now();
+        let started = {
+            // no reaping until the child is registered
+            let _spawning = self.inner.lock_for_spawn();
+            process
+                .start()
+                .and_then(|_| process.child_id())
+                .map(|id| Pid::from_raw(id as i32))
+                .map(|pid| (pid, self.inner.take_recv(pid)))
+        };
+        let (pid, (recv, generation)) = match started {
+            Ok(started) => started,
+            Err(e) => {
+                self.inner.child_done();
+                return Err(e);
+            }
+        };
+        logging::update(|c| c.pid = Some(pid.as_raw()));
+        trace!("spawned {}", pid);
+
+        let mut handle = ProcessHandle {
             pid,
+            generation,
             recv,
             inner: self.inner.clone(),
             proc: process,
+            status: Cell::new(None),
+            stats: Cell::new(None),
+            started: spawned,
+            runtime: Cell::new(None),
+            stdout_truncated: Cell::new(false),
+            stdout_buf: Vec::new(),
+            stdout_eof: false,
+            options_set: Cell::new(false),
+            tracees: RefCell::new(HashMap::new()),
+        };
+        if !handle.proc.opts.interactive {
+            // dropping the handle kills and reaps the child, so neither
+            // it nor its wait data linger
+            handle.send_input()?;
         }
+        Ok(handle)
     }
 
     /// The core logic of ProcessWaiter. This is fairly tricky, due to the complications
@@ -159,12 +595,12 @@ impl ProcessWaiter {
     ///
     /// We call 'sigtimedwait' in a loop, with a signal mask containing only 'SIGCHLD'.
     /// Whenever we receieve a signal (which is guaranteed to be SIGCHLD),
-    /// we call waitpid() in a loop with WNOHANG. This ensures that we process
+    /// we call wait4() in a loop with WNOHANG. This ensures that we process
     /// all child updates that have occured since our last call to 'sigtimedwait'.
     /// Due to how Linux signal delivery works, we are not guaranteed to receive
     /// a SIGCHLD for every single child event - if a SIGCHLD arives
     /// while another SIGCHLD is still pending, it won't be delievered.
-    /// We then send the 'waitpid' result over an MPSC channel, where it
+    /// We then send the 'wait4' result over an MPSC channel, where it
     /// will be consumed by the thread waiting on the child.
     ///
     /// There are a number of subtleties here:
@@ -200,23 +636,43 @@ impl ProcessWaiter {
     /// for a very short amount of time, it might exit before
     /// the parent has a chance to store the channel in the map.
     ///
-    /// To avoid this race condition, we allow the waiter thread to *also*
-    /// store the channel in the map. This creates two possible cases:
+    /// To avoid this race condition, spawning and reaping exclude each
+    /// other through the 'reaping' RwLock. Spawners hold it for reading
+    /// from before the fork until the channel is in the map, so any number
+    /// of threads can spawn at once. The waiter holds it for writing from
+    /// each wait4() call until the result is sent. So when the child exits,
+    /// the waiter always finds its channel, and sends the wait4() data to
+    /// the parent listening on the receive end of the channel. Because MPSC
+    /// channels are buffered, the data waits there until the parent reads it.
+    ///
+    /// This also makes pid reuse safe. A pid is only free again once it was
+    /// reaped, and by then its exit is queued in the old channel, before any
+    /// new child can be forked with the same pid. Whatever is still in the
+    /// map under a pid when a new child registers is therefore stale, e.g.
+    /// from a child spawned without the waiter, and gets replaced. Entries
+    /// carry a generation, so a late cleanup for the old process can't
+    /// remove the entry of the new one.
     ///
-    /// Case 1: The spawned process lives long enough for the parent
-    /// thread to store its PID and channel in the map. When it eventually
-    /// exits, the waiter thread sees the existing channel, and sends
-    /// the waitpid() data to the parent listening on the receive end of the channel.
+    /// 3. The map is only locked for the duration of a single insert, send or
+    /// remove. The 'reaping' lock is only held for a single wait4() call
+    /// at a time, so a long drain never stalls threads spawning new children
+    /// for long.
     ///
-    /// Case 2: The spawned process lives for a very short time. Specifically,
-    /// the waiter thread receives a SIGCHLD before the spawner thread has a
-    /// chance to update the map. In this case, the waiter thread will
-    /// create a new channel, and send the waitpid data to the 'Sender'
-    /// half of the channel. Because MPSC channels are buffered,
-    /// the WaitData will simply remain in the queue until
-    /// the spawner thread retrieves the 'Reciever' half of the channel from the map.
-    fn spawn_waiting_thread(waiter_lock: Arc<Mutex<ProcessWaiterInner>>) {
+    /// 4. wait4() is drained whenever sigtimedwait returns, including when
+    /// it times out. While any children are registered the timeout is only
+    /// a few milliseconds, so even if a SIGCHLD is lost, a child that has
+    /// exited is reported almost immediately rather than after a full second.
+    ///
+    /// 5. Once 'stop' is set, the thread keeps going until every child
+    /// spawned through the waiter has been reaped, then exits. A thread
+    /// started in the meantime shares the same map, so it doesn't matter
+    /// which of the two reaps a given child.
+    fn spawn_waiting_thread(
+        inner: Arc<ProcessWaiterInner>,
+        stop: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
         std::thread::spawn(move || {
+            let _died = PanicFlag(&inner.waiter_died);
             // Block SIGCHLD on this thread, just to be safe (in case
             // it somehow wasn't blocked on the parent thread)
             block_signal();
@@ -227,251 +683,2431 @@ impl ProcessWaiter {
 
             let sigset_ptr = mask.as_ref() as *const libc::sigset_t;
             let info_ptr = &mut info as *mut libc::siginfo_t;
+            // the last unexpected sigtimedwait and wait4 errors, logged once
+            let mut failing: Option<Errno> = None;
+            let mut wait_failing: Option<Errno> = None;
 
             loop {
+                let wait_ns = if inner.len() > 0 {
+                    BUSY_WAIT_NS
+                } else {
+                    IDLE_WAIT_NS
+                };
                 let mut timeout = libc::timespec {
-                    tv_sec: 1,
-                    tv_nsec: 0,
+                    tv_sec: (wait_ns / 1_000_000_000) as libc::time_t,
+                    tv_nsec: (wait_ns % 1_000_000_000) as libc::c_long,
                 };
 
-                loop {
-                    // Safe because we know that the first two pointers are valid,
-                    // and the third argument can safely be NULL
-                    let res = unsafe {
-                        libc::sigtimedwait(
-                            sigset_ptr,
-                            info_ptr,
-                            &mut timeout as *mut libc::timespec,
-                        )
-                    };
-                    if res == -1 {
-                        if Errno::last() == Errno::EAGAIN {
-                            continue;
+                // Safe because we know that the first two pointers are valid,
+                // and the third argument can safely be NULL
+                let res = unsafe {
+                    libc::sigtimedwait(sigset_ptr, info_ptr, &mut timeout as *mut libc::timespec)
+                };
+                if res == -1 {
+                    match Errno::last() {
+                        // timed out, or interrupted: drain and go again
+                        Errno::EAGAIN | Errno::EINTR => {}
+                        // these fail at once every time, so sleep out the
+                        // timeout instead of spinning. The drain below
+                        // still reaps children at the usual pace
+                        err => {
+                            if failing != Some(err) {
+                                error!(
+                                    "Error calling sigtimedwait: {:?}, polling wait4 instead",
+                                    err
+                                );
+                                failing = Some(err);
+                            }
+                            thread::sleep(Duration::from_nanos(wait_ns as u64));
                         }
-                        println!("Error calling sigtimedwait: {}", nix::errno::errno());
-                        continue;
                     }
+                } else if failing.take().is_some() {
+                    info!("sigtimedwait works again");
+                }
 
-                    {
-                        // Critical section - we repeatedly call waitpid()
-                        // to reap all children that have exited since the last
-                        // signal
-                        // We call waitpid with WNOHANG, which ensures
-                        // that we don't block with the lock held
-                        let proc_chans = &mut waiter_lock.lock().unwrap().proc_chans;
-
-                        loop {
-                            let res = waitpid(None, Some(WaitPidFlag::WNOHANG));
-                            trace!("Waitpid result: {:?}", res);
-
-                            if res.is_err() {
-                                if res == Err(nix::Error::Sys(Errno::ECHILD)) {
-                                    break;
-                                }
-                                panic!("Waitpid error: {:?}", res);
-                            }
-                            let res = res.ok().unwrap();
-
-                            if res == WaitStatus::StillAlive {
-                                break;
-                            }
-
-                            let pid = res.pid().unwrap();
+                // the thread keeps running, or every later finish()
+                // would wait out its timeout
+                if let Err(errno) = ProcessWaiter::drain(&inner) {
+                    if wait_failing != Some(errno) {
+                        error!("Error calling wait4: {:?}", errno);
+                        wait_failing = Some(errno);
+                    }
+                    inner.send_all(errno);
+                } else {
+                    wait_failing = None;
+                }
 
-                            let data = WaitData { status: res, pid };
+                if stop.load(Ordering::SeqCst) && inner.outstanding.load(Ordering::SeqCst) == 0 {
+                    trace!("Waiter thread stopping");
+                    return;
+                }
+            }
+        })
+    }
 
-                            let sender: &Sender<WaitData> =
-                                &proc_chans.entry(pid).or_insert_with(ChanPair::new).sender;
+    // We repeatedly call wait4() to reap all children
+    // that have exited since the last drain
+    // We call wait4 with WNOHANG, which ensures
+    // that we never block here. Unlike waitpid(), it also
+    // tells us what resources the child used.
+    // Fails with the errno of a wait4() error other than ECHILD
+    fn drain(inner: &ProcessWaiterInner) -> Result<(), Errno> {
+        loop {
+            // held until the data is sent, so that a reaped pid can only
+            // be reused once its exit is in the right channel
+            let _reaping = inner.lock_for_reap();
+            let mut raw_status = 0;
+            let mut usage: libc::rusage = unsafe { mem::zeroed() };
+            let res = unsafe {
+                libc::wait4(
+                    -1,
+                    &mut raw_status,
+                    libc::WNOHANG | libc::__WALL,
+                    &mut usage,
+                )
+            };
+            let reaped = Instant::now();
+            trace!("Wait4 result: {} {:#x}", res, raw_status);
 
-                            sender.send(data).expect("Failed to send WaitData!");
-                        }
-                    }
+            if res == -1 {
+                match Errno::last() {
+                    Errno::ECHILD => return Ok(()),
+                    Errno::EINTR => continue,
+                    errno => return Err(errno),
                 }
             }
-        });
+            if res == 0 {
+                // children left, but none of them changed state
+                return Ok(());
+            }
+
+            let pid = Pid::from_raw(res);
+            let status = match WaitStatus::from_raw(pid, raw_status) {
+                Ok(status) => status,
+                // only this child is affected, the next wait4() may work
+                Err(e) => {
+                    error!("Bad wait status {:#x} of {}: {:?}", raw_status, pid, e);
+                    inner.send(pid, Err(Errno::EINVAL));
+                    continue;
+                }
+            };
+            let stats = match status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    Some(ProcessStats::from_rusage(&usage))
+                }
+                _ => None,
+            };
+
+            inner.send(
+                pid,
+                Ok(WaitData {
+                    status,
+                    pid,
+                    stats,
+                    reaped,
+                }),
+            );
+        }
     }
 }
 
 #[derive(Debug)]
 pub struct Process {
-    binary: Binary,
+    binary: Arc<Binary>,
     cmd: Command,
     child: Option<Child>,
-    input: Vec<u8>,
+    input: Arc<[u8]>,
+    // written to a temporary file whose path is the last argument
+    file_input: Option<Arc<[u8]>>,
+    input_file: Option<InputFile>,
+    // stdin read straight from a file, optionally from a patched copy
+    stdin_file: Option<PathBuf>,
+    stdin_overlay: Option<(u64, Arc<[u8]>)>,
+    stdin_copy: Option<InputFile>,
+    opts: SpawnOptions,
+    pty_master: Option<PtyMaster>,
+    input_sequence: Vec<Arc<[u8]>>,
+    input_delay: Duration,
+    // added by pre_exec, installed by start after the built in hooks
+    user_hooks: Hooks<PreExecHook>,
+    // program the target is run through, see `wrapper`
+    wrapper: Option<OsString>,
+}
+
+// How a Process is spawned and watched, apart from its command line and
+// input. A ProcessTemplate hands a copy to every instance
+#[derive(Debug, Clone)]
+struct SpawnOptions {
     ptrace: bool,
+    ptrace_options: ptrace::Options,
+    syscalls: bool,
+    pin_cpu: bool,
+    no_aslr: bool,
+    interactive: bool,
+    prompts: bool,
+    // stdin and stdout are a pseudo-terminal, see use_pty
+    pty: bool,
+    isolate_network: bool,
+    memory_limit: Option<u64>,
+    kill_policy: KillPolicy,
+    max_output: Option<usize>,
+    stdout_mode: OutputMode,
+    stderr_mode: OutputMode,
+    cpu_limit: Option<u64>,
+    no_core_dumps: bool,
+    // uid and gid the child switches to before exec
+    run_as: Option<(u32, u32)>,
+    cpu: Option<usize>,
 }
 
-pub struct ProcessHandle {
-    pid: Pid,
-    inner: Arc<Mutex<ProcessWaiterInner>>,
-    recv: Receiver<WaitData>,
-    proc: Process,
+impl SpawnOptions {
+    fn new() -> SpawnOptions {
+        SpawnOptions {
+            ptrace: false,
+            ptrace_options: ptrace::Options::empty(),
+            syscalls: false,
+            pin_cpu: false,
+            no_aslr: false,
+            interactive: false,
+            prompts: false,
+            pty: false,
+            isolate_network: false,
+            memory_limit: None,
+            kill_policy: KillPolicy::default(),
+            max_output: None,
+            stdout_mode: OutputMode::default(),
+            stderr_mode: OutputMode::default(),
+            cpu_limit: None,
+            no_core_dumps: false,
+            run_as: None,
+            cpu: None,
+        }
+    }
 }
 
-impl ProcessHandle {
-    pub fn finish(&self, timeout: Duration) -> Result<Pid, SolverError> {
-        let start = Instant::now();
-        let mut time_left = timeout;
+type PreExecHook = Box<dyn FnMut() -> io::Result<()> + Send + Sync>;
+// a ProcessTemplate hook, shared by every instance
+type SharedHook = Arc<dyn Fn() -> io::Result<()> + Send + Sync>;
 
-        loop {
-            let data = self.recv.recv_timeout(time_left).expect("Receieve error!");
-            match data.status {
-                WaitStatus::Exited(_, _) => {
-                    // Remove process data from the map now that it has exited
-                    self.inner.lock().unwrap().proc_chans.remove(&data.pid);
-                    return Ok(data.pid);
-                }
-                _ => {
-                    let now = Instant::now();
-                    let elapsed = now - start;
-                    if elapsed > timeout {
-                        // TODO - kill process?
-                        return Err(SolverError::new(Runner::Timeout, "child timeout"));
-                    }
-                    time_left = match time_left.checked_sub(elapsed) {
-                        Some(t) => t,
-                        None => return Err(SolverError::new(Runner::Timeout, "child timed out")),
-                    };
-
-                    if self.proc.ptrace {
-                        ptrace::cont(self.pid, None).unwrap_or_else(|e| {
-                            panic!(
-                                "Failed to call ptrace::cont for pid {:?}: {:?}",
-                                self.pid, e
-                            )
-                        })
-                    }
-                }
-            }
-        }
+// pre_exec hooks, which Debug can only count
+#[derive(Clone)]
+struct Hooks<H>(Vec<H>);
+
+impl<H> fmt::Debug for Hooks<H> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} hooks", self.0.len())
     }
+}
 
-    pub fn pid(&self) -> Pid {
-        self.pid
+// numbers the input files of this b7 process
+static INPUT_FILES: AtomicUsize = AtomicUsize::new(0);
+
+// numbers the threads that started a pinned child
+#[cfg(feature = "pin-cpu")]
+static PINNING_THREADS: AtomicUsize = AtomicUsize::new(0);
+
+#[cfg(feature = "pin-cpu")]
+thread_local! {
+    // The workers of a brute start their first children together, so
+    // they get consecutive numbers and with them different CPUs
+    static PINNING_INDEX: usize = PINNING_THREADS.fetch_add(1, Ordering::SeqCst);
+}
+
+// Temporary copy of a file input, removed when the Process is dropped
+#[derive(Debug)]
+struct InputFile {
+    path: PathBuf,
+}
+
+impl InputFile {
+    // create_new, so a file planted at that path is never written to
+    fn open_new() -> Result<(InputFile, File), SolverError> {
+        let path = env::temp_dir().join(format!(
+            "b7-input-{}-{}",
+            std::process::id(),
+            INPUT_FILES.fetch_add(1, Ordering::SeqCst)
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok((InputFile { path }, file))
     }
 
-    // read buf to process then close it
-    pub fn read_stdout(&mut self, buf: &mut Vec<u8>) -> Result<usize, SolverError> {
-        if self.proc.child.is_none() {
-            return Err(SolverError::new(
-                Runner::RunnerError,
-                "child process not running",
-            ));
-        }
-        let child = self.proc.child.as_mut().unwrap();
-        match child.stdout.as_mut() {
-            Some(stdout) => stdout.read_to_end(buf).map_err(Into::into),
-            None => Err(Error::last_os_error().into()),
-        }
+    fn create(contents: &[u8]) -> Result<InputFile, SolverError> {
+        let (input_file, mut file) = InputFile::open_new()?;
+        file.write_all(contents)?;
+        Ok(input_file)
+    }
+
+    // copy of `source` with `bytes` written over it at `offset`
+    fn patched(source: &Path, offset: u64, bytes: &[u8]) -> Result<InputFile, SolverError> {
+        let (input_file, mut file) = InputFile::open_new()?;
+        io::copy(&mut File::open(source)?, &mut file)?;
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(bytes)?;
+        Ok(input_file)
     }
 }
 
-// Handle running a process
-impl Process {
-    pub fn new(path: &str) -> Process {
-        Process {
-            binary: Binary::new(path),
-            cmd: Command::new(path),
-            input: Vec::new(),
-            child: None,
-            ptrace: false,
+impl Drop for InputFile {
+    fn drop(&mut self) {
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!("Failed to remove {}: {}", self.path.display(), e);
         }
     }
+}
+
+// Our end of a use_pty child's terminal
+#[derive(Debug)]
+struct PtyMaster {
+    file: File,
+    // sent EOF, the child's stdin counts as closed
+    closed: bool,
+}
 
-    pub fn input(&mut self, stdin: Vec<u8>) {
-        self.input = stdin
+impl PtyMaster {
+    // A pty with echo and newline translation off, so the output reads
+    // the same as through a pipe. Returns the master and the slave
+    fn open() -> Result<(PtyMaster, File), SolverError> {
+        let pty = pty::openpty(None::<&Winsize>, None::<&Termios>)?;
+        let (master, slave) =
+            unsafe { (File::from_raw_fd(pty.master), File::from_raw_fd(pty.slave)) };
+        let mut attrs = termios::tcgetattr(slave.as_raw_fd())?;
+        attrs.local_flags.remove(LocalFlags::ECHO);
+        attrs.output_flags.remove(OutputFlags::ONLCR);
+        termios::tcsetattr(slave.as_raw_fd(), SetArg::TCSANOW, &attrs)?;
+        let master = PtyMaster {
+            file: master,
+            closed: false,
+        };
+        Ok((master, slave))
     }
+}
 
-    pub fn child_id(&self) -> Result<u32, SolverError> {
-        match &self.child {
-            Some(a) => Ok(a.id()),
-            None => Err(SolverError::new(Runner::IoError, "no child id")),
+impl Read for PtyMaster {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.file.read(buf) {
+            // the master reads EIO once the child closed its last fd
+            // of the slave, which is EOF for us
+            Err(ref e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+            res => res,
         }
     }
+}
 
-    pub fn write_input(&mut self) -> Result<(), SolverError> {
-        self.write_stdin(&self.input.clone())
+impl Write for PtyMaster {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.write(buf)
     }
 
-    pub fn args<I, S>(&mut self, args: I)
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-    {
-        self.cmd.args(args);
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
     }
+}
 
-    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) {
-        self.cmd.arg(arg);
-    }
+/// What `finish` does with a child that runs into its timeout
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KillPolicy {
+    /// SIGKILL the child's process group right away
+    SigkillImmediately,
+    /// SIGTERM the group first, so the target can flush its output,
+    /// and SIGKILL it if the child still runs after `grace`
+    TermThenKill { grace: Duration },
+    /// Leave the child running. It is killed once its handle is dropped
+    None,
+}
 
-    // initialize process and wait it
-    pub fn start(&mut self) -> Result<(), SolverError> {
-        if self.child.is_some() {
-            return Err(SolverError::new(Runner::Unknown, "child already running"));
-        }
-        self.cmd.stdin(Stdio::piped());
-        self.cmd.stdout(Stdio::piped());
-        self.cmd.stderr(Stdio::piped());
+impl Default for KillPolicy {
+    fn default() -> KillPolicy {
+        KillPolicy::SigkillImmediately
+    }
+}
 
-        if self.ptrace {
-            // Copied from spawn_ptrace
-            self.cmd.before_exec(|| {
-                ptrace::traceme().expect("TRACEME failed!");
-                Ok(())
-            });
+// "kill", "term:SECS" or "none", as used by the "kill_policy" var
+impl fmt::Display for KillPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KillPolicy::SigkillImmediately => write!(f, "kill"),
+            KillPolicy::TermThenKill { grace } => write!(f, "term:{}", grace.as_secs_f64()),
+            KillPolicy::None => write!(f, "none"),
         }
+    }
+}
 
-        let child = self.cmd.spawn();
+impl FromStr for KillPolicy {
+    type Err = SolverError;
 
-        // spawn process and wait after fork
-        //let child = self.cmd.spawn_ptrace();
-        match child {
-            Ok(c) => {
-                self.child = Some(c);
-                Ok(())
+    fn from_str(s: &str) -> Result<KillPolicy, SolverError> {
+        let bad = || {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad kill policy {}, expected kill, term:SECS or none", s),
+            )
+        };
+        match s {
+            "kill" => Ok(KillPolicy::SigkillImmediately),
+            "none" => Ok(KillPolicy::None),
+            _ if s.starts_with("term:") => {
+                let secs: f64 = s["term:".len()..].parse().map_err(|_| bad())?;
+                // a Duration holds less than 2^64 seconds
+                if !(secs >= 0.0 && secs < std::u64::MAX as f64) {
+                    return Err(bad());
+                }
+                Ok(KillPolicy::TermThenKill {
+                    grace: Duration::from_secs_f64(secs),
+                })
             }
-            Err(x) => Err(x.into()),
+            _ => Err(bad()),
         }
     }
+}
 
-    // write buf to process then close it
-    pub fn write_stdin(&mut self, buf: &[u8]) -> Result<(), SolverError> {
-        if self.child.is_none() {
-            return Err(SolverError::new(
-                Runner::RunnerError,
-                "Process is not running",
-            ));
-        }
-        let child = self.child.as_mut().unwrap();
-        match child.stdin.as_mut() {
-            Some(stdin) => stdin.write_all(buf).map_err(Into::into),
-            None => Err(SolverError::new(Runner::IoError, "could not open stdin")),
-        }
+/// Where the child's stdout or stderr goes
+#[derive(Clone, Debug, PartialEq)]
+pub enum OutputMode {
+    /// A pipe, read with `read_stdout` and `read_stderr`
+    Piped,
+    /// Thrown away, for solvers that don't look at the output
+    Null,
+    /// Appended to this file, which is created if it doesn't exist
+    File(PathBuf),
+}
+
+impl Default for OutputMode {
+    fn default() -> OutputMode {
+        OutputMode::Piped
     }
+}
 
-    // close stdin to prevent any reads hanging
-    pub fn close_stdin(&mut self) -> Result<(), SolverError> {
-        if self.child.is_none() {
-            return Err(SolverError::new(
-                Runner::RunnerError,
-                "child process not running",
-            ));
-        }
-        match self.child.as_mut().unwrap().stdin.take() {
-            Some(stdin) => {
-                drop(stdin);
-                Ok(())
+impl OutputMode {
+    fn stdio(&self) -> Result<Stdio, SolverError> {
+        Ok(match self {
+            OutputMode::Piped => Stdio::piped(),
+            OutputMode::Null => Stdio::null(),
+            OutputMode::File(path) => {
+                Stdio::from(OpenOptions::new().create(true).append(true).open(path)?)
             }
-            None => Err(Error::last_os_error().into()),
-        }
+        })
     }
+}
 
-    pub fn with_ptrace(&mut self, ptrace: bool) {
-        self.ptrace = ptrace;
+// "piped", "null" or "file:PATH", as used by the "stdout_mode" and
+// "stderr_mode" vars
+impl fmt::Display for OutputMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OutputMode::Piped => write!(f, "piped"),
+            OutputMode::Null => write!(f, "null"),
+            OutputMode::File(path) => write!(f, "file:{}", path.display()),
+        }
     }
+}
 
-    pub fn spawn(self) -> ProcessHandle {
+impl FromStr for OutputMode {
+    type Err = SolverError;
+
+    fn from_str(s: &str) -> Result<OutputMode, SolverError> {
+        match s {
+            "piped" => Ok(OutputMode::Piped),
+            "null" => Ok(OutputMode::Null),
+            _ if s.starts_with("file:") && s.len() > "file:".len() => {
+                Ok(OutputMode::File(PathBuf::from(&s["file:".len()..])))
+            }
+            _ => Err(SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad output mode {}, expected piped, null or file:PATH", s),
+            )),
+        }
+    }
+}
+
+/// How a child ended, as returned by `finish` and `try_finish`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ExitStatus {
+    pub pid: Pid,
+    /// Exit code, if the child exited normally
+    pub code: Option<i32>,
+    /// Signal that terminated the child, e.g. SIGSEGV for a crash
+    pub signal: Option<Signal>,
+    pub core_dumped: bool,
+}
+
+impl ExitStatus {
+    /// Exited with code 0
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// A `Runner::Crashed` error if a signal killed the child, for
+    /// solvers that want crashes counted apart from other failures
+    pub fn check_crash(&self) -> Result<(), SolverError> {
+        match self.signal {
+            Some(signal) => Err(SolverError::new(
+                Runner::Crashed,
+                &format!(
+                    "child {} killed by {:?}{}",
+                    self.pid,
+                    signal,
+                    if self.core_dumped {
+                        " (core dumped)"
+                    } else {
+                        ""
+                    }
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn from_wait(status: &WaitStatus) -> Option<ExitStatus> {
+        match *status {
+            WaitStatus::Exited(pid, code) => Some(ExitStatus {
+                pid,
+                code: Some(code),
+                signal: None,
+                core_dumped: false,
+            }),
+            WaitStatus::Signaled(pid, sig, core_dumped) => Some(ExitStatus {
+                pid,
+                code: None,
+                signal: Some(sig),
+                core_dumped,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub struct ProcessHandle {
+    pid: Pid,
+    // of our entry in the waiter's map
+    generation: usize,
+    inner: Arc<ProcessWaiterInner>,
+    recv: Receiver<WaitMsg>,
+    proc: Process,
+    // set once the child has exited
+    status: Cell<Option<ExitStatus>>,
+    stats: Cell<Option<ProcessStats>>,
+    started: Instant,
+    // wall clock time from spawn to exit
+    runtime: Cell<Option<Duration>>,
+    // read_stdout dropped output past max_output_bytes
+    stdout_truncated: Cell<bool>,
+    // output read while writing stdin, handed out first by read_stdout
+    stdout_buf: Vec<u8>,
+    stdout_eof: bool,
+    // Process::ptrace_options were set at the first stop
+    options_set: Cell<bool>,
+    // tracees the child forked, with the generation of their entries
+    tracees: RefCell<HashMap<Pid, usize>>,
+}
+
+impl ProcessHandle {
+    /// Waits for the child to exit, killing it if it is still running
+    /// at the timeout. If the cancel token of this thread
+    /// (see `cancel::set_current`) is cancelled meanwhile, the child is
+    /// killed and an Aborted error returned within about 100ms.
+    /// Exiting with an error code or being killed by a signal counts
+    /// as finishing, check the returned status (or `success`) for those
+    pub fn finish(&self, timeout: Duration) -> Result<ExitStatus, SolverError> {
+        self.finish_with(timeout, |_| Ok(()))
+    }
+
+    /// Like `finish`, but calls `on_stop` with every status that is
+    /// not an exit before the child is resumed. For ptraced children
+    /// this lets the caller inspect or modify the tracee at each stop.
+    /// With `Process::ptrace_options` that trace forks, every status of
+    /// the traced descendants, including their exits, goes there too
+    pub fn finish_with<F>(
+        &self,
+        timeout: Duration,
+        mut on_stop: F,
+    ) -> Result<ExitStatus, SolverError>
+    where
+        F: FnMut(&WaitStatus) -> Result<(), SolverError>,
+    {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(self.timed_out());
+            }
+            // wake up now and then to notice a cancelled brute
+            let data = match self.recv.recv_timeout((deadline - now).min(CANCEL_CHECK)) {
+                Ok(msg) => wait_data(msg)?,
+                Err(RecvTimeoutError::Timeout) => {
+                    if cancel::cancelled() {
+                        self.kill(KILL_TIMEOUT)?;
+                        return Err(SolverError::new(Runner::Aborted, "cancelled"));
+                    }
+                    // nobody reaps the child anymore, so its exit never comes
+                    if self.inner.waiter_died.load(Ordering::SeqCst) {
+                        return Err(SolverError::new(
+                            Runner::RunnerError,
+                            "the process waiter thread died, see its panic above",
+                        ));
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SolverError::new(
+                        Runner::RunnerError,
+                        "process waiter went away",
+                    ))
+                }
+            };
+            if data.pid != self.pid {
+                on_stop(&data.status)?;
+                self.tracee_event(&data)?;
+                continue;
+            }
+            match data.status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    // Remove process data from the map now that it has exited
+                    let status = self.exited(&data);
+                    self.check_limits()?;
+                    return Ok(status);
+                }
+                _ => {
+                    on_stop(&data.status)?;
+                    self.resume(data.pid, &data.status)?;
+                }
+            }
+        }
+    }
+
+    /// Non-blocking version of `finish`. Returns `Ok(None)` if the child
+    /// is still running, so that callers driving many children from an
+    /// event loop can poll them without tying up a thread per child.
+    pub fn try_finish(&self) -> Result<Option<ExitStatus>, SolverError> {
+        loop {
+            let data = match self.recv.try_recv() {
+                Ok(msg) => wait_data(msg)?,
+                Err(TryRecvError::Empty) => return Ok(None),
+                Err(TryRecvError::Disconnected) => {
+                    return Err(SolverError::new(
+                        Runner::RunnerError,
+                        "process waiter went away",
+                    ))
+                }
+            };
+            if data.pid != self.pid {
+                self.tracee_event(&data)?;
+                continue;
+            }
+            match data.status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    let status = self.exited(&data);
+                    self.check_limits()?;
+                    return Ok(Some(status));
+                }
+                _ => self.resume(data.pid, &data.status)?,
+            }
+        }
+    }
+
+    // Wake `waker` once there may be news for `try_finish`
+    #[cfg(feature = "async")]
+    pub(crate) fn set_waker(&self, waker: &Waker) {
+        self.inner.set_waker(self.pid, self.generation, waker);
+    }
+
+    // Apply the kill policy to a child that ran into its timeout, so a
+    // hung child doesn't outlive the brute. The error says what was done
+    fn timed_out(&self) -> SolverError {
+        let policy = self.proc.opts.kill_policy;
+        let outcome = match policy {
+            KillPolicy::SigkillImmediately => self.kill(KILL_TIMEOUT).map(|_| "was killed"),
+            KillPolicy::TermThenKill { grace } => self.terminate(grace).map(|exited| {
+                if exited {
+                    "exited after SIGTERM"
+                } else {
+                    "was killed after ignoring SIGTERM"
+                }
+            }),
+            KillPolicy::None => Ok("was left running"),
+        };
+        let what = outcome.unwrap_or_else(|e| {
+            warn!("Failed to kill timed out child {}: {}", self.pid, e);
+            "could not be killed"
+        });
+        SolverError::new(
+            Runner::Timeout,
+            &format!("child timed out and {} (kill policy {})", what, policy),
+        )
+    }
+
+    // SIGTERM the child's group and wait up to `grace` for the child to
+    // exit, then SIGKILL it. Returns whether it exited on its own
+    fn terminate(&self, grace: Duration) -> Result<bool, SolverError> {
+        if self.runtime.get().is_some() {
+            return Ok(true);
+        }
+        // like in kill, a queued exit means the pid must not be signalled
+        while let Ok(msg) = self.recv.try_recv() {
+            let data = wait_data(msg)?;
+            if data.pid != self.pid {
+                self.tracee_event(&data)?;
+                continue;
+            }
+            match data.status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.exited(&data);
+                    return Ok(true);
+                }
+                _ => self.resume(data.pid, &data.status)?,
+            }
+        }
+        signal::kill(Pid::from_raw(-self.pid.as_raw()), Signal::SIGTERM)?;
+        let deadline = Instant::now() + grace;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let data = match self.recv.recv_timeout(deadline - now) {
+                Ok(msg) => wait_data(msg)?,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SolverError::new(
+                        Runner::RunnerError,
+                        "process waiter went away",
+                    ))
+                }
+            };
+            if data.pid != self.pid {
+                self.tracee_event(&data)?;
+                continue;
+            }
+            match data.status {
+                WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                    self.exited(&data);
+                    return Ok(true);
+                }
+                // a traced child stops for the SIGTERM, pass it on
+                _ => self.resume(data.pid, &data.status)?,
+            }
+        }
+        self.kill(KILL_TIMEOUT)?;
+        Ok(false)
+    }
+
+    // Restart a traced child after a ptrace stop. Signals the child got
+    // are passed on, so e.g. a SIGSEGV still ends a crashing target
+    // right away. SIGTRAPs come from tracing itself and are dropped,
+    // as are stop signals, which would just stop the child again
+    fn resume(&self, pid: Pid, status: &WaitStatus) -> Result<(), SolverError> {
+        if !self.proc.opts.ptrace {
+            return Ok(());
+        }
+        // tracees inherit the options, so only the child needs them
+        let options = self.proc.opts.ptrace_options;
+        if pid == self.pid && !self.options_set.get() && !options.is_empty() {
+            // replaces the options of a trace_syscalls caller
+            let sysgood = if self.proc.opts.syscalls {
+                ptrace::Options::PTRACE_O_TRACESYSGOOD
+            } else {
+                ptrace::Options::empty()
+            };
+            ptrace::setoptions(pid, options | sysgood)?;
+            self.options_set.set(true);
+        }
+        if let WaitStatus::PtraceEvent(_, _, event) = *status {
+            if event == libc::PTRACE_EVENT_FORK
+                || event == libc::PTRACE_EVENT_VFORK
+                || event == libc::PTRACE_EVENT_CLONE
+            {
+                let tracee = Pid::from_raw(ptrace::getevent(pid)? as libc::pid_t);
+                trace!("{} has a new tracee {}", pid, tracee);
+                if let Some(generation) = self.inner.adopt(tracee, self.pid) {
+                    self.tracees.borrow_mut().insert(tracee, generation);
+                }
+            }
+        }
+        let signal = match *status {
+            WaitStatus::Stopped(_, Signal::SIGTRAP)
+            | WaitStatus::Stopped(_, Signal::SIGSTOP)
+            | WaitStatus::Stopped(_, Signal::SIGTSTP)
+            | WaitStatus::Stopped(_, Signal::SIGTTIN)
+            | WaitStatus::Stopped(_, Signal::SIGTTOU) => 0,
+            WaitStatus::Stopped(_, signal) => signal as libc::c_int,
+            WaitStatus::PtraceEvent(..) | WaitStatus::PtraceSyscall(_) => 0,
+            // not a ptrace stop, there is nothing to resume
+            _ => return Ok(()),
+        };
+        // stop again at the next syscall entry or exit when tracing them
+        let request = if self.proc.opts.syscalls {
+            libc::PTRACE_SYSCALL
+        } else {
+            libc::PTRACE_CONT
+        };
+        // nix's ptrace::syscall can't pass a signal on
+        let res = unsafe {
+            libc::ptrace(
+                request,
+                pid.as_raw(),
+                ptr::null_mut::<libc::c_void>(),
+                signal as usize as *mut libc::c_void,
+            )
+        };
+        Errno::result(res)?;
+        Ok(())
+    }
+
+    // Wait data of a tracee the child forked rather than of the child
+    // itself: stops are resumed, exits drop its entry
+    fn tracee_event(&self, data: &WaitData) -> Result<(), SolverError> {
+        match data.status {
+            WaitStatus::Exited(..) | WaitStatus::Signaled(..) => {
+                self.forget_tracee(data);
+                Ok(())
+            }
+            _ => self.resume(data.pid, &data.status),
+        }
+    }
+
+    fn forget_tracee(&self, data: &WaitData) {
+        if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = data.status {
+            if let Some(generation) = self.tracees.borrow_mut().remove(&data.pid) {
+                self.inner.remove(data.pid, generation);
+            }
+        }
+    }
+
+    fn exited(&self, data: &WaitData) -> ExitStatus {
+        self.inner.remove(data.pid, self.generation);
+        self.inner.child_done();
+        self.runtime
+            .set(Some(data.reaped.duration_since(self.started)));
+        let status = ExitStatus::from_wait(&data.status).expect("child has not exited");
+        self.status.set(Some(status));
+        self.stats.set(data.stats);
+        status
+    }
+
+    // The kernel sends SIGXCPU at the soft CPU limit and SIGKILL at the
+    // hard one, a second later. A SIGKILL is only blamed on the limit if
+    // the child did use that much CPU time, otherwise it was the OOM
+    // killer or someone else and is left as the plain signal it is.
+    // Running out of address space just makes allocations fail, so that
+    // shows up as whatever the target does then
+    fn check_limits(&self) -> Result<(), SolverError> {
+        let cpu_limit = match self.proc.opts.cpu_limit {
+            Some(limit) => limit,
+            None => return Ok(()),
+        };
+        let used_limit = || {
+            self.stats.get().map_or(false, |stats| {
+                stats.user_time + stats.system_time >= Duration::from_secs(cpu_limit)
+            })
+        };
+        match self.status.get().and_then(|s| s.signal) {
+            Some(Signal::SIGXCPU) => {}
+            Some(Signal::SIGKILL) if used_limit() => {}
+            _ => return Ok(()),
+        }
+        Err(SolverError::new(
+            Runner::ResourceLimit,
+            &format!("child exceeded its CPU time limit of {}s", cpu_limit),
+        ))
+    }
+
+    // Write the whole input, in the chunks of `input_sequence` or a
+    // line per prompt with `answer_prompts`, and close stdin. A target
+    // that exits without reading all of it is fine, it just never saw
+    // the rest
+    fn send_input(&mut self) -> Result<(), SolverError> {
+        // the child reads a file, there is no pipe to write to
+        if self.proc.stdin_file.is_some() {
+            return Ok(());
+        }
+        let written = if !self.proc.input_sequence.is_empty() {
+            self.write_sequence()
+        } else if self.proc.opts.prompts {
+            self.answer_prompts()
+        } else if self.proc.pty_master.is_none() {
+            // Streamed while stdout is read, or a child that echoes its
+            // input could fill the stdout pipe and stop reading stdin
+            // before all of it is written. A terminal reads EIO once the
+            // child is gone, so it is written in one go instead
+            let input = self.proc.input.clone();
+            self.write_stdin(&input)
+        } else {
+            self.proc.write_input()
+        };
+        if let Err(e) = written {
+            let broken_pipe = e
+                .source()
+                .and_then(|e| e.downcast_ref::<io::Error>())
+                .map_or(false, |e| e.kind() == io::ErrorKind::BrokenPipe);
+            if !broken_pipe {
+                return Err(e);
+            }
+        }
+        self.proc.close_stdin()
+    }
+
+    pub fn pid(&self) -> Pid {
+        self.pid
+    }
+
+    /// See `Process::wrapper_execs`
+    pub fn wrapper_execs(&self) -> usize {
+        self.proc.wrapper_execs()
+    }
+
+    /// Wall clock time from spawn until the waiter thread reaped the
+    /// child, once `finish` or `try_finish` has seen it exit
+    pub fn runtime(&self) -> Option<Duration> {
+        self.runtime.get()
+    }
+
+    /// Kills the child along with everything else in its process group,
+    /// e.g. helpers it forked, and waits until the child is reaped.
+    /// Does nothing if it was already reaped, e.g. by a `finish` that
+    /// timed out
+    pub fn kill(&self, timeout: Duration) -> Result<(), SolverError> {
+        if self.runtime.get().is_some() {
+            return Ok(());
+        }
+        if self.proc.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "child process not running",
+            ));
+        }
+        // an exit that is already queued means the pid may belong to
+        // another process by now, so it must not be signalled
+        while let Ok(msg) = self.recv.try_recv() {
+            let data = wait_data(msg)?;
+            if data.pid != self.pid {
+                self.tracee_event(&data)?;
+                continue;
+            }
+            if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = data.status {
+                self.exited(&data);
+                return Ok(());
+            }
+        }
+        // the child leads its own group, see Process::start. Only the
+        // child itself is reaped here, the rest go to init. A child
+        // reaped since the loop above has its exit on the way instead
+        {
+            let _spawning = self.inner.lock_for_spawn();
+            if !self.inner.reaped(self.pid, self.generation) {
+                signal::kill(Pid::from_raw(-self.pid.as_raw()), Signal::SIGKILL)?;
+            }
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(SolverError::new(Runner::Timeout, "child timed out"));
+            }
+            let data = match self.recv.recv_timeout(deadline - now) {
+                Ok(msg) => wait_data(msg)?,
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(SolverError::new(Runner::Timeout, "child timed out"))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(SolverError::new(
+                        Runner::RunnerError,
+                        "process waiter went away",
+                    ))
+                }
+            };
+            if data.pid != self.pid {
+                self.forget_tracee(&data);
+                continue;
+            }
+            // stops from before the kill landed need no resuming
+            if let WaitStatus::Exited(..) | WaitStatus::Signaled(..) = data.status {
+                self.exited(&data);
+                return Ok(());
+            }
+        }
+    }
+
+    /// Exit code of the child, once `finish` or `try_finish` has seen it exit
+    pub fn exit_code(&self) -> Option<i32> {
+        self.status.get().and_then(|s| s.code)
+    }
+
+    /// How the child ended, once it was reaped. A child killed at the
+    /// timeout shows up as terminated by SIGKILL
+    pub fn status(&self) -> Option<ExitStatus> {
+        self.status.get()
+    }
+
+    /// CPU time and peak memory of the child, once it was reaped.
+    /// A child killed at the timeout has the stats up to the kill
+    pub fn stats(&self) -> Option<ProcessStats> {
+        self.stats.get()
+    }
+
+    /// Sends an arbitrary signal to the child, e.g. SIGCONT
+    /// to resume it or SIGINT to simulate Ctrl-C
+    pub fn signal(&self, sig: Signal) -> Result<(), SolverError> {
+        let not_running = || SolverError::new(Runner::RunnerError, "child process not running");
+        if self.proc.child.is_none() || self.runtime.get().is_some() {
+            return Err(not_running());
+        }
+        // no reaping meanwhile, so a pid that wasn't reaped stays ours
+        let _spawning = self.inner.lock_for_spawn();
+        if self.inner.reaped(self.pid, self.generation) {
+            return Err(not_running());
+        }
+        signal::kill(self.pid, sig).map_err(Into::into)
+    }
+
+    // Write the input a line at a time, each once the child printed
+    // something since the last one, or PROMPT_TIMEOUT passed
+    fn answer_prompts(&mut self) -> Result<(), SolverError> {
+        let input = self.proc.input.clone();
+        let mut rest = &input[..];
+        while !rest.is_empty() {
+            let end = rest
+                .iter()
+                .position(|&b| b == b'\n')
+                .map_or(rest.len(), |i| i + 1);
+            self.wait_for_output(PROMPT_TIMEOUT)?;
+            self.write_stdin(&rest[..end])?;
+            rest = &rest[end..];
+        }
+        Ok(())
+    }
+
+    // Write the chunks of `input_sequence`, input_delay apart
+    fn write_sequence(&mut self) -> Result<(), SolverError> {
+        let chunks = self.proc.input_sequence.clone();
+        for (i, chunk) in chunks.iter().enumerate() {
+            if i > 0 {
+                self.read_output_for(self.proc.input_delay)?;
+            }
+            self.write_stdin(chunk)?;
+        }
+        Ok(())
+    }
+
+    // Keep reading stdout into stdout_buf for `delay`, so the child
+    // never blocks on a full pipe while we wait
+    fn read_output_for(&mut self, delay: Duration) -> Result<(), SolverError> {
+        let deadline = Instant::now() + delay;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(());
+            }
+            let wait = (deadline - now).min(CANCEL_CHECK);
+            match self.stdout_fd() {
+                Some(fd) => {
+                    if poll_fds(&mut [pollfd(fd, libc::POLLIN)], wait)? > 0 {
+                        self.buffer_stdout()?;
+                    }
+                }
+                None => thread::sleep(wait),
+            }
+            if cancel::cancelled() {
+                return Err(SolverError::new(Runner::Aborted, "cancelled"));
+            }
+        }
+    }
+
+    // fd of the child's stdout while there may be more to read
+    fn stdout_fd(&self) -> Option<RawFd> {
+        if self.stdout_eof {
+            return None;
+        }
+        self.proc.stdout_fd()
+    }
+
+    // Read whatever the child's stdout has into stdout_buf. Only called
+    // once poll said it's readable, so the read doesn't block
+    fn buffer_stdout(&mut self) -> Result<(), SolverError> {
+        let mut chunk = [0; PIPE_CHUNK];
+        let stdout = match self.proc.stdout_mut() {
+            Some(stdout) => stdout,
+            None => return Ok(()),
+        };
+        let n = stdout.read(&mut chunk)?;
+        if n == 0 {
+            self.stdout_eof = true;
+        }
+        self.stdout_buf.extend_from_slice(&chunk[..n]);
+        Ok(())
+    }
+
+    // Wait up to `timeout` for the child to write to stdout
+    fn wait_for_output(&mut self, timeout: Duration) -> Result<(), SolverError> {
+        let fd = match self.stdout_fd() {
+            Some(fd) => fd,
+            None => return Ok(()),
+        };
+        if poll_fds(&mut [pollfd(fd, libc::POLLIN)], timeout)? > 0 {
+            self.buffer_stdout()?;
+        }
+        Ok(())
+    }
+
+    /// Write `buf` to the stdin of a child spawned with `defer_input`.
+    /// Output the child writes meanwhile is kept for `read_stdout`, so
+    /// the two never block each other on full pipes
+    pub fn write_stdin(&mut self, buf: &[u8]) -> Result<(), SolverError> {
+        let stdin_fd = match self.proc.stdin_fd() {
+            Some(fd) => fd,
+            None => {
+                return Err(SolverError::new(
+                    Runner::IoError,
+                    "stdin of the child is closed",
+                ))
+            }
+        };
+        let mut rest = buf;
+        while !rest.is_empty() {
+            // poll ignores the entry of a negative fd
+            let mut fds = [
+                pollfd(stdin_fd, libc::POLLOUT),
+                pollfd(self.stdout_fd().unwrap_or(-1), libc::POLLIN),
+            ];
+            if poll_fds(&mut fds, CANCEL_CHECK)? == 0 {
+                if cancel::cancelled() {
+                    return Err(SolverError::new(Runner::Aborted, "cancelled"));
+                }
+                continue;
+            }
+            if fds[1].revents != 0 {
+                self.buffer_stdout()?;
+            }
+            if fds[0].revents != 0 {
+                // POLLOUT on a pipe means PIPE_BUF bytes fit without
+                // blocking. A closed pipe makes the write fail instead
+                let stdin = self.proc.stdin_mut().unwrap();
+                let n = without_sigpipe(|| stdin.write(&rest[..rest.len().min(libc::PIPE_BUF)]))?;
+                rest = &rest[n..];
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the child's stdout up to and including the next `delim`,
+    /// e.g. a prompt to answer with `write_stdin`, and append it to
+    /// `buf`. Stops early at EOF. Blocks until then or until cancelled,
+    /// and leaves what came after `delim` for the next read
+    pub fn read_until(&mut self, delim: u8, buf: &mut Vec<u8>) -> Result<usize, SolverError> {
+        if self.proc.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "child process not running",
+            ));
+        }
+        let start = buf.len();
+        // stdout_buf before this has no delim
+        let mut searched = 0;
+        loop {
+            if let Some(i) = self.stdout_buf[searched..].iter().position(|&b| b == delim) {
+                buf.extend(self.stdout_buf.drain(..=searched + i));
+                return Ok(buf.len() - start);
+            }
+            searched = self.stdout_buf.len();
+            let fd = match self.stdout_fd() {
+                Some(fd) => fd,
+                None => {
+                    buf.append(&mut self.stdout_buf);
+                    return Ok(buf.len() - start);
+                }
+            };
+            if poll_fds(&mut [pollfd(fd, libc::POLLIN)], CANCEL_CHECK)? > 0 {
+                self.buffer_stdout()?;
+            } else if cancel::cancelled() {
+                return Err(SolverError::new(Runner::Aborted, "cancelled"));
+            }
+        }
+    }
+
+    /// Close the child's stdin, e.g. after the last `write_stdin`
+    pub fn close_stdin(&mut self) -> Result<(), SolverError> {
+        self.proc.close_stdin()
+    }
+
+    /// The child's stdin, if the process was spawned interactive
+    pub fn stdin(&mut self) -> Option<&mut ChildStdin> {
+        self.proc.child.as_mut().and_then(|c| c.stdin.as_mut())
+    }
+
+    pub fn stdout(&mut self) -> Option<&mut ChildStdout> {
+        self.proc.child.as_mut().and_then(|c| c.stdout.as_mut())
+    }
+
+    // read buf to process then close it
+    pub fn read_stdout(&mut self, buf: &mut Vec<u8>) -> Result<usize, SolverError> {
+        if self.proc.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "child process not running",
+            ));
+        }
+        if !self.captures_stdout() {
+            return Err(SolverError::new(
+                Runner::IoError,
+                &format!(
+                    "stdout of the child is not piped but {}",
+                    self.proc.opts.stdout_mode
+                ),
+            ));
+        }
+        let start = buf.len();
+        buf.append(&mut self.stdout_buf);
+        let max = self.proc.opts.max_output;
+        let stdout = match self.proc.stdout_mut() {
+            Some(stdout) => stdout,
+            None => return Err(Error::last_os_error().into()),
+        };
+        let max = match max {
+            Some(max) => max,
+            None => {
+                stdout.read_to_end(buf)?;
+                return Ok(buf.len() - start);
+            }
+        };
+        let mut dropped = (buf.len() - start).saturating_sub(max) as u64;
+        buf.truncate(start + max.min(buf.len() - start));
+        let room = max - (buf.len() - start);
+        stdout.by_ref().take(room as u64).read_to_end(buf)?;
+        // read and thrown away, so the child never blocks on a full pipe
+        dropped += io::copy(stdout, &mut io::sink())?;
+        if dropped > 0 {
+            debug!("Dropped {} bytes of stdout from {}", dropped, self.pid);
+            self.stdout_truncated.set(true);
+        }
+        Ok(buf.len() - start)
+    }
+
+    /// Whether `read_stdout` can read the child's stdout, see
+    /// `Process::stdout_mode`
+    pub fn captures_stdout(&self) -> bool {
+        self.proc.opts.pty || self.proc.opts.stdout_mode == OutputMode::Piped
+    }
+
+    /// Whether `read_stderr` can read the child's stderr
+    pub fn captures_stderr(&self) -> bool {
+        self.proc.opts.stderr_mode == OutputMode::Piped
+    }
+
+    /// Whether `read_stdout` dropped output past the limit set with
+    /// `Process::max_output_bytes`
+    pub fn stdout_truncated(&self) -> bool {
+        self.stdout_truncated.get()
+    }
+
+    // read the child's stderr to the end, e.g. for an ASAN report
+    pub fn read_stderr(&mut self, buf: &mut Vec<u8>) -> Result<usize, SolverError> {
+        if self.proc.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "child process not running",
+            ));
+        }
+        if !self.captures_stderr() {
+            return Err(SolverError::new(
+                Runner::IoError,
+                &format!(
+                    "stderr of the child is not piped but {}",
+                    self.proc.opts.stderr_mode
+                ),
+            ));
+        }
+        let child = self.proc.child.as_mut().unwrap();
+        match child.stderr.as_mut() {
+            Some(stderr) => stderr.read_to_end(buf).map_err(Into::into),
+            None => Err(SolverError::new(
+                Runner::IoError,
+                "stderr of the child was not captured",
+            )),
+        }
+    }
+}
+
+// A handle dropped before its child was reaped, e.g. because the
+// solver returned early with an error, kills and reaps the child.
+// Reaping removes the pid from the waiter's map, and as the waiter only
+// ever sends one exit per child it can't add the pid back afterwards
+impl Drop for ProcessHandle {
+    fn drop(&mut self) {
+        if self.runtime.get().is_some() {
+            return;
+        }
+        if let Err(e) = self.kill(KILL_TIMEOUT) {
+            warn!("Failed to reap dropped child {}: {}", self.pid, e);
+            // the child is stuck somehow. If it may still exit, its
+            // entry stays until the waiter finds nobody listening for
+            // the exit, see send
+            if *e.runner() != Runner::Timeout {
+                self.inner.remove(self.pid, self.generation);
+            }
+            self.inner.child_done();
+        }
+    }
+}
+
+// Handle running a process
+impl Process {
+    pub fn new(path: &str) -> Process {
+        Process {
+            binary: Arc::new(Binary::new(path)),
+            cmd: Command::new(path),
+            input: Arc::from(Vec::new()),
+            file_input: None,
+            input_file: None,
+            stdin_file: None,
+            stdin_overlay: None,
+            stdin_copy: None,
+            child: None,
+            opts: SpawnOptions::new(),
+            pty_master: None,
+            input_sequence: Vec::new(),
+            input_delay: Duration::default(),
+            user_hooks: Hooks(Vec::new()),
+            wrapper: None,
+        }
+    }
+
+    /// Run the target through `cmd`, e.g. `stdbuf -o0`, which gets `args`
+    /// and then the target's path as its arguments. This starts the
+    /// command line over, so call it before `arg`, `env` and the like
+    pub fn wrapper(&mut self, cmd: &str, args: &[&str]) {
+        self.cmd = Command::new(cmd);
+        self.cmd.args(args);
+        self.cmd.arg(self.binary.path());
+        self.wrapper = Some(OsString::from(cmd));
+    }
+
+    /// How many execs the child makes before it is the target, 1 with a
+    /// `wrapper` that execs it. Ptrace users that act on the target's
+    /// exec stop have to skip this many SIGTRAP stops first
+    pub fn wrapper_execs(&self) -> usize {
+        if self.wrapper.is_some() {
+            1
+        } else {
+            0
+        }
+    }
+
+    // Candidate inputs can be large, so they are shared rather than copied
+    pub fn input<T: Into<Arc<[u8]>>>(&mut self, stdin: T) {
+        self.input = stdin.into()
+    }
+
+    /// Write the input as separate chunks, `input_delay` apart, keeping
+    /// stdin open until the last one is written. Replaces `input`
+    pub fn input_sequence<I, T>(&mut self, chunks: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<Arc<[u8]>>,
+    {
+        self.input_sequence = chunks.into_iter().map(Into::into).collect();
+        self.input = self.input_sequence.concat().into();
+    }
+
+    /// How long to wait between the chunks of `input_sequence`
+    pub fn input_delay(&mut self, delay: Duration) {
+        self.input_delay = delay;
+    }
+
+    /// Write `contents` to a temporary file when starting, and pass its
+    /// path as the last argument. The file is removed with the Process
+    pub fn file_input<T: Into<Arc<[u8]>>>(&mut self, contents: T) {
+        self.file_input = Some(contents.into())
+    }
+
+    /// Connect the child's stdin to the file at `path` instead of
+    /// writing the input to a pipe, so large inputs are never read into
+    /// memory. The input set with `input` is ignored then
+    pub fn input_file(&mut self, path: &Path) {
+        self.stdin_file = Some(path.to_path_buf());
+    }
+
+    /// Give the child a temporary copy of the `input_file` with `bytes`
+    /// written over it at `offset`. The copy is removed with the Process
+    pub fn input_file_overlay<T: Into<Arc<[u8]>>>(&mut self, offset: u64, bytes: T) {
+        self.stdin_overlay = Some((offset, bytes.into()));
+    }
+
+    /// Path of the temporary file made for `file_input`, once started
+    pub fn input_file_path(&self) -> Option<&Path> {
+        self.input_file.as_ref().map(|f| f.path.as_path())
+    }
+
+    /// The program and arguments the child runs with, quoted, e.g. for
+    /// a dry run
+    pub fn command_line(&self) -> String {
+        format!("{:?}", self.cmd)
+    }
+
+    pub fn child_id(&self) -> Result<u32, SolverError> {
+        match &self.child {
+            Some(a) => Ok(a.id()),
+            None => Err(SolverError::new(Runner::IoError, "no child id")),
+        }
+    }
+
+    pub fn write_input(&mut self) -> Result<(), SolverError> {
+        // cloning the Arc just bumps the refcount
+        let input = self.input.clone();
+        self.write_stdin(&input)
+    }
+
+    pub fn args<I, S>(&mut self, args: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.cmd.args(args);
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) {
+        self.cmd.arg(arg);
+    }
+
+    /// Set an environment variable of the child. The child inherits
+    /// B7's environment otherwise, see `env_clear`
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) {
+        self.cmd.env(key, value);
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        self.cmd.envs(vars);
+    }
+
+    // start the child with only the variables set with env/envs
+    pub fn env_clear(&mut self) {
+        self.cmd.env_clear();
+    }
+
+    /// Run the child in `dir`, for targets that open files relative to
+    /// their working directory. A relative program path may then be
+    /// looked up from `dir`, so prefer `ProcessTemplate::current_dir`
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        self.cmd.current_dir(dir);
+    }
+
+    // Write the files the child reads its input from, unless that was
+    // done already. Copying a large stdin file takes a while, so the
+    // waiter does this before it takes the spawn lock
+    fn write_inputs(&mut self) -> Result<(), SolverError> {
+        if self.input_file.is_none() {
+            if let Some(contents) = &self.file_input {
+                self.input_file = Some(InputFile::create(contents)?);
+            }
+        }
+        if self.stdin_copy.is_none() {
+            if let (Some(path), Some((offset, bytes))) = (&self.stdin_file, &self.stdin_overlay) {
+                self.stdin_copy = Some(InputFile::patched(path, *offset, bytes)?);
+            }
+        }
+        Ok(())
+    }
+
+    // initialize process and wait it
+    pub fn start(&mut self) -> Result<(), SolverError> {
+        if self.child.is_some() {
+            return Err(SolverError::new(Runner::Unknown, "child already running"));
+        }
+        self.write_inputs()?;
+        if let Some(file) = &self.input_file {
+            self.cmd.arg(&file.path);
+        }
+        let slave = if self.opts.pty {
+            let (master, slave) = PtyMaster::open()?;
+            self.pty_master = Some(master);
+            Some(slave)
+        } else {
+            None
+        };
+        match &self.stdin_file {
+            Some(path) => {
+                let file = match &self.stdin_copy {
+                    Some(copy) => File::open(&copy.path)?,
+                    None => File::open(path)?,
+                };
+                self.cmd.stdin(Stdio::from(file));
+            }
+            None if self.opts.pty => {
+                self.cmd
+                    .stdin(Stdio::from(slave.as_ref().unwrap().try_clone()?));
+            }
+            None => {
+                self.cmd.stdin(Stdio::piped());
+            }
+        }
+        match slave {
+            Some(slave) => self.cmd.stdout(Stdio::from(slave)),
+            None => self.cmd.stdout(self.opts.stdout_mode.stdio()?),
+        };
+        self.cmd.stderr(self.opts.stderr_mode.stdio()?);
+
+        // hooks run in the order they are added: the built in ones, the
+        // user's, then TRACEME so the child is only traced once set up
+
+        // a group of its own, so that killing the target also kills
+        // anything it forked
+        if self.opts.pty {
+            // a new session is a new group too, and the pty becomes its
+            // controlling terminal, which is what /dev/tty opens
+            self.hook(|| {
+                if unsafe { libc::setsid() } == -1
+                    || unsafe { libc::ioctl(0, libc::TIOCSCTTY, 0) } == -1
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        } else {
+            self.hook(|| {
+                if unsafe { libc::setpgid(0, 0) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        if let Some(cpu) = self.opts.cpu {
+            self.pin_to(cpu);
+        } else if self.opts.pin_cpu {
+            self.pin_to_single_cpu();
+        }
+
+        if self.opts.no_aslr {
+            self.hook(|| {
+                // keep the rest of the persona, e.g. READ_IMPLIES_EXEC
+                let persona = unsafe { libc::syscall(libc::SYS_personality, PERSONALITY_QUERY) };
+                if persona == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                let persona = persona as libc::c_ulong | ADDR_NO_RANDOMIZE;
+                if unsafe { libc::syscall(libc::SYS_personality, persona) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        // setrlimit is a plain syscall, safe between fork and exec
+        if let Some(bytes) = self.opts.memory_limit {
+            let limit = rlimit(bytes, bytes);
+            self.hook(move || {
+                if unsafe { libc::setrlimit(libc::RLIMIT_AS, &limit) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        if let Some(secs) = self.opts.cpu_limit {
+            // SIGXCPU at the soft limit, SIGKILL if the target ignores it
+            let limit = rlimit(secs, secs + 1);
+            self.hook(move || {
+                if unsafe { libc::setrlimit(libc::RLIMIT_CPU, &limit) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        if self.opts.no_core_dumps {
+            // A limit of 0 stops the kernel from writing a core file. A
+            // core_pattern piping to a helper such as systemd-coredump
+            // still runs it, and only the helper decides whether to
+            // honour the limit. PR_SET_DUMPABLE isn't set: execve resets
+            // it for any target that isn't setuid, so doing it here
+            // before the exec would have no effect
+            let limit = rlimit(0, 0);
+            self.hook(move || {
+                if unsafe { libc::setrlimit(libc::RLIMIT_CORE, &limit) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        if self.opts.isolate_network {
+            // a namespace with only a downed loopback device, where
+            // connecting anywhere fails right away with ENETUNREACH
+            self.hook(|| {
+                if unsafe { libc::unshare(libc::CLONE_NEWNET) } != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        // after everything that needs root, but before TRACEME, so the
+        // child stops at exec as the user it runs as
+        if let Some((uid, gid)) = self.opts.run_as {
+            self.hook(move || {
+                let groups = [gid as libc::gid_t];
+                if unsafe { libc::setgroups(1, groups.as_ptr()) } != 0
+                    || unsafe { libc::setgid(gid) } != 0
+                    || unsafe { libc::setuid(uid) } != 0
+                {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        for f in self.user_hooks.0.drain(..) {
+            self.cmd.before_exec(f);
+        }
+
+        if self.opts.ptrace {
+            // Copied from spawn_ptrace
+            self.hook(|| {
+                ptrace::traceme().expect("TRACEME failed!");
+                Ok(())
+            });
+        }
+
+        let child = self.cmd.spawn();
+        if self.opts.pty {
+            // the Command holds on to its copies of the slave, and the
+            // master only sees EOF once every one of them is closed
+            self.cmd.stdin(Stdio::null());
+            self.cmd.stdout(Stdio::null());
+        }
+
+        // spawn process and wait after fork
+        //let child = self.cmd.spawn_ptrace();
+        match child {
+            Ok(c) => {
+                self.child = Some(c);
+                Ok(())
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Err(self.not_found()),
+            Err(ref e) if self.opts.isolate_network && e.raw_os_error() == Some(libc::EPERM) => {
+                Err(SolverError::new(
+                    Runner::MissingArgs,
+                    "isolating the network of targets needs CAP_SYS_ADMIN, run b7 as root",
+                ))
+            }
+            Err(ref e) if self.opts.run_as.is_some() && e.raw_os_error() == Some(libc::EPERM) => {
+                let (uid, gid) = self.opts.run_as.unwrap();
+                Err(SolverError::new(
+                    Runner::MissingArgs,
+                    &format!("running targets as {}:{} needs root", uid, gid),
+                ))
+            }
+            Err(x) => Err(x.into()),
+        }
+    }
+
+    // exec fails with ENOENT both for a missing binary and for one whose
+    // ELF interpreter is missing, e.g. a 32 bit target without 32 bit libc
+    fn not_found(&self) -> SolverError {
+        // with a wrapper, the target is only exec'd by the wrapper
+        if let Some(wrapper) = &self.wrapper {
+            return SolverError::new(
+                Runner::NotFound,
+                &format!("wrapper {} not found", wrapper.to_string_lossy()),
+            );
+        }
+        let path = self.binary.path();
+        let message = if Path::new(path).exists() {
+            format!("{} exists, but its ELF interpreter is missing", path)
+        } else {
+            format!("target {} not found", path)
+        };
+        SolverError::new(Runner::NotFound, &message)
+    }
+
+    // write buf to process then close it
+    pub fn write_stdin(&mut self, buf: &[u8]) -> Result<(), SolverError> {
+        if self.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "Process is not running",
+            ));
+        }
+        match self.stdin_mut() {
+            Some(stdin) => without_sigpipe(|| stdin.write_all(buf)).map_err(Into::into),
+            None => Err(SolverError::new(Runner::IoError, "could not open stdin")),
+        }
+    }
+
+    // close stdin to prevent any reads hanging
+    pub fn close_stdin(&mut self) -> Result<(), SolverError> {
+        if self.child.is_none() {
+            return Err(SolverError::new(
+                Runner::RunnerError,
+                "child process not running",
+            ));
+        }
+        if let Some(pty) = self.pty_master.as_mut() {
+            if pty.closed {
+                return Err(Error::last_os_error().into());
+            }
+            // a terminal has no end to close, so send VEOF instead. The
+            // first one ends a line without a newline, if there is one
+            let eof: &[u8] = if self.input.last().map_or(true, |&b| b == b'\n') {
+                b"\x04"
+            } else {
+                b"\x04\x04"
+            };
+            pty.write_all(eof)?;
+            pty.closed = true;
+            return Ok(());
+        }
+        match self.child.as_mut().unwrap().stdin.take() {
+            Some(stdin) => {
+                drop(stdin);
+                Ok(())
+            }
+            None => Err(Error::last_os_error().into()),
+        }
+    }
+
+    // Where the child's input goes: the pty master, or the stdin pipe
+    fn stdin_mut(&mut self) -> Option<&mut dyn Write> {
+        if let Some(pty) = self.pty_master.as_mut() {
+            return if pty.closed { None } else { Some(pty) };
+        }
+        let stdin = self.child.as_mut()?.stdin.as_mut()?;
+        Some(stdin)
+    }
+
+    fn stdin_fd(&self) -> Option<RawFd> {
+        if let Some(pty) = &self.pty_master {
+            return if pty.closed {
+                None
+            } else {
+                Some(pty.file.as_raw_fd())
+            };
+        }
+        let stdin = self.child.as_ref()?.stdin.as_ref()?;
+        Some(stdin.as_raw_fd())
+    }
+
+    // Where the child's output comes from, like stdin_mut
+    fn stdout_mut(&mut self) -> Option<&mut dyn Read> {
+        if let Some(pty) = self.pty_master.as_mut() {
+            return Some(pty);
+        }
+        let stdout = self.child.as_mut()?.stdout.as_mut()?;
+        Some(stdout)
+    }
+
+    fn stdout_fd(&self) -> Option<RawFd> {
+        if let Some(pty) = &self.pty_master {
+            return Some(pty.file.as_raw_fd());
+        }
+        let stdout = self.child.as_ref()?.stdout.as_ref()?;
+        Some(stdout.as_raw_fd())
+    }
+
+    pub fn with_ptrace(&mut self, ptrace: bool) {
+        self.opts.ptrace = ptrace;
+    }
+
+    /// Same as `interactive`: `spawn` leaves stdin alone, and the input
+    /// is written in stages with `ProcessHandle::write_stdin`
+    pub fn defer_input(&mut self, defer: bool) {
+        self.opts.interactive = defer;
+    }
+
+    /// Write the input a line at a time when spawning, each line once
+    /// the child printed something (a prompt) or a second passed, for
+    /// targets that prompt before every read and bail on early input
+    pub fn answer_prompts(&mut self, prompts: bool) {
+        self.opts.prompts = prompts;
+    }
+
+    /// Run the child on a pseudo-terminal, for targets that check isatty
+    /// or open /dev/tty. Input and output go through the pty master,
+    /// with echo off. The terminal reads lines, so input only reaches
+    /// the child a line (at most 4096 bytes) at a time
+    pub fn use_pty(&mut self, pty: bool) {
+        self.opts.pty = pty;
+    }
+
+    /// Run the child in a network namespace of its own, without any
+    /// network to talk to. Needs CAP_SYS_ADMIN, `spawn` fails without
+    pub fn isolate_network(&mut self, isolate: bool) {
+        self.opts.isolate_network = isolate;
+    }
+
+    /// Leave stdin open after spawning instead of writing the input and
+    /// closing it, so the caller can keep talking to the child through
+    /// `ProcessHandle::stdin` and `ProcessHandle::stdout`
+    pub fn interactive(&mut self, interactive: bool) {
+        self.opts.interactive = interactive;
+    }
+
+    /// Runs `f` in the child between fork and exec, e.g. to set a prctl
+    /// flag, open an extra fd or chroot. Hooks run in the order they are
+    /// added, after the built in ones (a new process group or session,
+    /// CPU pinning, disabling ASLR, resource limits, network isolation
+    /// and `run_as`, so `f` sees all of those and no longer runs as root
+    /// with `run_as`) and before PTRACE_TRACEME, so a traced child stops
+    /// at exec with them done. An error from `f` fails the spawn.
+    ///
+    /// The child is a copy of a multithreaded process, so `f` must only
+    /// do async-signal-safe things: plain syscalls through libc are fine,
+    /// but no allocating, locking (including println!) or panicking
+    pub fn pre_exec<F>(&mut self, f: F)
+    where
+        F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.user_hooks.0.push(Box::new(f));
+    }
+
+    // a built in hook, which runs before the user's
+    fn hook<F>(&mut self, f: F)
+    where
+        F: FnMut() -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.cmd.before_exec(f);
+    }
+
+    /// Trace the child with these PTRACE_O_* options, set at its first
+    /// stop. With PTRACE_O_TRACEFORK, TRACEVFORK or TRACECLONE its
+    /// children are traced too, and `finish_with` resumes them and hands
+    /// their statuses to `on_stop`. PTRACE_O_EXITKILL kills the tracees
+    /// should b7 die. Implies `with_ptrace`
+    pub fn ptrace_options(&mut self, options: ptrace::Options) {
+        self.opts.ptrace_options = options;
+        self.opts.ptrace = true;
+    }
+
+    /// Resume the ptraced child with PTRACE_SYSCALL instead of
+    /// PTRACE_CONT, so `finish_with` sees a stop at every syscall.
+    /// The caller still has to set PTRACE_O_TRACESYSGOOD at the first stop
+    pub fn trace_syscalls(&mut self, syscalls: bool) {
+        self.opts.syscalls = syscalls;
+        if syscalls {
+            self.opts.ptrace = true;
+        }
+    }
+
+    /// Runs the child with all of its threads pinned to a single CPU.
+    /// They take turns instead of running in parallel, which makes the
+    /// counts of multithreaded targets vary less, though the scheduler
+    /// still picks the order. Children spawned from different threads
+    /// get different CPUs while there are enough of them. Only has an
+    /// effect with the `pin-cpu` feature.
+    pub fn pin_cpu(&mut self, pin: bool) {
+        self.opts.pin_cpu = pin;
+    }
+
+    /// Runs the child with ADDR_NO_RANDOMIZE, so that its stack, heap
+    /// and libraries (and a PIE binary itself) load at the same
+    /// addresses every run, like under gdb
+    pub fn disable_aslr(&mut self, disable: bool) {
+        self.opts.no_aslr = disable;
+    }
+
+    /// How `finish` gets rid of the child when it times out
+    pub fn kill_policy(&mut self, policy: KillPolicy) {
+        self.opts.kill_policy = policy;
+    }
+
+    /// Keep at most `bytes` of stdout in `read_stdout`, see
+    /// `ProcessHandle::stdout_truncated`. Unlimited by default
+    pub fn max_output_bytes(&mut self, bytes: usize) {
+        self.opts.max_output = Some(bytes);
+    }
+
+    /// Where the child's stdout goes, a pipe by default. Anything else
+    /// makes `read_stdout` fail. Ignored with `use_pty`, the terminal
+    /// is the child's stdout then
+    pub fn stdout_mode(&mut self, mode: OutputMode) {
+        self.opts.stdout_mode = mode;
+    }
+
+    /// Where the child's stderr goes, like `stdout_mode`
+    pub fn stderr_mode(&mut self, mode: OutputMode) {
+        self.opts.stderr_mode = mode;
+    }
+
+    /// Limit the address space of the child to `mem_bytes` (RLIMIT_AS)
+    /// and its CPU time to `cpu_secs` (RLIMIT_CPU), None lifts a limit
+    /// set before. A candidate that makes the target allocate without
+    /// bound fails its allocations instead of exhausting memory, and a
+    /// child killed by the CPU limit makes `finish` return a
+    /// ResourceLimit error rather than a timeout
+    pub fn with_limits(&mut self, mem_bytes: Option<u64>, cpu_secs: Option<u64>) {
+        self.opts.memory_limit = mem_bytes;
+        self.opts.cpu_limit = cpu_secs;
+    }
+
+    /// Run the child as `uid` and `gid`, with no other groups, e.g. so a
+    /// target doesn't run as root when b7 does for its perf counters.
+    /// Spawning fails unless b7 may switch to them
+    pub fn run_as(&mut self, uid: u32, gid: u32) {
+        self.opts.run_as = Some((uid, gid));
+    }
+
+    /// Keep a crashing child from writing a core file (RLIMIT_CORE 0),
+    /// which can take seconds per crash. Off for a plain Process, on for
+    /// the instances of a ProcessTemplate
+    pub fn disable_core_dumps(&mut self, disable: bool) {
+        self.opts.no_core_dumps = disable;
+    }
+
+    /// Run the child on CPU `core` only, e.g. a different core for the
+    /// children of every worker. Overrides `pin_cpu`, and works
+    /// without the pin-cpu feature. Fails if b7 itself may not
+    /// run on `core`, e.g. because it doesn't exist
+    pub fn cpu_affinity(&mut self, core: usize) -> Result<(), SolverError> {
+        check_cpu(core)?;
+        self.opts.cpu = Some(core);
+        Ok(())
+    }
+
+    // Restrict the child to one of the CPUs we are allowed to run on,
+    // a different one for every thread that spawns children, so that
+    // parallel workers don't compete for a single core. If the affinity
+    // mask can't be read, warn and run unpinned
+    #[cfg(feature = "pin-cpu")]
+    fn pin_to_single_cpu(&mut self) {
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        if unsafe { libc::sched_getaffinity(0, size, &mut allowed) } != 0 {
+            warn!(
+                "Could not read CPU affinity, running the target unpinned: {}",
+                Error::last_os_error()
+            );
+            return;
+        }
+        let cpus: Vec<usize> = (0..libc::CPU_SETSIZE as usize)
+            .filter(|&i| unsafe { libc::CPU_ISSET(i, &allowed) })
+            .collect();
+        if cpus.is_empty() {
+            warn!("No usable CPU found, running the target unpinned");
+            return;
+        }
+
+        let index = PINNING_INDEX.with(|index| *index);
+        self.pin_to(cpus[index % cpus.len()]);
+    }
+
+    // Run the child on `cpu` only
+    fn pin_to(&mut self, cpu: usize) {
+        let size = std::mem::size_of::<libc::cpu_set_t>();
+        let mut single: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+        unsafe { libc::CPU_SET(cpu, &mut single) };
+        // sched_setaffinity is a plain syscall, so it is safe to call
+        // between fork and exec. Threads created by the target inherit it
+        self.hook(move || {
+            if unsafe { libc::sched_setaffinity(0, size, &single) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+
+    #[cfg(not(feature = "pin-cpu"))]
+    fn pin_to_single_cpu(&mut self) {
+        static WARNED: std::sync::Once = std::sync::Once::new();
+        WARNED.call_once(|| warn!("Built without the pin-cpu feature, running targets unpinned"));
+    }
+
+    pub fn spawn(self) -> Result<ProcessHandle, SolverError> {
         WAITER.spawn_process(self)
     }
 }
+
+// The var `name` as a number, None if it isn't set
+pub(crate) fn number_var<T: FromStr>(
+    vars: &HashMap<String, String>,
+    name: &str,
+) -> Result<Option<T>, SolverError> {
+    match vars.get(name) {
+        Some(value) => value.parse().map(Some).map_err(|_| {
+            SolverError::new(
+                Runner::MissingArgs,
+                &format!("bad {} value {}", name, value),
+            )
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Check that children can be pinned to CPU `core`: it must be one
+/// this process is allowed to run on
+pub fn check_cpu(core: usize) -> Result<(), SolverError> {
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    if unsafe { libc::sched_getaffinity(0, size, &mut allowed) } != 0 {
+        return Err(Error::last_os_error().into());
+    }
+    if core >= libc::CPU_SETSIZE as usize || !unsafe { libc::CPU_ISSET(core, &allowed) } {
+        return Err(SolverError::new(
+            Runner::MissingArgs,
+            &format!("CPU {} does not exist or is not available to b7", core),
+        ));
+    }
+    Ok(())
+}
+
+/// The uid and gid of `user`, which is a user name, a uid (with that
+/// user's primary group, or the same number as gid if there is no such
+/// user) or "uid:gid"
+pub fn lookup_user(user: &str) -> Result<(u32, u32), SolverError> {
+    let bad_user = || SolverError::new(Runner::MissingArgs, &format!("unknown user {}", user));
+    let mut parts = user.splitn(2, ':');
+    let name = parts.next().unwrap_or("");
+    if let Some(gid) = parts.next() {
+        let uid = name.parse().map_err(|_| bad_user())?;
+        let gid = gid.parse().map_err(|_| bad_user())?;
+        return Ok((uid, gid));
+    }
+    // apply_vars runs on every worker thread, so only the reentrant getpw*_r
+    match name.parse::<u32>() {
+        Ok(uid) => match getpw(|pwd, buf, len, result| unsafe {
+            libc::getpwuid_r(uid, pwd, buf, len, result)
+        })? {
+            Some(ids) => Ok(ids),
+            None => Ok((uid, uid)),
+        },
+        Err(_) => {
+            let name = std::ffi::CString::new(name).map_err(|_| bad_user())?;
+            getpw(|pwd, buf, len, result| unsafe {
+                libc::getpwnam_r(name.as_ptr(), pwd, buf, len, result)
+            })?
+            .ok_or_else(bad_user)
+        }
+    }
+}
+
+// (uid, gid) of the passwd entry `lookup` finds with getpw*_r, None if
+// there is none. The buffer grows until the entry fits
+fn getpw<F>(lookup: F) -> Result<Option<(u32, u32)>, SolverError>
+where
+    F: Fn(
+        *mut libc::passwd,
+        *mut libc::c_char,
+        libc::size_t,
+        *mut *mut libc::passwd,
+    ) -> libc::c_int,
+{
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        let mut pwd: libc::passwd = unsafe { mem::zeroed() };
+        let mut result = ptr::null_mut();
+        match lookup(&mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) {
+            0 if result.is_null() => return Ok(None),
+            0 => return Ok(Some((pwd.pw_uid, pwd.pw_gid))),
+            libc::ERANGE => {
+                let len = buf.len() * 2;
+                buf.resize(len, 0);
+            }
+            err => return Err(Error::from_raw_os_error(err).into()),
+        }
+    }
+}
+
+/// Check that targets can be run with `isolate_network`, by running
+/// /bin/true that way
+pub fn check_network_isolation() -> Result<(), SolverError> {
+    let mut process = Process::new("/bin/true");
+    process.isolate_network(true);
+    process.spawn()?.finish(KILL_TIMEOUT)?;
+    Ok(())
+}
+
+fn rlimit(soft: u64, hard: u64) -> libc::rlimit {
+    libc::rlimit {
+        rlim_cur: soft as libc::rlim_t,
+        rlim_max: hard as libc::rlim_t,
+    }
+}
+
+fn pollfd(fd: RawFd, events: libc::c_short) -> libc::pollfd {
+    libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    }
+}
+
+// Run `f` with SIGPIPE blocked on this thread, so that writing to a
+// child that already exited fails with EPIPE. Rust programs ignore
+// SIGPIPE anyway, but a C program using b7 through the ffi may not,
+// and would be killed by it
+fn without_sigpipe<T, F: FnOnce() -> T>(f: F) -> T {
+    let mut pipe = SigSet::empty();
+    pipe.add(Signal::SIGPIPE);
+    let mut old = SigSet::empty();
+    if signal::pthread_sigmask(SigmaskHow::SIG_BLOCK, Some(&pipe), Some(&mut old)).is_err() {
+        return f();
+    }
+    let result = f();
+    if !old.contains(Signal::SIGPIPE) {
+        // take a SIGPIPE that f raised, or it arrives once unblocked
+        let mut pending: libc::sigset_t = unsafe { mem::zeroed() };
+        if unsafe { libc::sigpending(&mut pending) } == 0
+            && unsafe { libc::sigismember(&pending, libc::SIGPIPE) } == 1
+        {
+            let zero = libc::timespec {
+                tv_sec: 0,
+                tv_nsec: 0,
+            };
+            unsafe { libc::sigtimedwait(pipe.as_ref(), ptr::null_mut(), &zero) };
+        }
+        let _ = signal::pthread_sigmask(SigmaskHow::SIG_SETMASK, Some(&old), None);
+    }
+    result
+}
+
+// poll(2) that retries on EINTR, returns how many fds are ready
+fn poll_fds(fds: &mut [libc::pollfd], timeout: Duration) -> Result<usize, SolverError> {
+    let ms = timeout.as_millis().min(libc::c_int::max_value() as u128) as libc::c_int;
+    loop {
+        let ret = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, ms) };
+        if ret >= 0 {
+            return Ok(ret as usize);
+        }
+        let err = Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err.into());
+        }
+    }
+}
+
+/// `path` made absolute against B7's own working directory, so that an
+/// argument naming a file, like the target given to drrun, still finds
+/// it when the child runs elsewhere (see `ProcessTemplate::current_dir`)
+pub fn absolute_path(path: &str) -> OsString {
+    let relative = Path::new(path);
+    if relative.is_relative() {
+        if let Ok(cwd) = env::current_dir() {
+            return cwd.join(relative).into_os_string();
+        }
+    }
+    OsString::from(path)
+}
+
+// Look up a bare program name in PATH, the same way Command would
+fn resolve_program(path: &str) -> OsString {
+    if path.contains('/') {
+        return OsString::from(path);
+    }
+    if let Some(dirs) = env::var_os("PATH") {
+        for dir in env::split_paths(&dirs) {
+            let candidate = dir.join(path);
+            if candidate.is_file() {
+                return candidate.into_os_string();
+            }
+        }
+    }
+    OsString::from(path)
+}
+
+/// Everything about a Process that stays the same from run to run:
+/// the resolved program, fixed arguments and spawn options.
+/// Solvers build one per session and call `instantiate` for every
+/// candidate, which only has to add the candidate's argv and stdin
+#[derive(Debug, Clone)]
+pub struct ProcessTemplate {
+    binary: Arc<Binary>,
+    program: OsString,
+    args: Vec<OsString>,
+    // set before the candidate's own envp
+    envs: Vec<(OsString, OsString)>,
+    opts: SpawnOptions,
+    cwd: Option<PathBuf>,
+    // file read as stdin, and where the candidate's stdin goes in it
+    stdin_file: Option<(PathBuf, u64)>,
+    hooks: Hooks<SharedHook>,
+    // program and arguments the target is run through
+    wrapper: Option<(OsString, Vec<OsString>)>,
+}
+
+impl ProcessTemplate {
+    pub fn new(path: &str) -> ProcessTemplate {
+        ProcessTemplate {
+            binary: Arc::new(Binary::new(path)),
+            program: resolve_program(path),
+            args: Vec::new(),
+            envs: Vec::new(),
+            opts: SpawnOptions {
+                no_core_dumps: true,
+                ..SpawnOptions::new()
+            },
+            cwd: None,
+            stdin_file: None,
+            hooks: Hooks(Vec::new()),
+            wrapper: None,
+        }
+    }
+
+    /// Apply the session vars that concern how any target is run:
+    /// "pin_cpu", "cpu", "no_aslr", "cwd", "memory_limit" (bytes),
+    /// "cpu_limit" (seconds), "kill_policy", "max_output" (bytes),
+    /// "stdout_mode" and "stderr_mode" (see `OutputMode`) and
+    /// "stdin_file" with "stdin_offset", "prompts", "pty", "no_network",
+    /// "core_dumps", "wrap" (a command split on whitespace, see `wrapper`)
+    /// and "run_as" (see `lookup_user`). "cpu", "kill_policy",
+    /// the output modes, "no_network" and "run_as" are checked by
+    /// `B7Opts::run`. A value that doesn't parse is a `MissingArgs`
+    /// error, rather than running the target without the limit
+    pub fn apply_vars(&mut self, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.pin_cpu(vars.contains_key("pin_cpu"));
+        self.opts.cpu = number_var(vars, "cpu")?;
+        self.disable_aslr(vars.contains_key("no_aslr"));
+        self.opts.memory_limit = number_var(vars, "memory_limit")?;
+        self.opts.cpu_limit = number_var(vars, "cpu_limit")?;
+        self.opts.kill_policy = vars
+            .get("kill_policy")
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or_default();
+        self.opts.max_output = number_var(vars, "max_output")?;
+        self.opts.stdout_mode = vars
+            .get("stdout_mode")
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or_default();
+        self.opts.stderr_mode = vars
+            .get("stderr_mode")
+            .map(|v| v.parse())
+            .transpose()?
+            .unwrap_or_default();
+        self.answer_prompts(vars.contains_key("prompts"));
+        self.use_pty(vars.contains_key("pty"));
+        self.isolate_network(vars.contains_key("no_network"));
+        self.disable_core_dumps(!vars.contains_key("core_dumps"));
+        self.opts.run_as = vars.get("run_as").map(|v| lookup_user(v)).transpose()?;
+        self.wrapper = vars.get("wrap").and_then(|v| {
+            let mut words = v.split_whitespace().map(OsString::from);
+            words.next().map(|cmd| (cmd, words.collect()))
+        });
+        if let Some(path) = vars.get("stdin_file") {
+            let offset = number_var(vars, "stdin_offset")?.unwrap_or(0);
+            self.input_file(Path::new(path), offset);
+        }
+        if let Some(dir) = vars.get("cwd") {
+            self.current_dir(dir);
+        }
+        Ok(())
+    }
+
+    pub fn arg<S: AsRef<OsStr>>(&mut self, arg: S) {
+        self.args.push(arg.as_ref().to_os_string());
+    }
+
+    pub fn args<I, S>(&mut self, args: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        for arg in args {
+            self.arg(arg);
+        }
+    }
+
+    /// Set an environment variable of every instance. A variable of the
+    /// same name in the candidate's envp wins, see `Process::env`
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(&mut self, key: K, value: V) {
+        let key = key.as_ref().to_os_string();
+        let value = value.as_ref().to_os_string();
+        self.envs.push((key, value));
+    }
+
+    pub fn envs<I, K, V>(&mut self, vars: I)
+    where
+        I: IntoIterator<Item = (K, V)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        for (key, value) in vars {
+            self.env(key, value);
+        }
+    }
+
+    /// See `Process::pre_exec`. Every instance runs the same `f`, so
+    /// it is a `Fn` rather than a `FnMut`
+    pub fn pre_exec<F>(&mut self, f: F)
+    where
+        F: Fn() -> io::Result<()> + Send + Sync + 'static,
+    {
+        self.hooks.0.push(Arc::new(f));
+    }
+
+    /// See `Process::wrapper`. For a template that already runs the
+    /// target through a tool like drrun, the wrapper goes in front of
+    /// that tool, so it is not measured along with the target
+    pub fn wrapper(&mut self, cmd: &str, args: &[&str]) {
+        let args = args.iter().map(OsString::from).collect();
+        self.wrapper = Some((OsString::from(cmd), args));
+    }
+
+    pub fn with_ptrace(&mut self, ptrace: bool) {
+        self.opts.ptrace = ptrace;
+    }
+
+    /// See `Process::ptrace_options`
+    pub fn ptrace_options(&mut self, options: ptrace::Options) {
+        self.opts.ptrace_options = options;
+        self.opts.ptrace = true;
+    }
+
+    pub fn trace_syscalls(&mut self, syscalls: bool) {
+        self.opts.syscalls = syscalls;
+        if syscalls {
+            self.opts.ptrace = true;
+        }
+    }
+
+    pub fn pin_cpu(&mut self, pin: bool) {
+        self.opts.pin_cpu = pin;
+    }
+
+    pub fn disable_aslr(&mut self, disable: bool) {
+        self.opts.no_aslr = disable;
+    }
+
+    /// How `finish` gets rid of the child when it times out
+    pub fn kill_policy(&mut self, policy: KillPolicy) {
+        self.opts.kill_policy = policy;
+    }
+
+    /// Keep at most `bytes` of stdout in `read_stdout`, see
+    /// `ProcessHandle::stdout_truncated`. Unlimited by default
+    pub fn max_output_bytes(&mut self, bytes: usize) {
+        self.opts.max_output = Some(bytes);
+    }
+
+    /// See `Process::stdout_mode`
+    pub fn stdout_mode(&mut self, mode: OutputMode) {
+        self.opts.stdout_mode = mode;
+    }
+
+    /// See `Process::stderr_mode`
+    pub fn stderr_mode(&mut self, mode: OutputMode) {
+        self.opts.stderr_mode = mode;
+    }
+
+    /// See `Process::with_limits`
+    pub fn with_limits(&mut self, mem_bytes: Option<u64>, cpu_secs: Option<u64>) {
+        self.opts.memory_limit = mem_bytes;
+        self.opts.cpu_limit = cpu_secs;
+    }
+
+    pub fn disable_core_dumps(&mut self, disable: bool) {
+        self.opts.no_core_dumps = disable;
+    }
+
+    pub fn run_as(&mut self, uid: u32, gid: u32) {
+        self.opts.run_as = Some((uid, gid));
+    }
+
+    /// Feed every instance the file at `path` on stdin, with the stdin
+    /// of its input written over a copy of it at `offset`. Inputs with
+    /// an empty stdin get the file itself, see `Process::input_file`
+    pub fn input_file(&mut self, path: &Path, offset: u64) {
+        self.stdin_file = Some((path.to_path_buf(), offset));
+    }
+
+    pub fn cpu_affinity(&mut self, core: usize) -> Result<(), SolverError> {
+        check_cpu(core)?;
+        self.opts.cpu = Some(core);
+        Ok(())
+    }
+
+    pub fn interactive(&mut self, interactive: bool) {
+        self.opts.interactive = interactive;
+    }
+
+    pub fn answer_prompts(&mut self, prompts: bool) {
+        self.opts.prompts = prompts;
+    }
+
+    pub fn use_pty(&mut self, pty: bool) {
+        self.opts.pty = pty;
+    }
+
+    pub fn isolate_network(&mut self, isolate: bool) {
+        self.opts.isolate_network = isolate;
+    }
+
+    /// Run every instance in `dir`. The program path stays relative to
+    /// B7's own working directory, like the target path on the command line.
+    /// Arguments are passed on as they are, so a solver that passes the
+    /// target or another file as an argument makes it absolute first,
+    /// see `absolute_path`
+    pub fn current_dir<P: AsRef<Path>>(&mut self, dir: P) {
+        let program = Path::new(&self.program);
+        if program.is_relative() && program.components().count() > 1 {
+            if let Ok(cwd) = env::current_dir() {
+                self.program = cwd.join(program).into_os_string();
+            }
+        }
+        self.cwd = Some(dir.as_ref().to_path_buf());
+    }
+
+    // Build the Process for a single run of the candidate
+    pub fn instantiate(&self, inp: &Input) -> Process {
+        let mut cmd = match &self.wrapper {
+            Some((wrapper, args)) => {
+                let mut cmd = Command::new(wrapper);
+                cmd.args(args);
+                cmd.arg(&self.program);
+                cmd
+            }
+            None => Command::new(Path::new(&self.program)),
+        };
+        cmd.args(&self.args);
+        for arg in inp.argv.iter() {
+            cmd.arg(OsStr::from_bytes(arg));
+        }
+        for (key, value) in &self.envs {
+            cmd.env(key, value);
+        }
+        for (key, value) in inp.envp.iter() {
+            cmd.env(OsStr::from_bytes(key), OsStr::from_bytes(value));
+        }
+        if let Some(dir) = &self.cwd {
+            cmd.current_dir(dir);
+        }
+        Process {
+            binary: self.binary.clone(),
+            cmd,
+            child: None,
+            input: inp.stdin.clone(),
+            file_input: inp.file.as_ref().map(|f| f.contents.clone()),
+            input_file: None,
+            stdin_file: self.stdin_file.as_ref().map(|f| f.0.clone()),
+            stdin_overlay: match &self.stdin_file {
+                Some((_, offset)) if !inp.stdin.is_empty() => Some((*offset, inp.stdin.clone())),
+                _ => None,
+            },
+            stdin_copy: None,
+            opts: self.opts.clone(),
+            pty_master: None,
+            input_sequence: Vec::new(),
+            input_delay: Duration::default(),
+            user_hooks: Hooks(
+                self.hooks
+                    .0
+                    .iter()
+                    .map(|hook| {
+                        let hook = hook.clone();
+                        Box::new(move || hook()) as PreExecHook
+                    })
+                    .collect(),
+            ),
+            wrapper: self.wrapper.as_ref().map(|w| w.0.clone()),
+        }
+    }
+
+    /// Spawn an instance that gets `stdin` and no further arguments,
+    /// e.g. to run the same command on many inputs
+    pub fn spawn_with_input(&self, stdin: Vec<u8>) -> Result<ProcessHandle, SolverError> {
+        self.instantiate(&Input::new(vec![], stdin)).spawn()
+    }
+}
@@ -0,0 +1,235 @@
+use crate::binary::Binary;
+use crate::brute::*;
+use crate::dynamorio::stderr_excerpt;
+use crate::errors::*;
+use crate::process::{absolute_path, OutputMode, ProcessTemplate};
+use std::collections::HashMap;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// numbers the exec logs of this b7 process
+static EXEC_LOGS: AtomicUsize = AtomicUsize::new(0);
+
+/// Counts instructions of targets for other architectures, e.g. ARM or
+/// MIPS CTF binaries, under qemu-user. With the "qemu_plugin" var set
+/// to QEMU's libinsn.so the count is exact. Without, the translation
+/// blocks qemu logs as executed are counted, which works with any
+/// version of QEMU but is coarser and slower.
+///
+/// The "qemu_arch" var overrides the architecture read from the target,
+/// and "qemu" the path of the qemu binary (qemu-ARCH by default)
+#[derive(Clone, Default)]
+pub struct QemuSolver {
+    template: Option<ProcessTemplate>,
+}
+
+impl QemuSolver {
+    pub fn new() -> QemuSolver {
+        QemuSolver { template: None }
+    }
+}
+
+// qemu's command line is the same for every run, only the target's
+// own argv and stdin change
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let qemu = match vars.get("qemu") {
+        Some(qemu) => qemu.clone(),
+        None => match vars.get("qemu_arch") {
+            Some(arch) => format!("qemu-{}", arch),
+            None => format!("qemu-{}", Binary::new(path).arch()?),
+        },
+    };
+    let mut template = ProcessTemplate::new(&qemu);
+    match vars.get("qemu_plugin") {
+        Some(plugin) => {
+            template.arg("-plugin");
+            template.arg(absolute_path(plugin));
+            template.arg("-d");
+            template.arg("plugin");
+        }
+        // without chaining, every block that runs is logged
+        None => {
+            template.arg("-d");
+            template.arg("exec,nochain");
+        }
+    }
+    // the child may run in the "cwd" var's directory
+    template.arg(absolute_path(path));
+    template.apply_vars(vars)?;
+    // the plugin prints the count to stderr
+    template.stderr_mode(OutputMode::Piped);
+    Ok(template)
+}
+
+// libinsn prints "insns: N", newer versions "total insns: N"
+fn parse_insns(stderr: &[u8]) -> Option<i64> {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr
+        .lines()
+        .rev()
+        .filter_map(|line| line.split("insns: ").nth(1))
+        .filter_map(|count| count.trim().parse().ok())
+        .next()
+}
+
+// executed blocks are logged as "Trace 0: 0x... [...]"
+fn count_blocks(log: &mut dyn BufRead) -> Result<i64, SolverError> {
+    let mut blocks = 0;
+    let mut line = Vec::new();
+    while log.read_until(b'\n', &mut line)? > 0 {
+        if line.starts_with(b"Trace ") {
+            blocks += 1;
+        }
+        line.clear();
+    }
+    Ok(blocks)
+}
+
+// exec log of one run, removed when dropped. It goes to a file since
+// a pipe that full would block the target until it timed out
+struct ExecLog {
+    path: PathBuf,
+}
+
+impl ExecLog {
+    fn new() -> ExecLog {
+        ExecLog {
+            path: env::temp_dir().join(format!(
+                "b7-qemu-{}-{}.log",
+                std::process::id(),
+                EXEC_LOGS.fetch_add(1, Ordering::SeqCst)
+            )),
+        }
+    }
+}
+
+impl Drop for ExecLog {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+impl InstCounter for QemuSolver {
+    fn name(&self) -> &str {
+        "qemu"
+    }
+
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.template.as_ref()
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.template = Some(make_template(path, vars)?);
+        Ok(())
+    }
+
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let template = template_for(self.template.as_ref(), data, make_template)?;
+
+        let plugin = data.vars.contains_key("qemu_plugin");
+        let log = ExecLog::new();
+        let mut process = template.instantiate(&data.inp);
+        if !plugin {
+            // the same as -D, which would have to come before the target
+            process.env("QEMU_LOG_FILENAME", &log.path);
+        }
+
+        let output = match run_process(process, data.timeout)? {
+            (ChildOutcome::TimedOut, _) => {
+                return Err(SolverError::new(Runner::Timeout, "child timed out"));
+            }
+            // qemu passes on how the target ended, and the count of what
+            // ran before a crash is still there
+            (
+                ChildOutcome::Signaled {
+                    signal,
+                    core_dumped,
+                },
+                output,
+            ) => {
+                debug!(
+                    "Target killed by {:?} (core dumped: {}) on {:?}",
+                    signal, core_dumped, data.inp
+                );
+                output
+            }
+            (_, output) => output,
+        };
+
+        if !plugin {
+            let file = File::open(&log.path).map_err(|e| {
+                SolverError::with_source(
+                    Runner::IoError,
+                    &format!(
+                        "qemu wrote no exec log, stderr: {}",
+                        stderr_excerpt(&output.stderr)
+                    ),
+                    e,
+                )
+            })?;
+            return count_blocks(&mut BufReader::new(file));
+        }
+        match parse_insns(&output.stderr) {
+            Some(count) => Ok(count),
+            None => Err(SolverError::new(
+                Runner::IoError,
+                &format!(
+                    "Could not parse the qemu plugin's instruction count, stderr: {}",
+                    stderr_excerpt(&output.stderr)
+                ),
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_blocks, make_template, parse_insns};
+    use std::collections::HashMap;
+
+    #[test]
+    fn plugin_output() {
+        assert_eq!(parse_insns(b"insns: 1234\n"), Some(1234));
+        assert_eq!(parse_insns(b"hello\ntotal insns: 56\n"), Some(56));
+        assert_eq!(parse_insns(b"qemu: uncaught target signal 11\n"), None);
+    }
+
+    #[test]
+    fn exec_log() {
+        let log = b"Trace 0: 0x7f00 [00000000/00010000/0xb]\n\
+                    Linking TBs 0x7f00 to 0x7f40\n\
+                    Trace 0: 0x7f40 [00000000/00010040/0xb]\n";
+        assert_eq!(count_blocks(&mut &log[..]).unwrap(), 2);
+    }
+
+    #[test]
+    fn arch_from_target() {
+        let path = format!("{}/tests/strlen_test_x86", env!("CARGO_MANIFEST_DIR"));
+        let template = make_template(&path, &HashMap::new()).unwrap();
+        assert!(format!("{:?}", template).contains("qemu-i386"));
+
+        let mut vars = HashMap::new();
+        vars.insert(String::from("qemu_arch"), String::from("arm"));
+        let template = make_template(&path, &vars).unwrap();
+        assert!(format!("{:?}", template).contains("qemu-arm"));
+    }
+
+    // a relative target still names the same file with another cwd
+    #[test]
+    fn relative_target() {
+        let mut vars = HashMap::new();
+        vars.insert(String::from("qemu"), String::from("qemu-i386"));
+        vars.insert(String::from("cwd"), String::from("/"));
+        let template = make_template("tests/strlen_test_x86", &vars).unwrap();
+        let target = std::env::current_dir()
+            .unwrap()
+            .join("tests/strlen_test_x86");
+        assert!(format!("{:?}", template).contains(&*target.to_string_lossy()));
+    }
+}
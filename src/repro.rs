@@ -0,0 +1,168 @@
+use crate::errors::*;
+use crate::process::number_var;
+use crate::B7Results;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+// quote bytes for a POSIX shell. Anything goes inside single quotes
+// except a single quote itself, which has to close and reopen them
+fn shell_quote(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![b'\''];
+    for &b in bytes {
+        if b == b'\'' {
+            out.extend_from_slice(b"'\\''");
+        } else {
+            out.push(b);
+        }
+    }
+    out.push(b'\'');
+    out
+}
+
+// printf format string producing exactly `bytes`. Everything outside
+// printable ascii is an octal escape, so NULs and trailing newlines
+// survive and the script itself stays readable
+fn printf_format(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![b'\''];
+    for &b in bytes {
+        match b {
+            b'%' => out.extend_from_slice(b"%%"),
+            0x20..=0x7e if b != b'\\' && b != b'\'' => out.push(b),
+            _ => out.extend_from_slice(format!("\\{:03o}", b).as_bytes()),
+        }
+    }
+    out.push(b'\'');
+    out
+}
+
+// What the target read on stdin with the "stdin_file" var set: the file
+// with the solved stdin written over it at "stdin_offset", see
+// ProcessTemplate::input_file
+fn patched_stdin(
+    path: &Path,
+    vars: &HashMap<String, String>,
+    stdin: &[u8],
+) -> Result<Vec<u8>, SolverError> {
+    let mut contents = fs::read(path)?;
+    if !stdin.is_empty() {
+        let offset = number_var::<usize>(vars, "stdin_offset")?.unwrap_or(0);
+        let end = offset + stdin.len();
+        if contents.len() < end {
+            contents.resize(end, 0);
+        }
+        contents[offset..end].copy_from_slice(stdin);
+    }
+    Ok(contents)
+}
+
+/// Shell script that runs `target` with the solved input and exits with
+/// its status. The target gets B7's environment and nothing else
+/// (`env -i`), and the input files of the run are written to a temporary
+/// directory first. Arguments are written as-is (quoted), so the script
+/// is only as printable as the solution. `vars` are the vars of the run
+pub fn script(
+    target: &Path,
+    solver: &str,
+    vars: &HashMap<String, String>,
+    results: &B7Results,
+) -> Result<Vec<u8>, SolverError> {
+    let target = fs::canonicalize(target)?;
+    let hash = Sha256::digest(&fs::read(&target)?);
+    let stdin_file = match vars.get("stdin_file") {
+        Some(path) => Some(patched_stdin(Path::new(path), vars, &results.stdin)?),
+        None => None,
+    };
+
+    let mut out = Vec::new();
+    out.extend_from_slice(b"#!/bin/sh\n");
+    out.extend_from_slice(b"# Replays the input found by b7\n");
+    out.extend_from_slice(format!("# target: {}\n", target.display()).as_bytes());
+    out.extend_from_slice(format!("# sha256: {:x}\n", hash).as_bytes());
+    out.extend_from_slice(format!("# solver: {}\n", solver).as_bytes());
+    for phase in &results.stats.phases {
+        out.extend_from_slice(
+            format!(
+                "# phase {}: {} runs in {}s\n",
+                phase.name,
+                phase.runs,
+                phase.elapsed.as_secs()
+            )
+            .as_bytes(),
+        );
+    }
+
+    if stdin_file.is_some() || !results.file.is_empty() {
+        out.extend_from_slice(b"dir=$(mktemp -d) || exit 1\n");
+        out.extend_from_slice(b"trap 'rm -rf \"$dir\"' EXIT\n");
+    }
+    if let Some(contents) = &stdin_file {
+        out.extend_from_slice(b"printf ");
+        out.extend_from_slice(&printf_format(contents));
+        out.extend_from_slice(b" > \"$dir/stdin\"\n");
+    }
+    if !results.file.is_empty() {
+        out.extend_from_slice(b"printf ");
+        out.extend_from_slice(&printf_format(&results.file));
+        out.extend_from_slice(b" > \"$dir/file\"\n");
+    }
+
+    if stdin_file.is_none() {
+        out.extend_from_slice(b"printf ");
+        out.extend_from_slice(&printf_format(&results.stdin));
+        out.extend_from_slice(b" | ");
+    }
+    out.extend_from_slice(b"env -i");
+    for (key, value) in env::vars_os() {
+        let mut var = key.as_bytes().to_vec();
+        var.push(b'=');
+        var.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(b" \\\n    ");
+        out.extend_from_slice(&shell_quote(&var));
+    }
+    out.extend_from_slice(b" \\\n    ");
+    out.extend_from_slice(&shell_quote(target.as_os_str().as_bytes()));
+    for arg in &results.argv {
+        out.push(b' ');
+        out.extend_from_slice(&shell_quote(arg));
+    }
+    // the file input's path is the last argument, see Input::file
+    if !results.file.is_empty() {
+        out.extend_from_slice(b" \"$dir/file\"");
+    }
+    if stdin_file.is_some() {
+        out.extend_from_slice(b" < \"$dir/stdin\"");
+    }
+    out.extend_from_slice(b"\nexit $?\n");
+    Ok(out)
+}
+
+/// Write `script` to `out` and make it executable
+pub fn write_script(
+    out: &Path,
+    target: &Path,
+    solver: &str,
+    vars: &HashMap<String, String>,
+    results: &B7Results,
+) -> Result<(), SolverError> {
+    fs::write(out, script(target, solver, vars, results)?)?;
+    fs::set_permissions(out, fs::Permissions::from_mode(0o755))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{printf_format, shell_quote};
+
+    #[test]
+    fn quoting() {
+        assert_eq!(shell_quote(b"a b"), b"'a b'");
+        assert_eq!(shell_quote(b"it's"), b"'it'\\''s'");
+        assert_eq!(printf_format(b"100%\n"), b"'100%%\\012'");
+        assert_eq!(printf_format(b"\\'\0\xff"), b"'\\134\\047\\000\\377'");
+    }
+}
@@ -1,4 +1,8 @@
+use crate::generators::Input;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fmt::Debug;
+use std::time::Duration;
 
 fn get_average(input: &[i64]) -> i64 {
     if input.is_empty() {
@@ -30,10 +34,318 @@ pub fn find_outlier<I: Debug>(counts: &[(I, i64)]) -> &(I, i64) {
     &counts[max_idx]
 }
 
+/// Counters collected while brute forcing a single phase
+/// (e.g. stdin length or argv contents)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PhaseStats {
+    pub name: String,
+    pub runs: u64,
+    // candidates whose count came from the memo instead of a run
+    pub memo_hits: u64,
+    pub errors: u64,
+    pub timeouts: u64,
+    // runs a solver failed with Runner::Crashed, also counted as errors
+    #[serde(default)]
+    pub crashes: u64,
+    pub elapsed: Duration,
+    // wall clock time of the runs themselves, summed over the workers
+    #[serde(default)]
+    pub run_time: Duration,
+    // the candidate that took longest, and how long one run of it took
+    #[serde(default)]
+    pub slowest: Option<(String, Duration)>,
+    // rounds whose winner was too close to the reference count
+    pub uncertain: Vec<u32>,
+    // rounds where other candidates came within the tie threshold of
+    // the winner, so the pick between them may be wrong
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ties: Vec<Tie>,
+    // (round, candidate) for every round whose winner was replaced by
+    // the candidate after the next round found nothing, see max_backtrack
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backtracks: Vec<(u32, u64)>,
+    // (candidate, instruction count) pairs tried in every round
+    pub rounds: Vec<Vec<(u64, i64)>>,
+    // candidate the brute went on with after every round, by the index
+    // of the round in `rounds`. Backtracking replaces a pick, and a round
+    // stopped before it was decided has none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub picks: Vec<u64>,
+    // input the success detector accepted, which ended the phase early
+    pub success: Option<Input>,
+    // stopped by a CancelToken before the phase was done
+    #[serde(default)]
+    pub cancelled: bool,
+    // command lines a dry run would have run, see B7Opts::set_dry_run
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub commands: Vec<String>,
+}
+
+/// A round whose winner didn't stand out from some other candidates
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tie {
+    pub round: u32,
+    /// (candidate, count) of the winner, then of everything tied with it
+    pub candidates: Vec<(u64, i64)>,
+}
+
+/// How one round of a phase was decided, e.g. one byte of stdin
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Decision {
+    pub round: usize,
+    pub winner: u64,
+    pub count: i64,
+    // the candidate whose count came closest to the winner's, and that
+    // count. None if the winner was the only candidate
+    pub runner_up: Option<(u64, i64)>,
+    // distance between the two counts, 0 for a tie
+    pub margin: Option<i64>,
+}
+
+impl PhaseStats {
+    /// The winner of every decided round, as picked by the brute after
+    /// any backtracking, with its count and how close the runner-up came
+    pub fn decisions(&self) -> Vec<Decision> {
+        self.rounds
+            .iter()
+            .zip(&self.picks)
+            .enumerate()
+            .filter_map(|(pos, (round, &winner))| {
+                let &(_, count) = round.iter().find(|r| r.0 == winner)?;
+                let runner_up = round
+                    .iter()
+                    .filter(|r| r.0 != winner)
+                    .min_by_key(|r| (r.1 - count).abs())
+                    .cloned();
+                Some(Decision {
+                    round: pos,
+                    winner,
+                    count,
+                    runner_up,
+                    margin: runner_up.map(|r| (r.1 - count).abs()),
+                })
+            })
+            .collect()
+    }
+
+    pub fn runs_per_sec(&self) -> f64 {
+        let secs = duration_secs(self.elapsed);
+        if secs == 0.0 {
+            return 0.0;
+        }
+        self.runs as f64 / secs
+    }
+
+    /// Average wall clock time of a single run, zero if nothing ran
+    pub fn mean_latency(&self) -> Duration {
+        if self.runs == 0 {
+            return Duration::new(0, 0);
+        }
+        let nanos =
+            self.run_time.as_secs() * 1_000_000_000 + u64::from(self.run_time.subsec_nanos());
+        let mean = nanos / self.runs;
+        Duration::new(mean / 1_000_000_000, (mean % 1_000_000_000) as u32)
+    }
+
+    /// Records `runs` runs of `candidate` that took `time` together
+    pub fn add_run_time(&mut self, candidate: &str, time: Duration, runs: u32) {
+        self.run_time += time;
+        let latency = time / runs.max(1);
+        if self.slowest.as_ref().map_or(true, |s| latency > s.1) {
+            self.slowest = Some((candidate.to_string(), latency));
+        }
+    }
+}
+
+/// Statistics for a whole B7 run, one entry per phase
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    pub phases: Vec<PhaseStats>,
+}
+
+impl RunStats {
+    pub fn new() -> RunStats {
+        RunStats { phases: Vec::new() }
+    }
+
+    // record a finished phase under the given name
+    pub fn push(&mut self, name: &str, mut phase: PhaseStats) {
+        phase.name = name.to_string();
+        self.phases.push(phase);
+    }
+
+    // sum of every phase
+    pub fn total(&self) -> PhaseStats {
+        self.phases.iter().fold(
+            PhaseStats {
+                name: "total".to_string(),
+                ..PhaseStats::default()
+            },
+            |mut total, phase| {
+                total.runs += phase.runs;
+                total.memo_hits += phase.memo_hits;
+                total.errors += phase.errors;
+                total.timeouts += phase.timeouts;
+                total.crashes += phase.crashes;
+                total.elapsed += phase.elapsed;
+                total.run_time += phase.run_time;
+                if let Some(ref slowest) = phase.slowest {
+                    if total.slowest.as_ref().map_or(true, |s| slowest.1 > s.1) {
+                        total.slowest = Some(slowest.clone());
+                    }
+                }
+                total.uncertain.extend(&phase.uncertain);
+                total.ties.extend(phase.ties.iter().cloned());
+                total.backtracks.extend(&phase.backtracks);
+                total
+            },
+        )
+    }
+
+    /// Reasons not to trust the counts of the run, e.g. runs that failed
+    /// or a solver that fell back to measuring something else
+    pub fn warnings(&self, fallback: Option<&str>) -> Vec<String> {
+        let total = self.total();
+        let mut warnings = Vec::new();
+        if let Some(fallback) = fallback {
+            warnings.push(format!(
+                "the solver fell back to {}, counts are not instruction counts",
+                fallback
+            ));
+        }
+        if total.errors > 0 {
+            warnings.push(format!(
+                "{} of {} runs failed ({} timeouts, {} crashes)",
+                total.errors, total.runs, total.timeouts, total.crashes
+            ));
+        }
+        if !total.uncertain.is_empty() {
+            warnings.push(format!(
+                "rounds too close to the reference count to trust: {}",
+                total.uncertain.len()
+            ));
+        }
+        if !total.ties.is_empty() {
+            warnings.push(format!(
+                "rounds with candidates tied with the winner: {}",
+                total.ties.len()
+            ));
+        }
+        if !total.backtracks.is_empty() {
+            warnings.push(format!(
+                "rounds retried with their runner-up: {}",
+                total.backtracks.len()
+            ));
+        }
+        warnings
+    }
+
+    /// The table, then the warnings, a line each. Printed by --stats and
+    /// shown in the TUI once the run is done
+    pub fn report(&self, fallback: Option<&str>) -> Vec<String> {
+        let mut lines: Vec<String> = self.to_string().lines().map(String::from).collect();
+        for warning in self.warnings(fallback) {
+            lines.push(format!("warning: {}", warning));
+        }
+        lines
+    }
+}
+
+// A winning count that doesn't differ from the count of an input
+// with no signal by more than `margin` can't be trusted
+pub fn is_uncertain(count: i64, reference: i64, margin: i64) -> bool {
+    (count - reference).abs() <= margin
+}
+
+// The candidates other than `winner` whose count is within `threshold`
+// of its count, in the order of `counts`
+pub fn tied_with<'a, I: PartialEq>(
+    counts: &'a [(I, i64)],
+    winner: &(I, i64),
+    threshold: i64,
+) -> Vec<&'a (I, i64)> {
+    counts
+        .iter()
+        .filter(|c| c.0 != winner.0 && (c.1 - winner.1).abs() <= threshold)
+        .collect()
+}
+
+// Whether no candidate of a round stands out, every count being within
+// `threshold` of every other
+pub fn is_flat<I>(counts: &[(I, i64)], threshold: i64) -> bool {
+    let min = counts.iter().map(|c| c.1).min();
+    let max = counts.iter().map(|c| c.1).max();
+    match (min, max) {
+        (Some(min), Some(max)) => counts.len() > 1 && max - min <= threshold,
+        _ => false,
+    }
+}
+
+/// Rough wall clock time for `runs` executions taking `per_run`
+/// each, spread over `workers` threads
+pub fn estimate_duration(runs: u64, per_run: Duration, workers: usize) -> Duration {
+    let workers = workers.max(1) as u64;
+    let batches = (runs + workers - 1) / workers;
+    let nanos = per_run.as_secs() * 1_000_000_000 + u64::from(per_run.subsec_nanos());
+    let total = nanos.saturating_mul(batches);
+    Duration::new(total / 1_000_000_000, (total % 1_000_000_000) as u32)
+}
+
+fn duration_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9
+}
+
+fn write_row(f: &mut fmt::Formatter, phase: &PhaseStats) -> fmt::Result {
+    writeln!(
+        f,
+        "{:<12} {:>8} {:>8} {:>8} {:>9} {:>8} {:>8} {:>9} {:>9.2} {:>9.1} {:>9.2}",
+        phase.name,
+        phase.runs,
+        phase.memo_hits,
+        phase.errors,
+        phase.timeouts,
+        phase.crashes,
+        phase.backtracks.len(),
+        phase.uncertain.len(),
+        duration_secs(phase.elapsed),
+        phase.runs_per_sec(),
+        duration_secs(phase.mean_latency()) * 1e3
+    )
+}
+
+// human readable table of the run
+impl fmt::Display for RunStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{:<12} {:>8} {:>8} {:>8} {:>9} {:>8} {:>8} {:>9} {:>9} {:>9} {:>9}",
+            "phase",
+            "runs",
+            "cached",
+            "errors",
+            "timeouts",
+            "crashes",
+            "retries",
+            "uncertain",
+            "time (s)",
+            "runs/sec",
+            "ms/run"
+        )?;
+        for phase in &self.phases {
+            write_row(f, phase)?;
+        }
+        write_row(f, &self.total())
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{find_outlier, get_average};
+    use super::{
+        estimate_duration, find_outlier, get_average, is_flat, is_uncertain, tied_with, Decision,
+        PhaseStats, RunStats,
+    };
     use std::str::FromStr;
+    use std::time::Duration;
 
     #[test]
     fn test_average() {
@@ -69,4 +381,120 @@ mod tests {
     fn outlier_test_panic() {
         find_outlier(&[] as &[(String, i64)]);
     }
+
+    #[test]
+    fn uncertain() {
+        assert!(is_uncertain(100, 100, 0));
+        assert!(is_uncertain(103, 100, 5));
+        assert!(is_uncertain(97, 100, 5));
+        assert!(!is_uncertain(106, 100, 5));
+        assert!(!is_uncertain(101, 100, 0));
+    }
+
+    #[test]
+    fn ties() {
+        let counts = [(1, 10), (2, 30), (3, 29), (4, 33)];
+        assert!(tied_with(&counts, &(2, 30), 0).is_empty());
+        assert_eq!(tied_with(&counts, &(2, 30), 1), vec![&(3, 29)]);
+        assert_eq!(tied_with(&counts, &(2, 30), 3), vec![&(3, 29), &(4, 33)]);
+    }
+
+    #[test]
+    fn flat() {
+        assert!(is_flat(&[(1, 10), (2, 10), (3, 10)], 0));
+        assert!(!is_flat(&[(1, 10), (2, 12), (3, 10)], 1));
+        assert!(is_flat(&[(1, 10), (2, 12), (3, 10)], 2));
+        // a single candidate can't be compared to anything
+        assert!(!is_flat(&[(1, 10)], 0));
+    }
+
+    #[test]
+    fn latency() {
+        let mut phase = PhaseStats::default();
+        assert_eq!(phase.mean_latency(), Duration::new(0, 0));
+
+        phase.runs = 4;
+        phase.add_run_time("a", Duration::from_millis(30), 3);
+        phase.add_run_time("b", Duration::from_millis(50), 1);
+        assert_eq!(phase.run_time, Duration::from_millis(80));
+        assert_eq!(phase.mean_latency(), Duration::from_millis(20));
+        assert_eq!(
+            phase.slowest,
+            Some((String::from("b"), Duration::from_millis(50)))
+        );
+
+        let mut stats = RunStats::new();
+        stats.push("stdin", phase);
+        stats.push("argv", PhaseStats::default());
+        let total = stats.total();
+        assert_eq!(total.run_time, Duration::from_millis(80));
+        assert_eq!(total.slowest.unwrap().0, "b");
+        assert!(stats.to_string().contains("20.00"));
+    }
+
+    #[test]
+    fn decisions() {
+        let phase = PhaseStats {
+            rounds: vec![
+                vec![(0x41, 10), (0x42, 10), (0x43, 30)],
+                vec![(0x41, 10), (0x42, 10)],
+                vec![(0x44, 7)],
+                // stopped before it was decided
+                vec![(0x41, 3), (0x42, 9)],
+            ],
+            picks: vec![0x43, 0x41, 0x44],
+            ..PhaseStats::default()
+        };
+        assert_eq!(
+            phase.decisions(),
+            vec![
+                Decision {
+                    round: 0,
+                    winner: 0x43,
+                    count: 30,
+                    runner_up: Some((0x41, 10)),
+                    margin: Some(20),
+                },
+                Decision {
+                    round: 1,
+                    winner: 0x41,
+                    count: 10,
+                    runner_up: Some((0x42, 10)),
+                    margin: Some(0),
+                },
+                Decision {
+                    round: 2,
+                    winner: 0x44,
+                    count: 7,
+                    runner_up: None,
+                    margin: None,
+                },
+            ]
+        );
+
+        // a backtrack replaced the outlier of round 0 with the runner-up
+        let phase = PhaseStats {
+            rounds: vec![vec![(0x41, 10), (0x42, 25), (0x43, 30)]],
+            picks: vec![0x42],
+            backtracks: vec![(0, 0x42)],
+            ..PhaseStats::default()
+        };
+        let decisions = phase.decisions();
+        assert_eq!(decisions[0].winner, 0x42);
+        assert_eq!(decisions[0].count, 25);
+        assert_eq!(decisions[0].runner_up, Some((0x43, 30)));
+    }
+
+    #[test]
+    fn estimate() {
+        let second = Duration::new(1, 0);
+        assert_eq!(estimate_duration(10, second, 4), Duration::new(3, 0));
+        assert_eq!(estimate_duration(8, second, 4), Duration::new(2, 0));
+        assert_eq!(estimate_duration(3, second, 0), Duration::new(3, 0));
+        assert_eq!(estimate_duration(0, second, 4), Duration::new(0, 0));
+        assert_eq!(
+            estimate_duration(5, Duration::new(0, 500_000_000), 1),
+            Duration::new(2, 500_000_000)
+        );
+    }
 }
@@ -0,0 +1,150 @@
+use crate::errors::*;
+use crate::generators::Input;
+use crate::process::ProcessTemplate;
+use nix::sys::ptrace::{self, Options};
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use nix::unistd::Pid;
+use std::collections::HashMap;
+use std::fmt;
+use std::mem;
+use std::time::Duration;
+
+// offset of regs.orig_rax in the `struct user` used by PTRACE_PEEKUSER.
+// It holds the syscall number at both the entry and the exit stop
+fn orig_rax_offset() -> usize {
+    let user: libc::user = unsafe { mem::zeroed() };
+    let base = &user as *const libc::user as usize;
+    let reg = &user.regs.orig_rax as *const _ as usize;
+    reg - base
+}
+
+fn syscall_nr(pid: Pid) -> Result<u64, SolverError> {
+    // PEEKUSER returns the word itself, so -1 is only an error if errno says so
+    unsafe { *libc::__errno_location() = 0 };
+    let res = unsafe {
+        libc::ptrace(
+            libc::PTRACE_PEEKUSER,
+            pid.as_raw(),
+            orig_rax_offset() as *mut libc::c_void,
+            std::ptr::null_mut::<libc::c_void>(),
+        )
+    };
+    if res == -1 && unsafe { *libc::__errno_location() } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(res as u64)
+}
+
+/// Runs `inp` under ptrace and returns the number of every syscall the
+/// target made, in order. This stops the target twice per syscall, so it
+/// is far too slow to do for every candidate
+pub fn record(
+    path: &str,
+    vars: &HashMap<String, String>,
+    inp: &Input,
+    timeout: Duration,
+) -> Result<Vec<u64>, SolverError> {
+    let mut template = ProcessTemplate::new(path);
+    template.apply_vars(vars)?;
+    template.trace_syscalls(true);
+
+    let handle = template.instantiate(inp).spawn()?;
+    let mut syscalls = Vec::new();
+    let mut started = false;
+    let mut entering = true;
+    let wrapper_execs = handle.wrapper_execs();
+    let mut skip = wrapper_execs;
+    handle.finish_with(timeout, |status| {
+        match *status {
+            WaitStatus::Stopped(pid, Signal::SIGTRAP) if !started => {
+                // first stop is the exec, only trace the target from
+                // there. Without the option, a wrapper's syscall stops
+                // would look like further execs
+                ptrace::setoptions(pid, Options::PTRACE_O_TRACESYSGOOD)?;
+                if skip > 0 {
+                    skip -= 1;
+                } else {
+                    started = true;
+                    // a wrapper's execve of the target still has its exit
+                    entering = wrapper_execs == 0;
+                }
+            }
+            WaitStatus::PtraceSyscall(_) if !started => {}
+            WaitStatus::PtraceSyscall(pid) => {
+                // stops alternate between entry and exit, only keep entries
+                if entering {
+                    syscalls.push(syscall_nr(pid)?);
+                }
+                entering = !entering;
+            }
+            _ => {}
+        }
+        Ok(())
+    })?;
+
+    Ok(syscalls)
+}
+
+/// Where the syscall traces of a round's winner and runner-up part ways
+#[derive(Clone, Debug, PartialEq)]
+pub struct Divergence {
+    pub index: usize,
+    // None if that trace ended first
+    pub winner: Option<u64>,
+    pub runner_up: Option<u64>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let show = |nr: Option<u64>| match nr {
+            Some(nr) => format!("syscall {}", nr),
+            None => String::from("exit"),
+        };
+        write!(
+            f,
+            "traces diverge at syscall #{}: winner made {}, runner-up made {}",
+            self.index,
+            show(self.winner),
+            show(self.runner_up)
+        )
+    }
+}
+
+/// First position at which the two traces differ, None if they are equal
+pub fn diverge(winner: &[u64], runner_up: &[u64]) -> Option<Divergence> {
+    let len = winner.len().max(runner_up.len());
+    (0..len)
+        .find(|&i| winner.get(i) != runner_up.get(i))
+        .map(|index| Divergence {
+            index,
+            winner: winner.get(index).cloned(),
+            runner_up: runner_up.get(index).cloned(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn divergence() {
+        assert_eq!(diverge(&[0, 1, 2], &[0, 1, 2]), None);
+        assert_eq!(
+            diverge(&[0, 1, 2], &[0, 3, 2]),
+            Some(Divergence {
+                index: 1,
+                winner: Some(1),
+                runner_up: Some(3),
+            })
+        );
+        assert_eq!(
+            diverge(&[0, 1], &[0, 1, 60]),
+            Some(Divergence {
+                index: 2,
+                winner: None,
+                runner_up: Some(60),
+            })
+        );
+    }
+}
@@ -0,0 +1,62 @@
+use crate::brute::*;
+use crate::errors::*;
+use crate::process::ProcessTemplate;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Scores candidates by wall clock time instead of instructions, for
+/// targets where the correct input takes the slow path (e.g. a sleep on
+/// a match). Set the timeout so that wrong inputs exit well before it:
+/// a run that hits the timeout is killed and scored above any run that
+/// finished, so the candidate that timed out wins
+#[derive(Clone, Default)]
+pub struct TimingSolver {
+    template: Option<ProcessTemplate>,
+}
+
+impl TimingSolver {
+    pub fn new() -> TimingSolver {
+        TimingSolver { template: None }
+    }
+}
+
+fn make_template(
+    path: &str,
+    vars: &HashMap<String, String>,
+) -> Result<ProcessTemplate, SolverError> {
+    let mut template = ProcessTemplate::new(path);
+    template.apply_vars(vars)?;
+    Ok(template)
+}
+
+fn micros(d: Duration) -> i64 {
+    d.as_secs() as i64 * 1_000_000 + i64::from(d.subsec_micros())
+}
+
+impl InstCounter for TimingSolver {
+    fn name(&self) -> &str {
+        "timing"
+    }
+
+    fn template(&self) -> Option<&ProcessTemplate> {
+        self.template.as_ref()
+    }
+
+    fn setup(&mut self, path: &str, vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.template = Some(make_template(path, vars)?);
+        Ok(())
+    }
+
+    // runtime of the target in microseconds
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        let template = template_for(self.template.as_ref(), data, make_template)?;
+
+        let handle = template.instantiate(&data.inp).spawn()?;
+        match handle.finish(data.timeout) {
+            Ok(_) => Ok(micros(handle.runtime().unwrap_or(data.timeout))),
+            // finish already killed it
+            Err(ref e) if *e.runner() == Runner::Timeout => Ok(2 * micros(data.timeout)),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,68 @@
+#![cfg(feature = "async")]
+
+use b7::async_run;
+use b7::config::OptsConfig;
+use b7::mock::MockSolver;
+use b7::process::Process;
+use b7::run_async_with;
+use tokio::stream::StreamExt;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+fn config() -> OptsConfig {
+    OptsConfig {
+        path: String::from("mock"),
+        argstate: false,
+        max_len: Some(8),
+        ..OptsConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn mock_run_with_progress() {
+    let mut run = run_async_with(config(), Box::new(MockSolver::new(b"b7ok")));
+    let progress = run.progress().unwrap();
+    assert!(run.progress().is_none());
+
+    let results = (&mut run).await.unwrap();
+    assert_eq!(results.stdin, b"b7ok");
+    assert!(!results.cancelled);
+
+    // the brute thread is gone, so the stream ends after the last round
+    let events: Vec<_> = progress.collect().await;
+    assert_eq!(events[0].phase.as_ref().unwrap(), "stdin length");
+    let last = events.last().unwrap();
+    assert_eq!(last.phase.as_ref().unwrap(), "stdin");
+    assert_eq!(last.round, Some(3));
+    assert!(last.counts.iter().any(|c| c.0 == b'k'.to_string()));
+}
+
+#[tokio::test]
+async fn drop_cancels() {
+    let run = run_async_with(config(), Box::new(MockSolver::new(b"b7ok")));
+    let token = run.cancel_token();
+    drop(run);
+    assert!(token.is_cancelled());
+}
+
+#[tokio::test]
+async fn await_children() {
+    let mut sleeper = Process::new("/bin/sh");
+    sleeper.args(&["-c", "sleep 0.2; exit 3"]);
+    let mut sleeper = sleeper.spawn().unwrap();
+    let mut quick = Process::new("/bin/true").spawn().unwrap();
+
+    // awaiting the slow one first doesn't hold up the runtime
+    let (slow, fast) = tokio::join!(
+        async_run::finish(&mut sleeper),
+        async_run::finish(&mut quick)
+    );
+    assert_eq!(slow.unwrap().code, Some(3));
+    assert!(fast.unwrap().success());
+}
@@ -0,0 +1,132 @@
+use b7::brute::{InstCountData, InstCounter};
+use b7::config::OptsConfig;
+use b7::daemon::{self, Daemon};
+use b7::errors::SolverError;
+use b7::mock::MockSolver;
+use std::collections::HashMap;
+use std::fs;
+use std::os::unix::net::UnixStream;
+use std::sync::Arc;
+use std::thread;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+fn daemon(name: &str) -> Daemon {
+    let workdir = std::env::temp_dir().join(format!("b7-daemon-{}-{}", name, std::process::id()));
+    let mut daemon = Daemon::new(workdir);
+    daemon.set_solvers(|config| match config.solver.as_str() {
+        "mock" => Some(Box::new(MockSolver::new(b"b7ok")) as Box<InstCounter>),
+        "panic" => Some(Box::new(PanicSolver) as Box<InstCounter>),
+        _ => None,
+    });
+    daemon
+}
+
+fn job(solver: &str) -> OptsConfig {
+    OptsConfig {
+        path: String::from("mock"),
+        solver: String::from(solver),
+        argstate: false,
+        max_len: Some(8),
+        ..OptsConfig::default()
+    }
+}
+
+#[test]
+fn mock_job() {
+    let (server, client) = UnixStream::pair().unwrap();
+    let daemon = daemon("mock");
+    let server = thread::spawn(move || daemon.handle(server));
+
+    let mut rounds = 0;
+    let results = daemon::submit_over(client, job("mock"), |event| {
+        assert!(event.phase.is_some());
+        rounds += 1;
+    })
+    .unwrap();
+    server.join().unwrap().unwrap();
+
+    assert_eq!(results.stdin, b"b7ok");
+    assert!(!results.cancelled);
+    assert!(rounds > 4);
+}
+
+#[test]
+fn unknown_solver() {
+    let (server, client) = UnixStream::pair().unwrap();
+    let daemon = daemon("unknown");
+    let server = thread::spawn(move || daemon.handle(server));
+
+    let err = daemon::submit_over(client, job("nope"), |_| {}).unwrap_err();
+    server.join().unwrap().unwrap();
+    assert!(err.to_string().contains("unknown solver nope"));
+}
+
+struct PanicSolver;
+
+impl InstCounter for PanicSolver {
+    fn get_inst_count(&self, _data: &InstCountData) -> Result<i64, SolverError> {
+        Ok(0)
+    }
+
+    fn setup(&mut self, _path: &str, _vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        panic!("solver setup panicked");
+    }
+}
+
+// a job that panics gives its slot back, or with one slot the next
+// job would wait forever
+#[test]
+fn panicking_job() {
+    let daemon = Arc::new(daemon("panic"));
+    let (server, client) = UnixStream::pair().unwrap();
+    let panicking = {
+        let daemon = daemon.clone();
+        thread::spawn(move || daemon.handle(server))
+    };
+    assert!(daemon::submit_over(client, job("panic"), |_| {}).is_err());
+    assert!(panicking.join().is_err());
+
+    let (server, client) = UnixStream::pair().unwrap();
+    let server = thread::spawn(move || daemon.handle(server));
+    let results = daemon::submit_over(client, job("mock"), |_| {}).unwrap();
+    server.join().unwrap().unwrap();
+    assert_eq!(results.stdin, b"b7ok");
+}
+
+// output files end up where the client asked, not in the job's
+// directory, and the memo is carried over to the next job
+#[test]
+fn output_files() {
+    let dir = std::env::temp_dir().join(format!("b7-daemon-client-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut config = job("mock");
+    config.memo_file = Some(dir.join("memo"));
+    config.output_path = Some(dir.join("out"));
+
+    let (server, client) = UnixStream::pair().unwrap();
+    let daemon = daemon("files");
+    let server = thread::spawn(move || daemon.handle(server));
+    daemon::submit_over(client, config.clone(), |_| {}).unwrap();
+    server.join().unwrap().unwrap();
+
+    assert_eq!(fs::read(dir.join("out").join("stdin")).unwrap(), b"b7ok");
+    let memo = fs::read(dir.join("memo")).unwrap();
+    assert!(!memo.is_empty());
+
+    // a second job starts from that memo and writes it back
+    let (server, client) = UnixStream::pair().unwrap();
+    let daemon = daemon("files");
+    let server = thread::spawn(move || daemon.handle(server));
+    let results = daemon::submit_over(client, config, |_| {}).unwrap();
+    server.join().unwrap().unwrap();
+    assert_eq!(results.stdin, b"b7ok");
+    assert!(fs::read(dir.join("memo")).unwrap().len() >= memo.len());
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,3 @@
+#!/bin/sh
+# opens flag.txt relative to the working directory, see tests/process.rs
+exec cat ./flag.txt
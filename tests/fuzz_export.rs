@@ -0,0 +1,73 @@
+use b7::b7tui::Env;
+use b7::fuzz;
+use b7::mock::MockSolver;
+use b7::B7Opts;
+use regex::Regex;
+use std::collections::HashMap;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// undo afl_escape
+fn unescape(value: &str) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+        match bytes.next().unwrap() {
+            b'x' => {
+                let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                out.push(u8::from_str_radix(&hex, 16).unwrap());
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[test]
+fn dictionary_syntax() {
+    let secret = b"a\"b\nc\\";
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        false,
+        true,
+        Box::new(MockSolver::new(secret)),
+        &mut term,
+        HashMap::new(),
+        Duration::new(5, 0),
+    );
+    opts.set_max_len(Some(8)).set_charset(0x0a, 0x7e);
+    let results = opts.run().unwrap();
+    assert_eq!(results.stdin, secret);
+
+    let path = std::env::temp_dir().join(format!("b7-dict-{}", std::process::id()));
+    fuzz::write_dictionary(&path, &results).unwrap();
+    let dict = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    // name="value" with only \\, \" and \xNN escapes, as AFL parses it
+    let entry =
+        Regex::new(r#"^([A-Za-z0-9_]+)="((?:\\\\|\\"|\\x[0-9a-f]{2}|[ !#-\[\]-~])*)"$"#).unwrap();
+    let mut values = HashMap::new();
+    for line in dict
+        .lines()
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+    {
+        let caps = entry
+            .captures(line)
+            .unwrap_or_else(|| panic!("bad dictionary line: {}", line));
+        values.insert(caps[1].to_string(), unescape(&caps[2]));
+    }
+    assert_eq!(values["stdin"], secret);
+}
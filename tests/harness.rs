@@ -0,0 +1,72 @@
+use b7::brute::{InstCountData, InstCounter};
+use b7::generators::Input;
+use b7::harness::HarnessSolver;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, Read, Write};
+
+use ctor::ctor;
+
+// This test binary is its own harness: started with B7_TEST_HARNESS set,
+// it speaks the protocol of docs/harness.md instead of running tests
+#[ctor]
+fn on_init() {
+    if env::var_os("B7_TEST_HARNESS").is_some() {
+        harness();
+        std::process::exit(0);
+    }
+    // See tests/run_wyvern.rs for why this is needed
+    b7::process::block_signal();
+}
+
+fn read_u32(input: &mut impl Read) -> Option<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes).ok()?;
+    Some(u32::from_le_bytes(bytes))
+}
+
+fn read_bytes(input: &mut impl Read) -> Option<Vec<u8>> {
+    let mut bytes = vec![0; read_u32(input)? as usize];
+    input.read_exact(&mut bytes).ok()?;
+    Some(bytes)
+}
+
+// scores how many leading bytes of stdin match "b7ok", and writes more
+// to stderr per candidate than a pipe holds
+fn harness() {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    out.write_all(b"B7H1").unwrap();
+    out.flush().unwrap();
+    while let Some(argc) = read_u32(&mut input) {
+        for _ in 0..argc {
+            read_bytes(&mut input).unwrap();
+        }
+        let candidate = read_bytes(&mut input).unwrap();
+        let score = candidate
+            .iter()
+            .zip(b"b7ok")
+            .take_while(|(a, b)| a == b)
+            .count() as i64;
+        io::stderr().write_all(&[b'.'; 1 << 17]).unwrap();
+        out.write_all(&score.to_le_bytes()).unwrap();
+        out.flush().unwrap();
+    }
+}
+
+#[test]
+fn harness_scores() {
+    let exe = env::current_exe().unwrap();
+    let exe = exe.to_str().unwrap();
+    env::set_var("B7_TEST_HARNESS", "1");
+    let mut solver = HarnessSolver::new();
+    solver.setup(exe, &HashMap::new()).unwrap();
+    for &(stdin, score) in &[(&b"xx"[..], 0), (b"b7", 2), (b"b7o!", 3), (b"b7ok", 4)] {
+        let data = InstCountData::builder(exe)
+            .input(Input::new(vec![], stdin.to_vec()))
+            .build();
+        assert_eq!(solver.get_inst_count(&data).unwrap(), score);
+    }
+}
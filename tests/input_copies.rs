@@ -0,0 +1,39 @@
+use b7::generators::Input;
+use b7::process::Process;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counts every byte allocated by this test binary
+struct CountingAlloc;
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATED.fetch_add(layout.size(), Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+// Handing a large candidate to a Process must not copy it
+#[test]
+fn input_not_copied() {
+    let size = 1 << 20;
+    let inp = Input::new(vec![], vec![0x41; size]);
+
+    let before = ALLOCATED.load(Ordering::SeqCst);
+    let mut process = Process::new("/bin/true");
+    process.input(inp.stdin.clone());
+    // not started, so this fails - but only after preparing the input
+    assert!(process.write_input().is_err());
+    let after = ALLOCATED.load(Ordering::SeqCst);
+
+    assert!(after - before < size);
+}
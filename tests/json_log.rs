@@ -0,0 +1,63 @@
+use b7::b7tui::Env;
+use b7::logging::JsonLogger;
+use b7::mock::MockSolver;
+use b7::B7Opts;
+use log::LevelFilter;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+// Write end of a buffer the test reads back afterwards
+struct Shared(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn per_run_records_have_context() {
+    let buf = Arc::new(Mutex::new(Vec::new()));
+    let logger = JsonLogger::new(LevelFilter::Trace, Box::new(Shared(buf.clone())));
+    log::set_boxed_logger(Box::new(logger)).unwrap();
+    log::set_max_level(LevelFilter::Trace);
+
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        false,
+        true,
+        Box::new(MockSolver::new(b"b7")),
+        &mut term,
+        HashMap::new(),
+        Duration::new(5, 0),
+    );
+    opts.set_max_len(Some(4));
+    assert_eq!(opts.run().unwrap().stdin, b"b7");
+
+    let out = String::from_utf8(buf.lock().unwrap().clone()).unwrap();
+    let runs: Vec<serde_json::Value> = out
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .filter(|json: &serde_json::Value| {
+            json["message"].as_str().unwrap().starts_with("inst_count")
+        })
+        .collect();
+    assert!(!runs.is_empty());
+    for run in &runs {
+        assert_eq!(run["level"], "TRACE");
+        assert!(run["phase"] == "stdin length" || run["phase"] == "stdin");
+        assert!(run["round"].is_u64());
+        assert!(run["candidate"].is_string());
+    }
+    assert!(runs
+        .iter()
+        .any(|r| r["phase"] == "stdin" && r["candidate"] == "98"));
+}
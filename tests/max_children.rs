@@ -0,0 +1,95 @@
+use b7::b7tui::Env;
+use b7::brute::{InstCountData, InstCounter};
+use b7::errors::{Runner, SolverError};
+use b7::process::{Process, WAITER};
+use b7::B7Opts;
+use std::collections::HashMap;
+use std::sync::mpsc::channel;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// Notes the limit it was set up under, then fails the run
+struct SeesLimit(Arc<Mutex<Option<usize>>>);
+
+impl InstCounter for SeesLimit {
+    fn get_inst_count(&self, _data: &InstCountData) -> Result<i64, SolverError> {
+        Ok(0)
+    }
+
+    fn setup(&mut self, _path: &str, _vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        *self.0.lock().unwrap() = WAITER.max_children();
+        Err(SolverError::new(Runner::RunnerError, "setup failed"))
+    }
+}
+
+// The limit is global, so this is the only test in its binary.
+// Spawning past it fails or waits until a child is reaped
+#[test]
+fn max_children() {
+    assert_eq!(WAITER.max_children(), None);
+    WAITER.set_max_children(Some(2));
+    assert_eq!(WAITER.max_children(), Some(2));
+
+    let mut sleeper = Process::new("/bin/sleep");
+    sleeper.arg("5");
+    let first = sleeper.spawn().unwrap();
+    let second = Process::new("/bin/cat").spawn().unwrap();
+
+    let err = WAITER
+        .try_spawn_process(Process::new("/bin/true"))
+        .err()
+        .expect("spawned past the limit");
+    assert_eq!(*err.runner(), Runner::WouldBlock);
+
+    let (sender, receiver) = channel();
+    let blocked = thread::spawn(move || {
+        let handle = Process::new("/bin/true").spawn().unwrap();
+        sender.send(()).unwrap();
+        handle.finish(Duration::new(5, 0)).unwrap()
+    });
+    assert!(receiver.recv_timeout(Duration::from_millis(200)).is_err());
+
+    // reaping a child frees its slot
+    second.finish(Duration::new(5, 0)).unwrap();
+    receiver
+        .recv_timeout(Duration::new(5, 0))
+        .expect("spawn still blocked after a child was reaped");
+    assert!(blocked.join().unwrap().success());
+    first.kill(Duration::new(5, 0)).unwrap();
+
+    WAITER.set_max_children(None);
+    let handles: Vec<_> = (0..4)
+        .map(|_| Process::new("/bin/true").spawn().unwrap())
+        .collect();
+    for handle in handles {
+        handle.finish(Duration::new(5, 0)).unwrap();
+    }
+    assert_eq!(WAITER.tracked(), 0);
+
+    // a run's max_children var only lasts for that run, even if it fails
+    let seen = Arc::new(Mutex::new(None));
+    let mut vars = HashMap::new();
+    vars.insert(String::from("max_children"), String::from("3"));
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        false,
+        true,
+        Box::new(SeesLimit(seen.clone())),
+        &mut term,
+        vars,
+        Duration::new(5, 0),
+    );
+    assert!(opts.run().is_err());
+    assert_eq!(*seen.lock().unwrap(), Some(3));
+    assert_eq!(WAITER.max_children(), None);
+}
@@ -0,0 +1,715 @@
+use b7::b7tui::{Env, Ui};
+use b7::brute::{run_target, InstCountData, InstCounter};
+use b7::errors::{Runner, SolverError};
+use b7::mock::MockSolver;
+use b7::process::WAITER;
+use b7::B7Opts;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{Debug, Display};
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// Solver counting with a closure, for what MockSolver doesn't cover
+struct Counter<F>(F);
+
+fn counter<F>(count: F) -> Counter<F>
+where
+    F: Fn(&InstCountData) -> Result<i64, SolverError> + Send + Sync + 'static,
+{
+    Counter(count)
+}
+
+impl<F> InstCounter for Counter<F>
+where
+    F: Fn(&InstCountData) -> Result<i64, SolverError> + Send + Sync + 'static,
+{
+    fn get_inst_count(&self, data: &InstCountData) -> Result<i64, SolverError> {
+        (self.0)(data)
+    }
+}
+
+fn vars(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+    pairs
+        .iter()
+        .map(|(var, value)| (var.to_string(), value.to_string()))
+        .collect()
+}
+
+// Solves the stdin of "mock" with `solver`
+fn opts_with<U: Ui>(
+    ui: &mut U,
+    solver: impl InstCounter,
+    vars: HashMap<String, String>,
+) -> B7Opts<U> {
+    B7Opts::new(
+        "mock".to_string(),
+        false,
+        true,
+        Box::new(solver),
+        ui,
+        vars,
+        Duration::new(5, 0),
+    )
+}
+
+// Instruction count keeps climbing with the stdin length,
+// so the length plateau is never reached
+fn growing() -> impl InstCounter {
+    counter(|data| {
+        let len = data.inp.stdin.len() as i64;
+        Ok(len * len)
+    })
+}
+
+#[test]
+fn max_len_cap() {
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, growing(), HashMap::new());
+
+    let err = opts.run().unwrap_err();
+    assert_eq!(*err.runner(), Runner::RunnerError);
+    assert!(err.to_string().contains("raise --max-len"));
+}
+
+#[test]
+fn min_len_above_max_len() {
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, growing(), HashMap::new());
+    opts.set_min_len(10).set_max_len(Some(5));
+
+    let err = opts.run().unwrap_err();
+    assert_eq!(*err.runner(), Runner::MissingArgs);
+    assert!(err.to_string().contains("min length 10"));
+}
+
+// a fixed length is only tried, never grown into, so it isn't the cap
+#[test]
+fn fixed_len() {
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, MockSolver::new(b"b7ok"), HashMap::new());
+    opts.set_min_len(4).set_max_len(Some(4));
+
+    let results = opts.run().unwrap();
+    assert_eq!(results.stdin, b"b7ok");
+}
+
+fn run_flag(memo: &std::path::Path) -> (String, usize) {
+    let solver = MockSolver::new(b"b7ok");
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, solver.clone(), HashMap::new());
+    opts.set_max_len(Some(8))
+        .set_memo_file(Some(memo.to_path_buf()));
+
+    let results = opts.run().unwrap();
+    (results.stdin_brute, solver.runs())
+}
+
+#[test]
+fn resumed_session_uses_memo() {
+    let memo = std::env::temp_dir().join(format!("b7-memo-{}", std::process::id()));
+    let _ = std::fs::remove_file(&memo);
+
+    let (first, first_runs) = run_flag(&memo);
+    let (second, second_runs) = run_flag(&memo);
+    std::fs::remove_file(&memo).unwrap();
+
+    assert_eq!(first, "b7ok");
+    assert_eq!(second, "b7ok");
+    assert!(second_runs < first_runs);
+}
+
+#[test]
+fn decisions() {
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, MockSolver::new(b"b7ok"), HashMap::new());
+    opts.set_max_len(Some(8));
+    let results = opts.run().unwrap();
+
+    let decisions = results.decisions("stdin");
+    let winners: Vec<u8> = decisions.iter().map(|d| d.winner as u8).collect();
+    assert_eq!(winners, b"b7ok");
+    for (pos, decision) in decisions.iter().enumerate() {
+        assert_eq!(decision.round, pos);
+        assert_eq!(decision.count, 110 + 10 * pos as i64);
+        assert_eq!(decision.margin, Some(10));
+    }
+    assert!(results.decisions("argv").is_empty());
+}
+
+// Ui that keeps the statistics it is shown at the end, like the TUI,
+// and the ETA of every round
+#[derive(Default)]
+struct StatsUi {
+    report: Vec<String>,
+    etas: Vec<Option<Duration>>,
+}
+
+impl Ui for StatsUi {
+    fn update<I: 'static + Display + Copy + Into<u64> + Debug + Send + Ord>(
+        &mut self,
+        _results: &[(I, i64)],
+        _min: u64,
+    ) -> bool {
+        true
+    }
+
+    fn eta(&mut self, left: Option<Duration>) {
+        self.etas.push(left);
+    }
+
+    fn stats(&mut self, report: &[String]) {
+        self.report = report.to_vec();
+    }
+
+    fn wait(&mut self) -> bool {
+        true
+    }
+
+    fn done(&mut self) -> bool {
+        true
+    }
+}
+
+#[test]
+fn stats_report() {
+    let solver = MockSolver::new(b"b7ok");
+    let mut ui = StatsUi::default();
+    let results = {
+        let mut opts = opts_with(&mut ui, solver.clone(), HashMap::new());
+        opts.set_max_len(Some(8));
+        opts.run().unwrap()
+    };
+
+    // --stats prints what the TUI shows
+    let report = results.stats.report(None);
+    assert_eq!(ui.report, report);
+    // a header, a row for each phase and the total, no warnings
+    assert_eq!(report.len(), 4);
+    let header: Vec<&str> = report[0].split_whitespace().collect();
+    assert_eq!(
+        header[..7],
+        ["phase", "runs", "cached", "errors", "timeouts", "crashes", "retries"]
+    );
+    assert!(report[1].starts_with("stdin length"));
+    assert!(report[2].starts_with("stdin "));
+    let total: Vec<&str> = report[3].split_whitespace().collect();
+    assert_eq!(total[0], "total");
+    assert_eq!(total[1], solver.runs().to_string());
+    // no errors, timeouts, crashes or retries
+    assert_eq!(total[3..7], ["0", "0", "0", "0"]);
+
+    // the length round and the four bytes of "b7ok", the last byte
+    // leaves nothing to do
+    assert_eq!(ui.etas.len(), 5);
+    assert!(ui.etas.iter().all(Option::is_some));
+    assert_eq!(ui.etas[4], Some(Duration::new(0, 0)));
+
+    let report = results.stats.report(Some("task-clock"));
+    assert_eq!(report.len(), 5);
+    assert!(report[4].starts_with("warning: the solver fell back to task-clock"));
+}
+
+#[test]
+fn ties() {
+    let run = |threshold: Option<u32>| {
+        let mut term = Env::new();
+        let mut opts = opts_with(&mut term, MockSolver::new(b"b7"), HashMap::new());
+        opts.set_max_len(Some(4));
+        if let Some(threshold) = threshold {
+            opts.set_tie_threshold(threshold);
+        }
+        opts.run().unwrap()
+    };
+    assert!(run(None).ties("stdin").is_empty());
+
+    // every right byte is only 10 ahead of the rest
+    let results = run(Some(10));
+    assert_eq!(results.stdin, b"b7");
+    let ties = results.ties("stdin");
+    assert_eq!(ties.len(), 2);
+    for (tie, &byte) in ties.iter().zip(b"b7") {
+        assert_eq!(tie.candidates[0].0, u64::from(byte));
+        assert_eq!(tie.candidates.len(), 0x7e - 0x20 + 1);
+    }
+    assert!(results.ties("stdin length").is_empty());
+}
+
+// Scores like MockSolver for "b7", except that a leading 'a' looks even
+// better than the right 'b' but leads nowhere
+fn decoy() -> impl InstCounter {
+    let mock = MockSolver::new(b"b7");
+    counter(move |data| {
+        let stdin = &data.inp.stdin;
+        if stdin.len() == 2 && stdin[0] == b'a' {
+            return Ok(115);
+        }
+        mock.get_inst_count(data)
+    })
+}
+
+#[test]
+fn backtrack() {
+    let run = |max: u32| {
+        let mut term = Env::new();
+        let mut opts = opts_with(&mut term, decoy(), HashMap::new());
+        opts.set_max_len(Some(4)).set_max_backtrack(max);
+        opts.run().unwrap()
+    };
+    assert_eq!(run(0).stdin[0], b'a');
+
+    let results = run(1);
+    assert_eq!(results.stdin, b"b7");
+    let stdin = results.stats.phases.iter().find(|p| p.name == "stdin");
+    assert_eq!(stdin.unwrap().backtracks, vec![(0, u64::from(b'b'))]);
+    // the decision is the pick that replaced the decoy
+    let decisions = results.decisions("stdin");
+    assert_eq!(decisions[0].winner, u64::from(b'b'));
+    assert_eq!(decisions.len(), 2);
+    let report = results.stats.report(None);
+    assert_eq!(
+        report.last().unwrap(),
+        "warning: rounds retried with their runner-up: 1"
+    );
+}
+
+#[test]
+fn dry_run() {
+    let solver = MockSolver::new(b"b7ok");
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, solver.clone(), HashMap::new());
+    opts.set_max_len(Some(3)).set_dry_run(true);
+    let results = opts.run().unwrap();
+
+    assert_eq!(solver.runs(), 0);
+    let lengths = &results.stats.phases[0];
+    assert_eq!(lengths.name, "stdin length");
+    assert_eq!(lengths.runs, 0);
+    assert_eq!(lengths.commands.len(), 4);
+    assert!(lengths.commands[2].starts_with("\"mock\""));
+    assert!(lengths.commands[2].ends_with("< \"AA\""));
+    // the contents are shown at max_len, for their first round only
+    let stdin = &results.stats.phases[1];
+    assert_eq!(stdin.name, "stdin");
+    assert_eq!(stdin.rounds.len(), 1);
+    assert_eq!(stdin.commands.len(), stdin.rounds[0].len());
+    assert!(stdin.commands[0].ends_with("AA\""));
+}
+
+#[test]
+fn file_brute() {
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        false,
+        false,
+        Box::new(MockSolver::for_file(b"b7ok")),
+        &mut term,
+        HashMap::new(),
+        Duration::new(5, 0),
+    );
+    opts.set_max_len(Some(8)).set_file_brute(true);
+
+    let results = opts.run().unwrap();
+    assert_eq!(results.file_brute, "b7ok");
+    assert!(results.stdin.is_empty());
+}
+
+#[test]
+fn pad_byte() {
+    // notes any length probe not made of '.'
+    let other_pad = Arc::new(AtomicBool::new(false));
+    let padded = {
+        let other_pad = other_pad.clone();
+        let mock = MockSolver::new(b"b7ok");
+        counter(move |data| {
+            let stdin = &data.inp.stdin;
+            if stdin.len() != 4 && stdin.iter().any(|&b| b != b'.') {
+                other_pad.store(true, Ordering::SeqCst);
+            }
+            mock.get_inst_count(data)
+        })
+    };
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, padded, vars(&[("pad", "0x2e")]));
+    opts.set_max_len(Some(8));
+    let results = opts.run().unwrap();
+    assert_eq!(results.stdin, b"b7ok");
+    assert!(!other_pad.load(Ordering::SeqCst));
+
+    let mut term = Env::new();
+    let mut opts = opts_with(
+        &mut term,
+        MockSolver::new(b"b7ok"),
+        vars(&[("pad", "0x100")]),
+    );
+    assert!(opts.run().is_err());
+}
+
+fn solve_argv(max_argc: Option<u32>) -> (Vec<Vec<u8>>, usize) {
+    // three arguments of two bytes each stand out. Records the most
+    // arguments it was run with
+    let max_seen = Arc::new(Mutex::new(0));
+    let counted = {
+        let max_seen = max_seen.clone();
+        counter(move |data| {
+            let argv = &data.inp.argv;
+            let mut max_seen = max_seen.lock().unwrap();
+            *max_seen = (*max_seen).max(argv.len());
+            let argc = if argv.len() == 3 {
+                1000
+            } else {
+                argv.len() as i64
+            };
+            let two_bytes = argv.iter().filter(|arg| arg.len() == 2).count() as i64;
+            Ok(argc + two_bytes * 10)
+        })
+    };
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        true,
+        false,
+        Box::new(counted),
+        &mut term,
+        HashMap::new(),
+        Duration::new(5, 0),
+    );
+    opts.set_max_len(Some(4)).set_max_argc(max_argc);
+    let results = opts.run().unwrap();
+    let max_seen = *max_seen.lock().unwrap();
+    (results.argv, max_seen)
+}
+
+#[test]
+fn max_argc() {
+    let (argv, max_seen) = solve_argv(None);
+    assert_eq!(argv.len(), 3);
+    assert!(argv.iter().all(|arg| arg.len() == 2));
+    assert_eq!(max_seen, 5);
+
+    let (argv, max_seen) = solve_argv(Some(2));
+    assert_eq!(argv.len(), 2);
+    assert_eq!(max_seen, 2);
+}
+
+#[test]
+fn missing_cwd() {
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, MockSolver::new(b"b7ok"), HashMap::new());
+    opts.set_cwd(Some("/nonexistent/b7".into()));
+    let err = opts.run().unwrap_err();
+    assert_eq!(*err.runner(), Runner::MissingArgs);
+}
+
+// only measures once set up, like perf after probing
+struct NeedsSetup {
+    ready: bool,
+}
+
+impl InstCounter for NeedsSetup {
+    fn get_inst_count(&self, _data: &InstCountData) -> Result<i64, SolverError> {
+        if self.ready {
+            Ok(100)
+        } else {
+            Err(SolverError::new(Runner::RunnerError, "not set up"))
+        }
+    }
+
+    fn setup(&mut self, _path: &str, _vars: &HashMap<String, String>) -> Result<(), SolverError> {
+        self.ready = true;
+        Ok(())
+    }
+}
+
+#[test]
+fn plan_like_a_run() {
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "/bin/true".to_string(),
+        false,
+        true,
+        Box::new(NeedsSetup { ready: false }),
+        &mut term,
+        vars(&[("max_parallel", "3")]),
+        Duration::new(5, 0),
+    );
+    let plan = opts.plan(2).unwrap();
+    assert_eq!(plan.workers, 3);
+    // stdin lengths up to DEFAULT_INPUT_MAX_LEN unless set_max_len says otherwise
+    assert_eq!(plan.phases[0], (String::from("stdin length"), 52));
+    let hash = Sha256::digest(&fs::read("/bin/true").unwrap());
+    assert_eq!(plan.target_sha256, format!("{:x}", hash));
+    assert!(plan.to_string().contains(&plan.target_sha256));
+    assert!(plan.per_run.is_some());
+}
+
+// a typo in a number is an error rather than the default
+#[test]
+fn bad_numbers() {
+    for (var, value) in &[
+        ("reference_margin", "5O"),
+        ("max_parallel", "four"),
+        ("reference", "1e3"),
+        ("tie_threshold", "-1"),
+        ("max_backtrack", "two"),
+    ] {
+        let mut term = Env::new();
+        let mut opts = opts_with(&mut term, MockSolver::new(b"b7ok"), vars(&[(*var, *value)]));
+        let err = opts.run().unwrap_err();
+        assert_eq!(*err.runner(), Runner::MissingArgs);
+        assert!(err.to_string().contains(value));
+    }
+}
+
+#[test]
+fn crashes_counted_apart() {
+    // length 4 stands out, and length 2 crashes the "target"
+    let crashing = counter(|data| match data.inp.stdin.len() {
+        2 => Err(SolverError::new(Runner::Crashed, "killed by SIGSEGV")),
+        4 => Ok(100),
+        len => Ok(len as i64),
+    });
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, crashing, HashMap::new());
+    opts.set_max_len(Some(8));
+
+    let results = opts.run().unwrap();
+    let lengths = &results.stats.phases[0];
+    assert_eq!(lengths.name, "stdin length");
+    assert_eq!(lengths.crashes, 1);
+    assert_eq!(lengths.errors, 1);
+    assert_eq!(lengths.timeouts, 0);
+    let warnings = results.stats.warnings(None);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].ends_with("runs failed (0 timeouts, 1 crashes)"));
+}
+
+#[test]
+fn every_candidate_failed() {
+    // every run times out, so no round has anything to pick from
+    let timeout = counter(|_| Err(SolverError::new(Runner::Timeout, "timed out")));
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, timeout, HashMap::new());
+    opts.set_max_len(Some(4));
+
+    let err = opts.run().unwrap_err();
+    assert_eq!(*err.runner(), Runner::RunnerError);
+    assert!(err
+        .to_string()
+        .contains("every candidate in round 0 failed"));
+}
+
+// Ui of a user who quits after the first round
+struct QuitUi {
+    rounds: u32,
+    done: bool,
+}
+
+impl Ui for QuitUi {
+    fn update<I: 'static + Display + Copy + Into<u64> + Debug + Send + Ord>(
+        &mut self,
+        _results: &[(I, i64)],
+        _min: u64,
+    ) -> bool {
+        self.rounds += 1;
+        true
+    }
+
+    fn wait(&mut self) -> bool {
+        false
+    }
+
+    fn done(&mut self) -> bool {
+        self.done = true;
+        false
+    }
+}
+
+#[test]
+fn quit_ends_run() {
+    let mut ui = QuitUi {
+        rounds: 0,
+        done: false,
+    };
+    let results = {
+        let mut opts = B7Opts::new(
+            "mock".to_string(),
+            true,
+            true,
+            Box::new(MockSolver::new(b"b7ok")),
+            &mut ui,
+            HashMap::new(),
+            Duration::new(5, 0),
+        );
+        opts.run().unwrap()
+    };
+    assert!(results.cancelled);
+    // nothing after the argc phase ran
+    assert_eq!(results.stats.phases.len(), 1);
+    assert_eq!(ui.rounds, 1);
+    assert!(ui.done);
+}
+
+#[test]
+fn cancel_keeps_partial_result() {
+    // a slow mock that flags when the second byte of "b7ok" is being tried
+    let second_byte = Arc::new(AtomicBool::new(false));
+    let slow = {
+        let second_byte = second_byte.clone();
+        let mock = MockSolver::new(b"b7ok");
+        counter(move |data| {
+            if data.inp.stdin.starts_with(b"b7") {
+                second_byte.store(true, Ordering::SeqCst);
+            }
+            thread::sleep(Duration::from_millis(10));
+            mock.get_inst_count(data)
+        })
+    };
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, slow, HashMap::new());
+    opts.set_max_len(Some(8));
+
+    let token = opts.cancel_token();
+    let canceller = thread::spawn(move || {
+        while !second_byte.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        token.cancel();
+    });
+    let results = opts.run().unwrap();
+    canceller.join().unwrap();
+
+    assert!(results.cancelled);
+    assert_eq!(results.stdin.len(), 4);
+    assert_eq!(results.stdin[0], b'b');
+    assert_ne!(results.stdin, b"b7ok");
+}
+
+#[test]
+fn cancel_kills_children() {
+    // every run outlives the test unless it is killed
+    let sleeper = counter(|data| {
+        let data = InstCountData::builder("/bin/sleep")
+            .input(b7::generators::Input::builder().arg("10").build())
+            .timeout(data.timeout)
+            .build();
+        run_target(&data).map(|_| 0)
+    });
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        "mock".to_string(),
+        false,
+        true,
+        Box::new(sleeper),
+        &mut term,
+        HashMap::new(),
+        Duration::new(30, 0),
+    );
+
+    let token = opts.cancel_token();
+    let canceller = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(300));
+        token.cancel();
+    });
+    let start = Instant::now();
+    let results = opts.run().unwrap();
+    canceller.join().unwrap();
+
+    assert!(results.cancelled);
+    assert!(start.elapsed() < Duration::new(3, 0));
+    assert_eq!(WAITER.tracked(), 0);
+    // every run was cut short, so none of them counts
+    assert!(results.stats.phases.iter().all(|p| p.runs == 0));
+}
+
+#[test]
+fn max_parallel() {
+    // tracks how many counts run at the same time
+    let peak = Arc::new(AtomicUsize::new(0));
+    let concurrency = {
+        let running = AtomicUsize::new(0);
+        let peak = peak.clone();
+        let mock = MockSolver::new(b"b7");
+        counter(move |data| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            let mut seen = peak.load(Ordering::SeqCst);
+            while now > seen {
+                match peak.compare_exchange(seen, now, Ordering::SeqCst, Ordering::SeqCst) {
+                    Ok(_) => break,
+                    Err(actual) => seen = actual,
+                }
+            }
+            thread::sleep(Duration::from_millis(2));
+            running.fetch_sub(1, Ordering::SeqCst);
+            mock.get_inst_count(data)
+        })
+    };
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, concurrency, HashMap::new());
+    opts.set_max_len(Some(4)).set_max_parallel(Some(2));
+    let results = opts.run().unwrap();
+    assert_eq!(results.stdin, b"b7");
+    let peak = peak.load(Ordering::SeqCst);
+    assert!(peak >= 1 && peak <= 2, "peak concurrency {}", peak);
+}
+
+#[test]
+fn output_path() {
+    let dir = std::env::temp_dir().join(format!("b7-output-{}", std::process::id()));
+    let mut term = Env::new();
+    let mut opts = opts_with(&mut term, MockSolver::new(b"b7\xe9"), HashMap::new());
+    opts.set_max_len(Some(4))
+        .set_charset(0x20, 0xff)
+        .set_output_path(Some(dir.clone()));
+    let results = opts.run().unwrap();
+    // not valid UTF-8, so only the display string loses it
+    assert_eq!(results.stdin, b"b7\xe9");
+    assert_eq!(std::fs::read(dir.join("stdin")).unwrap(), b"b7\xe9");
+    // argv wasn't solved
+    assert!(!dir.join("argv").exists());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn detector_sees_stderr() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let script = std::env::temp_dir().join(format!("b7-detect-{}.sh", std::process::id()));
+    fs::write(&script, "#!/bin/sh\ncat >&2\n").unwrap();
+    fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let seen = Arc::new(AtomicBool::new(false));
+    let seen_clone = seen.clone();
+    let mut term = Env::new();
+    let mut opts = B7Opts::new(
+        script.to_string_lossy().into_owned(),
+        false,
+        true,
+        Box::new(MockSolver::new(b"b7")),
+        &mut term,
+        HashMap::new(),
+        Duration::new(5, 0),
+    );
+    opts.set_max_len(Some(4)).set_detector(move |output| {
+        if output.stderr == b"b7" {
+            seen_clone.store(true, Ordering::SeqCst);
+        }
+        None
+    });
+    let results = opts.run().unwrap();
+    assert_eq!(results.stdin, b"b7");
+    assert!(seen.load(Ordering::SeqCst));
+    fs::remove_file(&script).unwrap();
+}
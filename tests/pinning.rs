@@ -0,0 +1,48 @@
+#![cfg(feature = "pin-cpu")]
+
+use b7::brute::{run_target, InstCountData};
+use b7::generators::Input;
+use std::thread;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+fn allowed_cpus() -> usize {
+    let size = std::mem::size_of::<libc::cpu_set_t>();
+    let mut allowed: libc::cpu_set_t = unsafe { std::mem::zeroed() };
+    assert_eq!(unsafe { libc::sched_getaffinity(0, size, &mut allowed) }, 0);
+    (0..libc::CPU_SETSIZE as usize)
+        .filter(|&i| unsafe { libc::CPU_ISSET(i, &allowed) })
+        .count()
+}
+
+// the CPUs a pinned child may run on, as it reports them
+fn child_cpus() -> String {
+    let data = InstCountData::builder("/bin/sh")
+        .input(
+            Input::builder()
+                .arg("-c")
+                .arg("grep Cpus_allowed_list /proc/self/status")
+                .build(),
+        )
+        .var("pin_cpu", "1")
+        .build();
+    String::from_utf8(run_target(&data).unwrap().1.stdout).unwrap()
+}
+
+// Workers are numbered in the order they first spawn, so this is the
+// only test in its binary. Two of them must not share a CPU
+#[test]
+fn workers_get_their_own_cpu() {
+    let first = thread::spawn(child_cpus).join().unwrap();
+    let second = thread::spawn(child_cpus).join().unwrap();
+    assert!(!first.contains(','), "not pinned: {}", first);
+    if allowed_cpus() > 1 {
+        assert_ne!(first, second);
+    }
+}
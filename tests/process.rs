@@ -0,0 +1,1010 @@
+use b7::brute::{run_target, ChildOutcome, InstCountData};
+use b7::errors::Runner;
+use b7::generators::Input;
+use b7::process::{self, KillPolicy, OutputMode, Process, ProcessTemplate};
+use nix::sys::ptrace::Options;
+use nix::sys::signal::Signal;
+use nix::sys::wait::WaitStatus;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+#[test]
+fn spawn_missing_binary() {
+    let err = Process::new("/nonexistent/b7-target").spawn().unwrap_err();
+    assert_eq!(*err.runner(), Runner::NotFound);
+    assert_eq!(
+        err.to_string(),
+        "NotFound: target /nonexistent/b7-target not found"
+    );
+}
+
+// a target that exits without reading its input is not an error
+#[test]
+fn unread_input() {
+    let mut process = Process::new("/bin/true");
+    process.input(vec![b'A'; 1 << 20]);
+    let handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+}
+
+// Every child must get its own exit back, also when pids are reused
+// and some handles are dropped before their child was reaped
+#[test]
+fn pid_reuse_stress() {
+    let threads: Vec<_> = (0..4)
+        .map(|t| {
+            thread::spawn(move || {
+                for i in 0..600 {
+                    let code = (t * 600 + i) % 200;
+                    let mut process = Process::new("/bin/sh");
+                    process.arg("-c");
+                    process.arg(format!("exit {}", code));
+                    let handle = process.spawn().unwrap();
+                    if i % 3 == 0 {
+                        drop(handle);
+                        continue;
+                    }
+                    let status = handle.finish(Duration::new(10, 0)).unwrap();
+                    assert_eq!(status.code, Some(code));
+                }
+            })
+        })
+        .collect();
+    for t in threads {
+        t.join().unwrap();
+    }
+}
+
+#[test]
+fn exit_code() {
+    let handle = Process::new("/bin/false").spawn().unwrap();
+    assert_eq!(handle.exit_code(), None);
+    handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(handle.exit_code(), Some(1));
+}
+
+#[test]
+fn finish_returns_status() {
+    let handle = Process::new("/bin/false").spawn().unwrap();
+    let status = handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(status.pid, handle.pid());
+    assert_eq!(status.code, Some(1));
+    assert_eq!(status.signal, None);
+    assert!(!status.success());
+}
+
+#[test]
+fn busy_child_stats() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("i=0; while [ $i -lt 300000 ]; do i=$((i+1)); done");
+    let handle = process.spawn().unwrap();
+    assert_eq!(handle.stats(), None);
+    handle.finish(Duration::new(30, 0)).unwrap();
+    let stats = handle.stats().unwrap();
+    assert!(stats.user_time > Duration::from_millis(0));
+    assert!(stats.max_rss > 0);
+}
+
+// children that exit before the spawner registers still get their
+// stats, which were queued in the channel with the exit status
+#[test]
+fn short_child_stats() {
+    for _ in 0..50 {
+        let handle = Process::new("/bin/true").spawn().unwrap();
+        handle.finish(Duration::new(5, 0)).unwrap();
+        assert!(handle.stats().is_some());
+    }
+}
+
+#[test]
+fn signal_status() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("kill -SEGV $$");
+    let handle = process.spawn().unwrap();
+    let status = handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(status.code, None);
+    assert_eq!(status.signal, Some(Signal::SIGSEGV));
+    assert!(!status.success());
+    assert_eq!(handle.status(), Some(status));
+    assert_eq!(handle.exit_code(), None);
+    let err = status.check_crash().unwrap_err();
+    assert_eq!(*err.runner(), Runner::Crashed);
+}
+
+// signals a traced child gets are passed on instead of swallowed
+#[test]
+fn ptrace_forwards_signals() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("trap 'exit 3' USR1; kill -USR1 $$; sleep 10");
+    process.with_ptrace(true);
+    let handle = process.spawn().unwrap();
+    let status = handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(status.code, Some(3));
+
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("kill -SEGV $$; sleep 10");
+    process.with_ptrace(true);
+    let handle = process.spawn().unwrap();
+    let status = handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(status.signal, Some(Signal::SIGSEGV));
+}
+
+// with fork tracing, the shell's child is resumed and reaped as well
+#[test]
+fn ptrace_follows_forks() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("/bin/true; exit 3");
+    process.ptrace_options(
+        Options::PTRACE_O_TRACEFORK | Options::PTRACE_O_TRACEVFORK | Options::PTRACE_O_EXITKILL,
+    );
+    let handle = process.spawn().unwrap();
+    let leader = handle.pid();
+    let mut forks = 0;
+    let mut tracee_exits = 0;
+    let status = handle
+        .finish_with(Duration::new(5, 0), |status| {
+            match *status {
+                WaitStatus::PtraceEvent(pid, ..) if pid == leader => forks += 1,
+                WaitStatus::Exited(pid, 0) if pid != leader => tracee_exits += 1,
+                _ => {}
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert_eq!(status.code, Some(3));
+    assert_eq!(forks, 1);
+    assert_eq!(tracee_exits, 1);
+}
+
+fn term_then_kill(script: &str) -> (String, Vec<u8>) {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg(script);
+    process.kill_policy(KillPolicy::TermThenKill {
+        grace: Duration::from_millis(500),
+    });
+    let mut handle = process.spawn().unwrap();
+    let err = handle.finish(Duration::from_millis(200)).unwrap_err();
+    assert_eq!(*err.runner(), Runner::Timeout);
+    // reaped either way
+    assert!(handle.runtime().is_some());
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    (err.to_string(), stdout)
+}
+
+#[test]
+fn kill_policy_term() {
+    let (err, stdout) = term_then_kill("trap 'echo flushed; exit 0' TERM; sleep 10 & wait");
+    assert!(err.contains("exited after SIGTERM"), "{}", err);
+    assert_eq!(stdout, b"flushed\n");
+
+    let start = Instant::now();
+    let (err, _) = term_then_kill("trap '' TERM; sleep 10");
+    assert!(err.contains("killed after ignoring SIGTERM"), "{}", err);
+    assert!(start.elapsed() < Duration::new(5, 0));
+}
+
+#[test]
+fn kill_policy_parse() {
+    assert_eq!(
+        "term:1.5".parse::<KillPolicy>().unwrap(),
+        KillPolicy::TermThenKill {
+            grace: Duration::from_millis(1500)
+        }
+    );
+    for policy in &["kill", "none", "term:2"] {
+        assert_eq!(policy.parse::<KillPolicy>().unwrap().to_string(), *policy);
+    }
+    assert!("term:".parse::<KillPolicy>().is_err());
+    assert!("term:1e30".parse::<KillPolicy>().is_err());
+    assert!("stop".parse::<KillPolicy>().is_err());
+}
+
+// 10MB of output only ever takes up the capped amount, and the
+// child still gets to write all of it and exit
+#[test]
+fn max_output_bytes() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("yes | head -c 10000000");
+    process.max_output_bytes(4096);
+    let mut handle = process.spawn().unwrap();
+    let mut stdout = Vec::new();
+    assert_eq!(handle.read_stdout(&mut stdout).unwrap(), 4096);
+    assert_eq!(stdout.len(), 4096);
+    assert!(stdout.capacity() < 1 << 20);
+    assert!(handle.stdout_truncated());
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+
+    let mut process = Process::new("/bin/echo");
+    process.arg("b7");
+    process.max_output_bytes(4096);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"b7\n");
+    assert!(!handle.stdout_truncated());
+}
+
+// a 4MB stdin file, read as is and with bytes patched into a copy
+#[test]
+fn input_file() {
+    let path = std::env::temp_dir().join(format!("b7-stdin-{}", std::process::id()));
+    std::fs::write(&path, vec![b'A'; 4 << 20]).unwrap();
+    let head = |overlay: Option<&[u8]>| {
+        let mut process = Process::new("/bin/sh");
+        process.arg("-c");
+        process.arg("head -c 6");
+        process.input_file(&path);
+        if let Some(bytes) = overlay {
+            process.input_file_overlay(2, bytes);
+        }
+        let mut handle = process.spawn().unwrap();
+        handle.finish(Duration::new(5, 0)).unwrap();
+        let mut stdout = Vec::new();
+        handle.read_stdout(&mut stdout).unwrap();
+        stdout
+    };
+    assert_eq!(head(None), b"AAAAAA");
+    assert_eq!(head(Some(b"b7")), b"AAb7AA");
+
+    // the brute's candidates end up in the copy through the vars
+    let data = InstCountData::builder("/bin/sh")
+        .input(
+            Input::builder()
+                .arg("-c")
+                .arg("head -c 6")
+                .stdin("ok")
+                .build(),
+        )
+        .var("stdin_file", path.to_str().unwrap())
+        .var("stdin_offset", "4")
+        .build();
+    let (_, output) = run_target(&data).unwrap();
+    assert_eq!(output.stdout, b"AAAAok");
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn run_target_crash() {
+    let data = InstCountData::builder("/bin/sh")
+        .input(Input::builder().arg("-c").arg("kill -ABRT $$").build())
+        .build();
+    match run_target(&data).unwrap().0 {
+        ChildOutcome::Signaled { signal, .. } => assert_eq!(signal, Signal::SIGABRT),
+        outcome => panic!("unexpected outcome {:?}", outcome),
+    }
+}
+
+// Children that exit right away must be reported well within a short
+// timeout, even when their SIGCHLDs get coalesced
+#[test]
+fn short_timeout_latency() {
+    let mut timeouts = 0;
+    for _ in 0..500 {
+        let handle = Process::new("/bin/true").spawn().unwrap();
+        if handle.finish(Duration::from_millis(150)).is_err() {
+            timeouts += 1;
+        }
+    }
+    assert_eq!(timeouts, 0);
+}
+
+// finish reaps a child that is still running at the timeout
+#[test]
+fn timeout_kills() {
+    let mut process = Process::new("/bin/sleep");
+    process.arg("10");
+    let handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::from_millis(100)).is_err());
+    assert!(handle.runtime().unwrap() < Duration::new(5, 0));
+    assert_eq!(handle.exit_code(), None);
+}
+
+// the runtime ends when the child is reaped, not when finish looks
+#[test]
+fn runtime_until_reaped() {
+    let mut process = Process::new("/bin/sleep");
+    process.arg("0.1");
+    let handle = process.spawn().unwrap();
+    assert!(handle.runtime().is_none());
+    thread::sleep(Duration::from_millis(600));
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let runtime = handle.runtime().unwrap();
+    assert!(runtime >= Duration::from_millis(100), "{:?}", runtime);
+    assert!(runtime < Duration::from_millis(500), "{:?}", runtime);
+}
+
+// a forked helper must not outlive a target that timed out
+#[test]
+fn timeout_kills_group() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("sleep 30 & echo $!; wait");
+    let mut handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::from_millis(500)).is_err());
+
+    let mut out = Vec::new();
+    handle.read_stdout(&mut out).unwrap();
+    let helper = String::from_utf8(out).unwrap();
+    let stat = format!("/proc/{}/stat", helper.trim());
+    // killed, and either reaped by init or a zombie waiting for it
+    thread::sleep(Duration::from_millis(100));
+    if let Ok(stat) = std::fs::read_to_string(&stat) {
+        let state = stat.rsplit(") ").next().unwrap().chars().next();
+        assert_eq!(state, Some('Z'));
+    }
+}
+
+#[test]
+fn drop_reaps_child() {
+    let mut process = Process::new("/bin/sleep");
+    process.arg("10");
+    let handle = process.spawn().unwrap();
+    let proc_dir = format!("/proc/{}", handle.pid());
+    assert!(std::path::Path::new(&proc_dir).exists());
+    drop(handle);
+    assert!(!std::path::Path::new(&proc_dir).exists());
+}
+
+#[test]
+fn kill_after_timeout() {
+    let mut process = Process::new("/bin/sleep");
+    process.arg("10");
+    let handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::from_millis(100)).is_err());
+    handle.kill(Duration::new(5, 0)).unwrap();
+    assert_eq!(handle.exit_code(), None);
+    assert!(handle.runtime().unwrap() < Duration::new(5, 0));
+}
+
+// A pre_exec hook runs in the child before the target starts
+#[test]
+fn pre_exec_hook() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("exit $(ulimit -n)");
+    process.pre_exec(|| {
+        let limit = libc::rlimit {
+            rlim_cur: 42,
+            rlim_max: 42,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(())
+    });
+    let handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(handle.exit_code(), Some(42));
+}
+
+#[test]
+fn current_dir() {
+    let mut process = Process::new("/bin/pwd");
+    process.current_dir("/tmp");
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut out = Vec::new();
+    handle.read_stdout(&mut out).unwrap();
+    assert_eq!(out, b"/tmp\n");
+}
+
+// the "cwd" var applies to every run, and a relative target path still
+// means relative to the test's own directory
+#[test]
+fn run_target_cwd() {
+    let dir = std::env::temp_dir().join(format!("b7-cwd-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("flag.txt"), "b7{cwd}").unwrap();
+
+    let data = InstCountData::builder("tests/fixtures/cat_flag.sh")
+        .var("cwd", dir.to_str().unwrap())
+        .build();
+    let (outcome, output) = run_target(&data).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(outcome, ChildOutcome::Exited(0));
+    assert_eq!(output.stdout, b"b7{cwd}");
+}
+
+// the target gets the path of a copy of the file input as its last
+// argument, and the copy is gone once the run is over
+#[test]
+fn run_target_file_input() {
+    let inp = Input::builder()
+        .arg("-c")
+        .arg("cat \"$0\" && printf '\\n%s' \"$0\"")
+        .file("b7{file}")
+        .build();
+    let data = InstCountData::builder("/bin/sh").input(inp).build();
+    let (outcome, output) = run_target(&data).unwrap();
+    assert_eq!(outcome, ChildOutcome::Exited(0));
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let mut lines = stdout.lines();
+    assert_eq!(lines.next(), Some("b7{file}"));
+    let path = lines.next().expect("no path printed");
+    assert!(!std::path::Path::new(path).exists());
+}
+
+fn stack_mapping(ptrace: bool) -> Vec<u8> {
+    let mut process = Process::new("/bin/grep");
+    process.arg("stack");
+    process.arg("/proc/self/maps");
+    process.disable_aslr(true);
+    process.with_ptrace(ptrace);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(handle.exit_code(), Some(0));
+    let mut out = Vec::new();
+    handle.read_stdout(&mut out).unwrap();
+    out
+}
+
+// the stack lands at the same address every run, also when the
+// personality hook has to run before PTRACE_TRACEME
+#[test]
+fn aslr_disabled() {
+    let first = stack_mapping(false);
+    assert_eq!(stack_mapping(false), first);
+    assert_eq!(stack_mapping(true), first);
+}
+
+#[test]
+fn cpu_limit() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("while :; do :; done");
+    process.with_limits(None, Some(1));
+    let handle = process.spawn().unwrap();
+    let err = handle.finish(Duration::new(10, 0)).unwrap_err();
+    assert_eq!(*err.runner(), Runner::ResourceLimit);
+    assert_eq!(handle.status().unwrap().signal, Some(Signal::SIGXCPU));
+}
+
+// a kill -9 from elsewhere isn't the limit just because one is set
+#[test]
+fn cpu_limit_other_kill() {
+    let mut process = Process::new("/bin/sleep");
+    process.arg("10");
+    process.with_limits(None, Some(5));
+    let handle = process.spawn().unwrap();
+    handle.signal(Signal::SIGKILL).unwrap();
+    let status = handle.finish(Duration::new(10, 0)).unwrap();
+    assert_eq!(status.signal, Some(Signal::SIGKILL));
+}
+
+// allocations past the limit fail, the child decides what happens then
+#[test]
+fn memory_limit() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("x=$(head -c 100000000 /dev/zero | tr '\\0' x); echo ${#x}");
+    process.with_limits(Some(32 << 20), None);
+    let handle = process.spawn().unwrap();
+    handle.finish(Duration::new(10, 0)).unwrap();
+    assert!(!handle.status().unwrap().success());
+}
+
+fn ulimit_v(mem_bytes: Option<u64>) -> Vec<u8> {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("ulimit -v");
+    process.with_limits(Some(16 << 20), None);
+    process.with_limits(mem_bytes, None);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    stdout
+}
+
+#[test]
+fn with_limits() {
+    assert_eq!(ulimit_v(Some(64 << 20)), b"65536\n");
+    // lifted again, so whatever b7 itself runs with
+    assert_ne!(ulimit_v(None), b"16384\n");
+}
+
+#[test]
+fn cpu_affinity() {
+    let mut process = Process::new("/bin/grep");
+    process.arg("Cpus_allowed_list");
+    process.arg("/proc/self/status");
+    process.cpu_affinity(0).unwrap();
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut out = Vec::new();
+    handle.read_stdout(&mut out).unwrap();
+    assert_eq!(
+        String::from_utf8(out).unwrap().split_whitespace().last(),
+        Some("0")
+    );
+}
+
+#[test]
+fn cpu_affinity_missing_core() {
+    let err = Process::new("/bin/true").cpu_affinity(1 << 20).unwrap_err();
+    assert_eq!(*err.runner(), Runner::MissingArgs);
+    assert!(err.to_string().contains("CPU 1048576"));
+}
+
+#[test]
+fn run_target_output() {
+    let data = InstCountData::builder("/bin/sh")
+        .input(
+            Input::builder()
+                .arg("-c")
+                .arg("cat; exit 4")
+                .stdin("hi")
+                .build(),
+        )
+        .build();
+    let (outcome, output) = run_target(&data).unwrap();
+    assert_eq!(outcome, ChildOutcome::Exited(4));
+    assert_eq!(output.stdout, b"hi");
+    assert!(output.runtime.is_some());
+}
+
+#[test]
+fn run_target_timeout() {
+    let data = InstCountData::builder("/bin/sleep")
+        .input(Input::builder().arg("10").build())
+        .timeout(Duration::from_millis(100))
+        .build();
+    let (outcome, _) = run_target(&data).unwrap();
+    assert_eq!(outcome, ChildOutcome::TimedOut);
+}
+
+#[test]
+fn read_stderr() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("echo out; echo err >&2");
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+
+    let mut stderr = Vec::new();
+    assert_eq!(handle.read_stderr(&mut stderr).unwrap(), 4);
+    assert_eq!(stderr, b"err\n");
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"out\n");
+}
+
+#[test]
+fn env_vars() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("printf '%s,%s' \"$KEY\" \"$HOME\"");
+    process.env_clear();
+    process.envs(vec![("KEY", "b7")]);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"b7,");
+}
+
+// the environment of an Input reaches targets started by solvers
+#[test]
+fn input_env() {
+    let data = InstCountData::builder("/bin/sh")
+        .input(
+            Input::builder()
+                .arg("-c")
+                .arg("printf %s \"$KEY\"")
+                .env("KEY", "dr4g0n")
+                .build(),
+        )
+        .build();
+    let (_, output) = run_target(&data).unwrap();
+    assert_eq!(output.stdout, b"dr4g0n");
+}
+
+// a prompt before every read, answered a line at a time
+#[test]
+fn answer_prompts() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("printf 'name? '; read a; printf 'pass? '; read b; echo \"$a-$b\"");
+    process.input(b"b7\nok\n".to_vec());
+    process.answer_prompts(true);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"name? pass? b7-ok\n");
+}
+
+// cat echoes 1MB back while it is written, which would deadlock
+// on full pipes if write_stdin didn't read stdout meanwhile
+#[test]
+fn write_stdin_stages() {
+    let mut process = Process::new("/bin/cat");
+    process.defer_input(true);
+    let mut handle = process.spawn().unwrap();
+    handle.write_stdin(&vec![b'A'; 1 << 20]).unwrap();
+    handle.write_stdin(b"b7").unwrap();
+    handle.close_stdin().unwrap();
+    let mut stdout = Vec::new();
+    assert_eq!(handle.read_stdout(&mut stdout).unwrap(), (1 << 20) + 2);
+    assert!(stdout.ends_with(b"AAb7"));
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+}
+
+// the same without defer_input: spawn writes the input, and has to
+// read stdout meanwhile
+#[test]
+fn large_input_echoed() {
+    let mut process = Process::new("/bin/cat");
+    process.input(vec![b'A'; 1 << 20]);
+    let mut handle = process.spawn().unwrap();
+    let mut stdout = Vec::new();
+    assert_eq!(handle.read_stdout(&mut stdout).unwrap(), 1 << 20);
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+}
+
+// output that isn't piped can't be read, and can't fill a pipe either
+#[test]
+fn output_mode() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("head -c 1048576 /dev/zero; echo b7 >&2");
+    process.stdout_mode(OutputMode::Null);
+    let path = std::env::temp_dir().join(format!("b7-stderr-{}", std::process::id()));
+    process.stderr_mode(OutputMode::File(path.clone()));
+    let mut handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+    assert!(!handle.captures_stdout());
+    let err = handle.read_stdout(&mut Vec::new()).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "IoError: stdout of the child is not piped but null"
+    );
+    assert!(handle.read_stderr(&mut Vec::new()).is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), b"b7\n");
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!("null".parse::<OutputMode>().unwrap(), OutputMode::Null);
+    assert_eq!(
+        "file:/tmp/out".parse::<OutputMode>().unwrap().to_string(),
+        "file:/tmp/out"
+    );
+    assert!("file:".parse::<OutputMode>().is_err());
+}
+
+// the wrapper execs the target, so the child stops at two execs
+#[test]
+fn wrapper() {
+    let mut process = Process::new("/bin/sh");
+    process.wrapper("/usr/bin/env", &["B7_WRAPPED=1"]);
+    process.arg("-c");
+    process.arg("echo $B7_WRAPPED");
+    process.with_ptrace(true);
+    let mut handle = process.spawn().unwrap();
+    assert_eq!(handle.wrapper_execs(), 1);
+    let mut execs = 0;
+    let status = handle
+        .finish_with(Duration::new(5, 0), |status| {
+            if let WaitStatus::Stopped(_, Signal::SIGTRAP) = *status {
+                execs += 1;
+            }
+            Ok(())
+        })
+        .unwrap();
+    assert!(status.success());
+    assert_eq!(execs, 2);
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"1\n");
+
+    let mut template = ProcessTemplate::new("/bin/sh");
+    template.arg("-c");
+    template.arg("echo $B7_WRAPPED");
+    let mut vars = std::collections::HashMap::new();
+    vars.insert(
+        String::from("wrap"),
+        String::from("/usr/bin/env  B7_WRAPPED=2"),
+    );
+    template.apply_vars(&vars).unwrap();
+    let mut handle = template.spawn_with_input(vec![]).unwrap();
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"2\n");
+
+    let mut process = Process::new("/bin/true");
+    process.wrapper("/nonexistent/b7-wrapper", &[]);
+    let err = process.spawn().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "NotFound: wrapper /nonexistent/b7-wrapper not found"
+    );
+}
+
+// once the waiter reaped the child its pid may be reused, so signalling
+// it has to fail whether or not the handle has seen the exit yet
+#[test]
+fn signal_after_exit() {
+    let mut sleeper = Process::new("/bin/sleep");
+    sleeper.arg("5");
+    let handle = sleeper.spawn().unwrap();
+    handle.signal(Signal::SIGSTOP).unwrap();
+    handle.signal(Signal::SIGKILL).unwrap();
+
+    let handle = Process::new("/bin/true").spawn().unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let err = handle.signal(Signal::SIGINT).unwrap_err();
+    assert_eq!(err.to_string(), "RunnerError: child process not running");
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+    assert!(handle.signal(Signal::SIGINT).is_err());
+}
+
+// a dialog: wait for each line of output before answering it
+#[test]
+fn read_until() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("printf 'name? '; read a; echo \"hi $a\"; echo bye");
+    process.defer_input(true);
+    let mut handle = process.spawn().unwrap();
+    let mut prompt = Vec::new();
+    assert_eq!(handle.read_until(b' ', &mut prompt).unwrap(), 6);
+    assert_eq!(prompt, b"name? ");
+    handle.write_stdin(b"b7\n").unwrap();
+    let mut line = Vec::new();
+    handle.read_until(b'\n', &mut line).unwrap();
+    assert_eq!(line, b"hi b7\n");
+    handle.close_stdin().unwrap();
+
+    // the rest stops at EOF without the delimiter
+    let mut rest = Vec::new();
+    handle.read_until(b'!', &mut rest).unwrap();
+    assert_eq!(rest, b"bye\n");
+    assert_eq!(handle.read_until(b'\n', &mut rest).unwrap(), 0);
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+}
+
+// stdin stays open between the chunks of a sequence
+#[test]
+fn input_sequence() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("read a; echo \"got $a\"; read b; echo \"$a-$b\"");
+    process.input_sequence(vec![b"b7\n".to_vec(), b"ok\n".to_vec()]);
+    process.input_delay(Duration::from_millis(300));
+    let start = Instant::now();
+    let mut handle = process.spawn().unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(300));
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"got b7\nb7-ok\n");
+}
+
+// the child sees a terminal on stdin and /dev/tty, and its output
+// comes back without the input echoed
+#[test]
+fn use_pty() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("test -t 0 && echo tty; read a </dev/tty; echo \"got $a\"");
+    process.input(&b"b7\n"[..]);
+    process.use_pty(true);
+    let mut handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"tty\ngot b7\n");
+
+    // without a newline, the input still reaches it
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("read a; echo \"got $a\"");
+    process.input(&b"ok"[..]);
+    process.use_pty(true);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"got ok\n");
+}
+
+// only loopback is left in the child's namespace, or without the
+// privilege to make one, the error says what's missing
+#[test]
+fn isolate_network() {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("grep -c : /proc/net/dev");
+    process.isolate_network(true);
+    let mut handle = match process.spawn() {
+        Ok(handle) => handle,
+        Err(e) => {
+            assert_eq!(*e.runner(), Runner::MissingArgs);
+            assert!(e.to_string().contains("CAP_SYS_ADMIN"));
+            return;
+        }
+    };
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"1\n");
+}
+
+// a crash leaves no core file behind
+#[test]
+fn disable_core_dumps() {
+    let dir = std::env::temp_dir().join(format!("b7-cores-{}", std::process::id()));
+    std::fs::create_dir(&dir).unwrap();
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("ulimit -c; kill -SEGV $$");
+    process.current_dir(&dir);
+    process.disable_core_dumps(true);
+    let mut handle = process.spawn().unwrap();
+    let status = handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    assert_eq!(stdout, b"0\n");
+    assert_eq!(status.signal, Some(Signal::SIGSEGV));
+    assert!(!status.core_dumped);
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    std::fs::remove_dir(&dir).unwrap();
+}
+
+fn ids_as_nobody(ptrace: bool) -> Vec<u8> {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("id -u; id -g; id -G");
+    process.run_as(65534, 65534);
+    process.with_ptrace(ptrace);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    stdout
+}
+
+// the child drops root, also when it is traced from exec on. Switching
+// users needs root, so there is nothing to check without it
+#[test]
+fn run_as() {
+    if !nix::unistd::geteuid().is_root() {
+        return;
+    }
+    assert_eq!(ids_as_nobody(false), b"65534\n65534\n65534\n");
+    assert_eq!(ids_as_nobody(true), b"65534\n65534\n65534\n");
+}
+
+#[test]
+fn lookup_user() {
+    assert_eq!(process::lookup_user("root").unwrap(), (0, 0));
+    assert_eq!(process::lookup_user("0").unwrap(), (0, 0));
+    assert_eq!(process::lookup_user("1000:100").unwrap(), (1000, 100));
+    assert!(process::lookup_user("no such user").is_err());
+    assert!(process::lookup_user("1000:staff").is_err());
+}
+
+fn hook_marker(ptrace: bool) -> Vec<u8> {
+    let mut process = Process::new("/bin/sh");
+    process.arg("-c");
+    process.arg("echo child");
+    process.with_ptrace(ptrace);
+    // write(2) is async-signal-safe, and stdout is already in place
+    process.pre_exec(|| {
+        let own_group = unsafe { libc::getpgrp() == libc::getpid() };
+        let marker: &[u8] = if own_group {
+            b"hook in own group\n"
+        } else {
+            b"hook before setpgid\n"
+        };
+        unsafe { libc::write(1, marker.as_ptr() as *const libc::c_void, marker.len()) };
+        Ok(())
+    });
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    assert_eq!(handle.exit_code(), Some(0));
+    let mut stdout = Vec::new();
+    handle.read_stdout(&mut stdout).unwrap();
+    stdout
+}
+
+// user hooks run after the built in ones and before PTRACE_TRACEME
+#[test]
+fn pre_exec_order() {
+    assert_eq!(hook_marker(false), b"hook in own group\nchild\n");
+    assert_eq!(hook_marker(true), b"hook in own group\nchild\n");
+}
+
+// an error from a hook fails the spawn instead of running the target
+#[test]
+fn pre_exec_error() {
+    let mut process = Process::new("/bin/true");
+    process.pre_exec(|| Err(std::io::Error::from_raw_os_error(libc::EACCES)));
+    assert!(process.spawn().is_err());
+}
+
+// one template, spawned again for each input
+#[test]
+fn spawn_with_input() {
+    let mut template = ProcessTemplate::new("sh");
+    template.arg("-c");
+    template.arg("read a; echo \"got $a\"");
+    for input in &["b7", "again"] {
+        let mut handle = template
+            .spawn_with_input(format!("{}\n", input).into_bytes())
+            .unwrap();
+        assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+        let mut stdout = Vec::new();
+        handle.read_stdout(&mut stdout).unwrap();
+        assert_eq!(stdout, format!("got {}\n", input).into_bytes());
+    }
+}
+
+// the candidate's envp goes on top of the template's variables, and
+// every instance runs the template's hooks
+#[test]
+fn template_env_and_hooks() {
+    let mut template = ProcessTemplate::new("sh");
+    template.arg("-c");
+    template.arg("echo $B7_A $B7_B; ulimit -n");
+    template.env("B7_A", "template");
+    template.envs(vec![("B7_B", "template")]);
+    template.pre_exec(|| {
+        let limit = libc::rlimit {
+            rlim_cur: 42,
+            rlim_max: 42,
+        };
+        match unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &limit) } {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    });
+    let mut inp = Input::new(vec![], vec![]);
+    inp.envp.push((b"B7_B".to_vec(), b"candidate".to_vec()));
+    for _ in 0..2 {
+        let mut handle = template.instantiate(&inp).spawn().unwrap();
+        assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+        let mut stdout = Vec::new();
+        handle.read_stdout(&mut stdout).unwrap();
+        assert_eq!(stdout, b"template candidate\n42\n");
+    }
+}
+
+// a typo in a limit must not run the target without it
+#[test]
+fn bad_vars() {
+    for (var, value) in &[
+        ("memory_limit", "64M"),
+        ("cpu_limit", "1s"),
+        ("max_output", "-1"),
+        ("stdin_offset", "0x10"),
+        ("run_as", "b7-no-such-user"),
+        ("kill_policy", "gently"),
+    ] {
+        let mut vars = std::collections::HashMap::new();
+        vars.insert(String::from("stdin_file"), String::from("/dev/null"));
+        vars.insert(var.to_string(), value.to_string());
+        let mut template = ProcessTemplate::new("/bin/true");
+        let err = template.apply_vars(&vars).unwrap_err();
+        assert_eq!(*err.runner(), Runner::MissingArgs);
+        assert!(err.to_string().contains(value), "{}", err);
+    }
+}
@@ -0,0 +1,141 @@
+use b7::repro;
+use b7::statistics::RunStats;
+use b7::B7Results;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn results(argv: Vec<Vec<u8>>, stdin: &[u8]) -> B7Results {
+    B7Results {
+        arg_brute: String::new(),
+        stdin_brute: String::from_utf8_lossy(stdin).into_owned(),
+        file_brute: String::new(),
+        argv,
+        stdin: stdin.to_vec(),
+        file: Vec::new(),
+        min_len: 0,
+        max_len: None,
+        stats: RunStats::new(),
+        cancelled: false,
+        fallback: None,
+    }
+}
+
+// write the script for `results` and run it
+fn replay(
+    name: &str,
+    target: &Path,
+    vars: &HashMap<String, String>,
+    results: &B7Results,
+) -> std::process::Output {
+    let script = std::env::temp_dir().join(format!("b7-repro-{}-{}.sh", name, std::process::id()));
+    repro::write_script(&script, target, "perf", vars, results).unwrap();
+    let output = Command::new(&script)
+        .env("B7_REPRO_REPLAY", "1")
+        .output()
+        .unwrap();
+    std::fs::remove_file(&script).unwrap();
+    output
+}
+
+#[test]
+fn replays_wyvern() {
+    let mut target = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    target.push("tests");
+    target.push("wyvern");
+
+    let output = replay(
+        "wyvern",
+        &target,
+        &HashMap::new(),
+        &results(vec![], b"dr4g0n_or_p4tric1an_it5_LLVM\n"),
+    );
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("A great success!"));
+}
+
+#[test]
+fn exact_bytes_and_status() {
+    let stdin = b"100% \\ it's\0\xff\n\n";
+    let argv = vec![b"-c".to_vec(), b"cat; exit 3".to_vec()];
+    let output = replay(
+        "sh",
+        Path::new("/bin/sh"),
+        &HashMap::new(),
+        &results(argv, stdin),
+    );
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(&output.stdout[..], &stdin[..]);
+}
+
+#[test]
+fn metadata() {
+    let script = repro::script(
+        Path::new("/bin/sh"),
+        "dynamorio",
+        &HashMap::new(),
+        &results(vec![], b""),
+    )
+    .unwrap();
+    let script = String::from_utf8(script).unwrap();
+    assert!(script.starts_with("#!/bin/sh\n"));
+    assert!(script.contains("# solver: dynamorio\n"));
+    let hash = script
+        .lines()
+        .find(|l| l.starts_with("# sha256: "))
+        .unwrap();
+    assert_eq!(hash.len(), "# sha256: ".len() + 64);
+}
+
+#[test]
+fn environment() {
+    std::env::set_var("B7_REPRO_RUN", "run's value");
+    let argv = vec![
+        b"-c".to_vec(),
+        b"echo \"$B7_REPRO_RUN:${B7_REPRO_REPLAY-unset}\"".to_vec(),
+    ];
+    let output = replay(
+        "env",
+        Path::new("/bin/sh"),
+        &HashMap::new(),
+        &results(argv, b""),
+    );
+    assert!(output.status.success());
+    assert_eq!(&output.stdout[..], b"run's value:unset\n");
+}
+
+#[test]
+fn file_input() {
+    let mut results = results(
+        vec![b"-c".to_vec(), b"cat \"$1\"".to_vec(), b"sh".to_vec()],
+        b"",
+    );
+    results.file = b"b7\0ok\n".to_vec();
+    let output = replay("file", Path::new("/bin/sh"), &HashMap::new(), &results);
+    assert!(output.status.success());
+    assert_eq!(&output.stdout[..], b"b7\0ok\n");
+}
+
+#[test]
+fn patched_stdin_file() {
+    let input = std::env::temp_dir().join(format!("b7-repro-stdin-{}", std::process::id()));
+    fs::write(&input, b"hello world\n").unwrap();
+    let mut vars = HashMap::new();
+    vars.insert(
+        "stdin_file".to_string(),
+        input.to_str().unwrap().to_string(),
+    );
+    vars.insert("stdin_offset".to_string(), "6".to_string());
+
+    let argv = vec![b"-c".to_vec(), b"cat".to_vec()];
+    let output = replay(
+        "stdin-file",
+        Path::new("/bin/sh"),
+        &vars,
+        &results(argv, b"b7"),
+    );
+    fs::remove_file(&input).unwrap();
+    assert!(output.status.success());
+    assert_eq!(&output.stdout[..], b"hello b7rld\n");
+}
@@ -0,0 +1,155 @@
+use b7::b7tui::Env;
+use b7::config::{OptsConfig, Versioned, FORMAT_VERSION};
+use b7::generators::Input;
+use b7::statistics::{PhaseStats, RunStats};
+use b7::B7Results;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+
+// serialize, read back and serialize again: both must match
+fn round_trip<T: Serialize + DeserializeOwned>(value: T) -> T {
+    let json = Versioned::to_json(value).unwrap();
+    let back: T = Versioned::from_json(&json).unwrap();
+    let again = serde_json::to_string_pretty(&Versioned {
+        version: FORMAT_VERSION,
+        data: &back,
+    })
+    .unwrap();
+    assert_eq!(json, again);
+    back
+}
+
+fn stats() -> RunStats {
+    let mut stats = RunStats::new();
+    stats.push(
+        "stdin",
+        PhaseStats {
+            runs: 95,
+            memo_hits: 3,
+            errors: 2,
+            timeouts: 1,
+            elapsed: Duration::new(5, 250),
+            uncertain: vec![1, 4],
+            rounds: vec![vec![(0x41, 100), (0x42, 120)]],
+            success: Some(Input::new(vec![b"-v".to_vec()], b"b7\xff".to_vec())),
+            ..PhaseStats::default()
+        },
+    );
+    stats
+}
+
+#[test]
+fn run_stats() {
+    let back = round_trip(stats());
+    let phase = &back.phases[0];
+    assert_eq!(phase.name, "stdin");
+    assert_eq!(phase.elapsed, Duration::new(5, 250));
+    assert_eq!(&*phase.success.as_ref().unwrap().stdin, b"b7\xff");
+}
+
+#[test]
+fn results() {
+    let results = B7Results {
+        arg_brute: String::from("[-v], "),
+        stdin_brute: String::from("b7"),
+        file_brute: String::new(),
+        argv: vec![b"-v".to_vec()],
+        stdin: b"b7".to_vec(),
+        file: Vec::new(),
+        min_len: 0,
+        max_len: Some(64),
+        stats: stats(),
+        cancelled: false,
+        fallback: None,
+    };
+    let back = round_trip(results);
+    assert_eq!(back.stdin, b"b7");
+    assert_eq!(back.stats.phases.len(), 1);
+}
+
+#[test]
+fn opts_config() {
+    let mut vars = HashMap::new();
+    vars.insert(String::from("dynpath"), String::from("/opt/dynamorio"));
+    let config = OptsConfig {
+        path: String::from("./target"),
+        solver: String::from("dynamorio"),
+        vars,
+        charset: (0, 0xff),
+        heatmap: Some(PathBuf::from("heat.png")),
+        strip_trailing_newline: true,
+        output_path: Some(PathBuf::from("solved")),
+        ..OptsConfig::default()
+    };
+    assert_eq!(round_trip(config.clone()), config);
+}
+
+#[test]
+fn opts_config_from_opts() {
+    let mut term = Env::new();
+    let config = OptsConfig {
+        path: String::from("./target"),
+        max_len: Some(32),
+        output_path: Some(PathBuf::from("solved")),
+        ..OptsConfig::default()
+    };
+    let opts = config.clone().into_opts(&mut term).unwrap();
+    assert_eq!(opts.config(), config);
+}
+
+#[test]
+fn unknown_fields_rejected() {
+    let json = r#"{"version": 1, "data": {"path": "x", "colour": "red"}}"#;
+    assert!(Versioned::<OptsConfig>::from_json(json).is_err());
+}
+
+#[test]
+fn newer_version_rejected() {
+    let json = format!(r#"{{"version": {}, "data": {{}}}}"#, FORMAT_VERSION + 1);
+    assert!(Versioned::<OptsConfig>::from_json(&json).is_err());
+}
+
+// the job file `b7 submit` takes by default
+#[test]
+fn opts_config_toml() {
+    let toml = r#"
+        version = 1
+
+        [data]
+        path = "./wyvern"
+        solver = "dynamorio"
+        max_len = 32
+
+        [data.vars]
+        dynpath = "dynamorio/build"
+    "#;
+    let config: OptsConfig = Versioned::from_toml(toml).unwrap();
+    assert_eq!(config.path, "./wyvern");
+    assert_eq!(config.solver, "dynamorio");
+    assert_eq!(config.max_len, Some(32));
+    assert_eq!(config.vars["dynpath"], "dynamorio/build");
+    assert_eq!(config.timeout, OptsConfig::default().timeout);
+
+    let newer = format!("version = {}\n[data]\n", FORMAT_VERSION + 1);
+    assert!(Versioned::<OptsConfig>::from_toml(&newer).is_err());
+}
+
+// Files written by older versions must keep loading
+#[test]
+fn v1_fixture() {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("fixtures");
+    path.push("opts_v1.json");
+    let json = std::fs::read_to_string(path).unwrap();
+
+    let config: OptsConfig = Versioned::from_json(&json).unwrap();
+    assert_eq!(config.solver, "dynamorio");
+    assert_eq!(config.max_len, Some(64));
+    assert_eq!(config.vars["dynpath"], "dynamorio/build");
+    // fields added after the fixture was written take their defaults
+    assert_eq!(config.charset, OptsConfig::default().charset);
+}
@@ -0,0 +1,29 @@
+use b7::process::Process;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// Like a C program using the ffi, and unlike Rust ones, this binary
+// doesn't ignore SIGPIPE. Input for children that exit without reading
+// it must not kill it. The only test here, since the change is global
+#[test]
+fn child_exits_before_input() {
+    unsafe { libc::signal(libc::SIGPIPE, libc::SIG_DFL) };
+
+    let mut process = Process::new("/bin/true");
+    process.input(vec![b'A'; 1 << 20]);
+    let handle = process.spawn().unwrap();
+    assert!(handle.finish(Duration::new(5, 0)).unwrap().success());
+
+    let mut process = Process::new("/bin/true");
+    process.defer_input(true);
+    let mut handle = process.spawn().unwrap();
+    handle.finish(Duration::new(5, 0)).unwrap();
+    assert!(handle.write_stdin(&[b'A'; 1 << 16]).is_err());
+}
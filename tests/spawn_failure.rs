@@ -0,0 +1,34 @@
+use b7::process::{Process, WAITER};
+use std::path::Path;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// Children that exit before their input is written, spawns that fail
+// and handles dropped early must all leave the waiter's map empty.
+// Everything is in one test, others running at once would count too
+#[test]
+fn no_entries_left() {
+    for i in 0..3000 {
+        let mut process = Process::new("/bin/false");
+        // more than a pipe holds, so writing it runs into EPIPE
+        process.input(vec![b'A'; 1 << 17]);
+        let handle = process.spawn().unwrap();
+        if i % 2 == 0 {
+            let status = handle.finish(Duration::new(5, 0)).unwrap();
+            assert_eq!(status.code, Some(1));
+        }
+    }
+
+    let mut process = Process::new("/bin/cat");
+    process.input_file(Path::new("/nonexistent/b7-input"));
+    assert!(process.spawn().is_err());
+
+    assert_eq!(WAITER.tracked(), 0);
+}
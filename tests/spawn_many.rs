@@ -0,0 +1,36 @@
+use b7::process::{Process, WAITER};
+use std::thread;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// The waiter is global, so this is the only test in its binary, or
+// children of other tests would be counted too.
+// Spawn lots of short lived children from many threads at once,
+// and make sure every one of them is reaped and forgotten
+#[test]
+fn spawn_many() {
+    let threads: Vec<_> = (0..8)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..250 {
+                    let handle = Process::new("/bin/true").spawn().unwrap();
+                    handle
+                        .finish(Duration::new(5, 0))
+                        .expect("child was not reaped");
+                }
+            })
+        })
+        .collect();
+
+    for t in threads {
+        t.join().unwrap();
+    }
+    assert_eq!(WAITER.tracked(), 0);
+}
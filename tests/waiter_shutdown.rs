@@ -0,0 +1,39 @@
+use b7::process::{shutdown_waiter, Process, WAITER};
+use std::thread;
+use std::time::Duration;
+
+use ctor::ctor;
+
+// See tests/run_wyvern.rs for why this is needed
+#[ctor]
+fn on_init() {
+    b7::process::block_signal();
+}
+
+// Shutting down waits for running children, and spawning
+// afterwards brings the waiter thread back
+#[test]
+fn shutdown_and_restart() {
+    let mut sleeper = Process::new("/bin/sleep");
+    sleeper.arg("0.2");
+    let handle = sleeper.spawn().unwrap();
+
+    let finisher = thread::spawn(move || {
+        handle
+            .finish(Duration::new(5, 0))
+            .expect("child was not reaped")
+    });
+    shutdown_waiter();
+    assert!(finisher.join().unwrap().success());
+    assert_eq!(WAITER.tracked(), 0);
+
+    let handle = Process::new("/bin/true").spawn().unwrap();
+    let status = handle
+        .finish(Duration::new(5, 0))
+        .expect("restarted waiter did not reap child");
+    assert!(status.success());
+
+    shutdown_waiter();
+    // a second shutdown with no thread running is a no-op
+    shutdown_waiter();
+}